@@ -0,0 +1,144 @@
+// Generates the OUI vendor table for src/oui.rs
+
+use std::collections::HashMap;
+use std::env;
+use std::fs;
+use std::path::Path;
+
+// IEEE registries checked into data/oui, refreshed from
+//   https://standards-oui.ieee.org/oui/oui.csv     (MA-L, 24-bit prefixes)
+//   https://standards-oui.ieee.org/oui28/mam.csv   (MA-M, 28-bit prefixes)
+//   https://standards-oui.ieee.org/oui36/oui36.csv (MA-S, 36-bit prefixes)
+const OUI_REGISTRIES: [(&str, &str); 3] = [
+    ("MA_L", "oui.csv"),
+    ("MA_M", "mam.csv"),
+    ("MA_S", "oui36.csv"),
+];
+
+// Vendor names are shortened to fit the display ("Apple, Inc." -> "Apple") and deduplicated.
+// tools/oui.py applies the same rules.
+const VENDOR_NAME_MAX_LEN: usize = 16;
+// Names are stored back to back with a length byte each and the absolute offset of every
+// 32nd name, instead of a u32 offset per name
+const VENDOR_NAME_CHECKPOINT: usize = 32;
+const NAME_SUFFIXES: &[&str] = &[
+    "inc", "incorporated", "ltd", "limited", "co", "coltd", "corp", "corporation", "corporate", "company",
+    "llc", "gmbh", "ag", "sa", "sas", "bv", "nv", "oy", "ab", "as", "asa", "kg", "pte", "pty", "plc",
+    "srl", "spa", "sro", "kk", "electronics", "electronic", "technology", "technologies", "tech",
+    "systems", "communications", "communication", "international", "group", "holdings", "industries",
+    "industrial",
+];
+
+fn main() {
+    println!("cargo:rerun-if-changed=build.rs");
+    generate_oui_table(Path::new("data/oui"));
+}
+
+fn short_vendor_name(name: &str) -> String {
+    let name = name.split(',').next().unwrap_or(name);
+    let mut words: Vec<&str> = name.split_whitespace().collect();
+    while words.len() > 1 {
+        let last = words[words.len() - 1]
+            .chars()
+            .filter(|c| c.is_alphanumeric())
+            .collect::<String>()
+            .to_lowercase();
+        if !NAME_SUFFIXES.contains(&last.as_str()) {
+            break;
+        }
+        words.pop();
+    }
+    let short: String = words.join(" ").chars().take(VENDOR_NAME_MAX_LEN).collect();
+    short.trim_end_matches([' ', '.', '-', '&']).to_string()
+}
+
+// The first three fields of a registry line: registry, assignment, organization name
+fn csv_fields(line: &str) -> Vec<String> {
+    let mut fields = Vec::new();
+    let mut field = String::new();
+    let mut quoted = false;
+    let mut chars = line.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' if quoted && chars.peek() == Some(&'"') => {
+                field.push('"');
+                chars.next();
+            },
+            '"' => quoted = !quoted,
+            ',' if !quoted => {
+                fields.push(std::mem::take(&mut field));
+                if fields.len() == 3 {
+                    return fields;
+                }
+            },
+            _ => field.push(c),
+        }
+    }
+    fields.push(field);
+    fields
+}
+
+// Writes oui_table.rs to OUT_DIR from the registries in `data_dir`
+fn generate_oui_table(data_dir: &Path) {
+    let mut names: Vec<String> = Vec::new();
+    let mut name_ids: HashMap<String, u16> = HashMap::new();
+    let mut tables = String::new();
+
+    for (table, file) in OUI_REGISTRIES {
+        let path = data_dir.join(file);
+        println!("cargo:rerun-if-changed={}", path.display());
+        let registry = fs::read_to_string(&path).unwrap_or_else(|e| panic!("Failed to read {}: {}", path.display(), e));
+
+        let mut entries: Vec<(u64, u16)> = Vec::new();
+        for line in registry.lines().skip(1) {
+            let fields = csv_fields(line.trim_end());
+            if fields.len() < 3 {
+                continue;
+            }
+            let Ok(prefix) = u64::from_str_radix(fields[1].trim(), 16) else {
+                continue;
+            };
+            let name = short_vendor_name(&fields[2]);
+            let id = *name_ids.entry(name.clone()).or_insert_with(|| {
+                names.push(name);
+                u16::try_from(names.len() - 1).expect("Too many OUI vendor names for a u16 index")
+            });
+            entries.push((prefix, id));
+        }
+        entries.sort_unstable();
+        entries.dedup_by_key(|entry| entry.0);
+
+        let prefix_type = if table == "MA_S" { "u64" } else { "u32" };
+        let prefixes: Vec<String> = entries.iter().map(|(prefix, _)| prefix.to_string()).collect();
+        let ids: Vec<String> = entries.iter().map(|(_, id)| id.to_string()).collect();
+        tables.push_str(&format!(
+            "pub static {table}_PREFIXES: [{prefix_type}; {len}] = [{prefixes}];\npub static {table}_NAMES: [u16; {len}] = [{ids}];\n",
+            len = entries.len(),
+            prefixes = prefixes.join(","),
+            ids = ids.join(","),
+        ));
+    }
+
+    let mut all_names = String::new();
+    let mut lens = Vec::with_capacity(names.len());
+    let mut checkpoints = Vec::new();
+    for (idx, name) in names.iter().enumerate() {
+        if idx % VENDOR_NAME_CHECKPOINT == 0 {
+            checkpoints.push(all_names.len().to_string());
+        }
+        all_names.push_str(name);
+        lens.push(name.len().to_string());
+    }
+    tables.push_str(&format!(
+        "pub const VENDOR_NAME_CHECKPOINT: usize = {};\npub static VENDOR_NAMES: &str = {:?};\npub static VENDOR_NAME_LENS: [u8; {}] = [{}];\npub static VENDOR_NAME_CHECKPOINTS: [u32; {}] = [{}];\n",
+        VENDOR_NAME_CHECKPOINT,
+        all_names,
+        lens.len(),
+        lens.join(","),
+        checkpoints.len(),
+        checkpoints.join(","),
+    ));
+
+    let out_dir = env::var("OUT_DIR").expect("OUT_DIR not set");
+    fs::write(Path::new(&out_dir).join("oui_table.rs"), tables).expect("Failed to write OUI table");
+}
@@ -0,0 +1,495 @@
+// Access point inventory built from beacons and probe responses
+//
+// Bodies are parsed in the scan loop from the copied frame, so elements past MAX_FRAME_LEN
+// (usually the HE capabilities of large beacons) can be missing.
+
+use alloc::{collections::BTreeMap, string::{String, ToString}, vec::Vec};
+
+use crate::frame::{FrameType, MacAddress};
+use crate::ie::{self, EXT_HE_CAPABILITIES, IE_COUNTRY, IE_DS_PARAMETER_SET, IE_HT_CAPABILITIES, IE_RSN, IE_SSID,
+    IE_VENDOR_SPECIFIC, IE_VHT_CAPABILITIES, SUBTYPE_BEACON, SUBTYPE_PROBE_RESPONSE};
+use crate::observation::Observation;
+
+const SSID_MAX_LEN: usize = 32;
+const MANUFACTURER_LEN: usize = 16;
+// Serialized size of one record, see `AccessPoint::write_record`
+pub const RECORD_LEN: usize = 6 + 4 * 4 + 1 + 1 + 2 + 2 + 1 + 1 + 2 + 1 + SSID_MAX_LEN + MANUFACTURER_LEN;
+
+// Timestamp u64, beacon interval u16 and capability info u16 precede the elements
+const FIXED_FIELDS_LEN: usize = 12;
+const CAPABILITY_PRIVACY: u16 = 0x0010;
+
+const OUI_IEEE: [u8; 3] = [0x00, 0x0F, 0xAC];
+const OUI_MICROSOFT: [u8; 3] = [0x00, 0x50, 0xF2];
+const MICROSOFT_TYPE_WPA: u8 = 1;
+const MICROSOFT_TYPE_WPS: u8 = 4;
+const WPS_ATTR_MANUFACTURER: u16 = 0x1021;
+
+// Saved record flag bits
+const FLAG_HIDDEN: u8 = 1 << 0;
+const FLAG_HT: u8 = 1 << 1;
+const FLAG_VHT: u8 = 1 << 2;
+const FLAG_HE: u8 = 1 << 3;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Security {
+    Open,
+    Wep,
+    Wpa,
+    Wpa2,
+    Wpa3,
+    // WPA3 transition mode: PSK and SAE both offered
+    Wpa2Wpa3,
+    // Opportunistic wireless encryption (enhanced open)
+    Owe,
+}
+
+impl Security {
+    pub fn id(&self) -> u8 {
+        match self {
+            Security::Open => 0,
+            Security::Wep => 1,
+            Security::Wpa => 2,
+            Security::Wpa2 => 3,
+            Security::Wpa3 => 4,
+            Security::Wpa2Wpa3 => 5,
+            Security::Owe => 6,
+        }
+    }
+
+    pub fn from_id(id: u8) -> Option<Security> {
+        match id {
+            0 => Some(Security::Open),
+            1 => Some(Security::Wep),
+            2 => Some(Security::Wpa),
+            3 => Some(Security::Wpa2),
+            4 => Some(Security::Wpa3),
+            5 => Some(Security::Wpa2Wpa3),
+            6 => Some(Security::Owe),
+            _ => None,
+        }
+    }
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            Security::Open => "Open",
+            Security::Wep => "WEP",
+            Security::Wpa => "WPA",
+            Security::Wpa2 => "WPA2",
+            Security::Wpa3 => "WPA3",
+            Security::Wpa2Wpa3 => "W2/3",
+            Security::Owe => "OWE",
+        }
+    }
+}
+
+// What a single beacon or probe response says about its BSS
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BeaconInfo {
+    pub ssid: Vec<u8>,
+    // SSID element missing, empty or all zeros
+    pub hidden: bool,
+    // From the DS parameter set, which beats the receive channel for adjacent channel leakage
+    pub channel: Option<u8>,
+    pub beacon_interval: u16,
+    pub capability: u16,
+    pub security: Security,
+    pub ht: bool,
+    pub vht: bool,
+    pub he: bool,
+    pub country: Option<[u8; 2]>,
+    // Manufacturer advertised in the WPS element
+    pub manufacturer: Option<String>,
+}
+
+// Security from the AKM suites of an RSN element
+fn rsn_security(data: &[u8]) -> Security {
+    // version u16, group cipher suite, pairwise suite count + list, then the AKM suites
+    let pairwise_offset = 2 + 4;
+    let Some(pairwise_count) = data.get(pairwise_offset..pairwise_offset + 2) else {
+        return Security::Wpa2;
+    };
+    let akm_offset = pairwise_offset + 2 + 4 * u16::from_le_bytes([pairwise_count[0], pairwise_count[1]]) as usize;
+    let Some(akm_count) = data.get(akm_offset..akm_offset + 2) else {
+        return Security::Wpa2;
+    };
+    let akm_count = u16::from_le_bytes([akm_count[0], akm_count[1]]) as usize;
+
+    let (mut wpa2, mut wpa3, mut owe) = (false, false, false);
+    for suite in data[akm_offset + 2..].chunks_exact(4).take(akm_count) {
+        if suite[..3] != OUI_IEEE {
+            continue;
+        }
+        match suite[3] {
+            1..=6 => wpa2 = true,
+            8 | 9 | 11 | 12 | 24 | 25 => wpa3 = true,
+            18 => owe = true,
+            _ => {},
+        }
+    }
+
+    match (wpa2, wpa3, owe) {
+        (true, true, _) => Security::Wpa2Wpa3,
+        (false, true, _) => Security::Wpa3,
+        (false, false, true) => Security::Owe,
+        _ => Security::Wpa2,
+    }
+}
+
+// WPS attributes are big endian (type u16, length u16, value)
+fn wps_manufacturer(mut data: &[u8]) -> Option<String> {
+    while data.len() >= 4 {
+        let attr = u16::from_be_bytes([data[0], data[1]]);
+        let len = u16::from_be_bytes([data[2], data[3]]) as usize;
+        let value = data.get(4..4 + len)?;
+        if attr == WPS_ATTR_MANUFACTURER {
+            let name = String::from_utf8_lossy(value).trim().to_string();
+            return if name.is_empty() { None } else { Some(name) };
+        }
+        data = &data[4 + len..];
+    }
+    None
+}
+
+pub fn parse_beacon_body(body: &[u8]) -> Option<BeaconInfo> {
+    if body.len() < FIXED_FIELDS_LEN {
+        return None;
+    }
+    let beacon_interval = u16::from_le_bytes([body[8], body[9]]);
+    let capability = u16::from_le_bytes([body[10], body[11]]);
+
+    let mut info = BeaconInfo {
+        ssid: Vec::new(),
+        hidden: true,
+        channel: None,
+        beacon_interval,
+        capability,
+        security: if capability & CAPABILITY_PRIVACY != 0 { Security::Wep } else { Security::Open },
+        ht: false,
+        vht: false,
+        he: false,
+        country: None,
+        manufacturer: None,
+    };
+    let mut rsn = None;
+    let mut wpa = false;
+
+    for element in ie::elements(&body[FIXED_FIELDS_LEN..]) {
+        match element.id {
+            IE_SSID => {
+                info.hidden = element.data.iter().all(|b| *b == 0);
+                if !info.hidden {
+                    info.ssid = element.data[..element.data.len().min(SSID_MAX_LEN)].to_vec();
+                }
+            },
+            IE_DS_PARAMETER_SET => info.channel = element.data.first().copied(),
+            IE_COUNTRY if element.data.len() >= 2 => info.country = Some([element.data[0], element.data[1]]),
+            IE_HT_CAPABILITIES => info.ht = true,
+            IE_VHT_CAPABILITIES => info.vht = true,
+            IE_RSN => rsn = Some(rsn_security(element.data)),
+            IE_VENDOR_SPECIFIC => match element.vendor_oui() {
+                Some((OUI_MICROSOFT, MICROSOFT_TYPE_WPA)) => wpa = true,
+                Some((OUI_MICROSOFT, MICROSOFT_TYPE_WPS)) => info.manufacturer = wps_manufacturer(&element.data[4..]),
+                _ => {},
+            },
+            _ => {
+                if element.extension_id() == Some(EXT_HE_CAPABILITIES) {
+                    info.he = true;
+                }
+            },
+        }
+    }
+
+    if let Some(security) = rsn {
+        info.security = security;
+    } else if wpa {
+        info.security = Security::Wpa;
+    }
+    Some(info)
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AccessPoint {
+    pub info: BeaconInfo,
+    // Milliseconds since the start of the scan
+    pub first_seen_ms: u32,
+    pub last_seen_ms: u32,
+    pub beacons: u32,
+    pub probe_responses: u32,
+    pub rssi_max: i8,
+    // Channel the frames were received on
+    pub rx_channel: u8,
+}
+
+impl AccessPoint {
+    pub fn channel(&self) -> u8 {
+        self.info.channel.unwrap_or(self.rx_channel)
+    }
+
+    // SSID for display; hidden networks whose name was revealed by a probe response show it
+    pub fn ssid(&self) -> String {
+        if self.info.ssid.is_empty() {
+            "<hidden>".to_string()
+        } else {
+            String::from_utf8_lossy(&self.info.ssid).to_string()
+        }
+    }
+
+    fn update(&mut self, info: BeaconInfo, observation: &Observation, now_ms: u32) {
+        // Probe responses of hidden networks carry the real SSID; keep it once learned
+        let revealed = core::mem::take(&mut self.info.ssid);
+        let hidden = self.info.hidden || info.hidden;
+        self.info = info;
+        self.info.hidden = hidden;
+        if self.info.ssid.is_empty() {
+            self.info.ssid = revealed;
+        }
+
+        self.last_seen_ms = now_ms;
+        self.rssi_max = self.rssi_max.max(observation.meta.rssi);
+        self.rx_channel = observation.meta.channel;
+    }
+
+    // Layout: bssid, first_seen_ms, last_seen_ms, beacons, probe_responses, rssi_max, channel,
+    // beacon_interval, capability, security, flags, country [2], ssid_len, ssid [32],
+    // manufacturer [16] (NUL padded)
+    pub fn write_record(&self, bssid: &MacAddress, out: &mut Vec<u8>) {
+        out.extend_from_slice(bssid);
+        out.extend_from_slice(&self.first_seen_ms.to_le_bytes());
+        out.extend_from_slice(&self.last_seen_ms.to_le_bytes());
+        out.extend_from_slice(&self.beacons.to_le_bytes());
+        out.extend_from_slice(&self.probe_responses.to_le_bytes());
+        out.push(self.rssi_max as u8);
+        out.push(self.channel());
+        out.extend_from_slice(&self.info.beacon_interval.to_le_bytes());
+        out.extend_from_slice(&self.info.capability.to_le_bytes());
+        out.push(self.info.security.id());
+
+        let mut flags = 0;
+        if self.info.hidden {
+            flags |= FLAG_HIDDEN;
+        }
+        if self.info.ht {
+            flags |= FLAG_HT;
+        }
+        if self.info.vht {
+            flags |= FLAG_VHT;
+        }
+        if self.info.he {
+            flags |= FLAG_HE;
+        }
+        out.push(flags);
+        out.extend_from_slice(&self.info.country.unwrap_or([0, 0]));
+
+        let mut ssid = [0u8; SSID_MAX_LEN];
+        ssid[..self.info.ssid.len()].copy_from_slice(&self.info.ssid);
+        out.push(self.info.ssid.len() as u8);
+        out.extend_from_slice(&ssid);
+
+        let mut manufacturer = [0u8; MANUFACTURER_LEN];
+        if let Some(name) = &self.info.manufacturer {
+            let bytes = name.as_bytes();
+            let len = bytes.len().min(MANUFACTURER_LEN);
+            manufacturer[..len].copy_from_slice(&bytes[..len]);
+        }
+        out.extend_from_slice(&manufacturer);
+    }
+
+    // Inverse of `write_record`; the saved channel stands in for both the advertised and the
+    // receive channel
+    pub fn read_record(record: &[u8]) -> Option<(MacAddress, AccessPoint)> {
+        if record.len() < RECORD_LEN {
+            return None;
+        }
+        let u16_at = |offset: usize| u16::from_le_bytes([record[offset], record[offset + 1]]);
+        let u32_at = |offset: usize| u32::from_le_bytes([record[offset], record[offset + 1], record[offset + 2], record[offset + 3]]);
+        let mut bssid = [0u8; 6];
+        bssid.copy_from_slice(&record[..6]);
+        let channel = record[23];
+        let flags = record[29];
+        let country = [record[30], record[31]];
+        let ssid_len = (record[32] as usize).min(SSID_MAX_LEN);
+        let manufacturer = &record[33 + SSID_MAX_LEN..33 + SSID_MAX_LEN + MANUFACTURER_LEN];
+        let manufacturer = String::from_utf8_lossy(manufacturer).trim_end_matches('\0').to_string();
+        let info = BeaconInfo {
+            ssid: record[33..33 + ssid_len].to_vec(),
+            hidden: flags & FLAG_HIDDEN != 0,
+            channel: Some(channel),
+            beacon_interval: u16_at(24),
+            capability: u16_at(26),
+            security: Security::from_id(record[28])?,
+            ht: flags & FLAG_HT != 0,
+            vht: flags & FLAG_VHT != 0,
+            he: flags & FLAG_HE != 0,
+            country: (country != [0, 0]).then_some(country),
+            manufacturer: (!manufacturer.is_empty()).then_some(manufacturer),
+        };
+        let access_point = AccessPoint {
+            info,
+            first_seen_ms: u32_at(6),
+            last_seen_ms: u32_at(10),
+            beacons: u32_at(14),
+            probe_responses: u32_at(18),
+            rssi_max: record[22] as i8,
+            rx_channel: channel,
+        };
+        Some((bssid, access_point))
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct ApInventory {
+    access_points: BTreeMap<MacAddress, AccessPoint>,
+}
+
+impl ApInventory {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.access_points.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.access_points.is_empty()
+    }
+
+    pub fn get(&self, bssid: &MacAddress) -> Option<&AccessPoint> {
+        self.access_points.get(bssid)
+    }
+
+    // Beacons and probe responses update the inventory, everything else is ignored
+    pub fn observe(&mut self, observation: &Observation, now_ms: u32) {
+        let header = &observation.header;
+        if header.frame_type() != FrameType::Management {
+            return;
+        }
+        let is_beacon = header.subtype() == SUBTYPE_BEACON;
+        if !is_beacon && header.subtype() != SUBTYPE_PROBE_RESPONSE {
+            return;
+        }
+        let (Some(bssid), Some(info)) = (header.bssid(), parse_beacon_body(observation.body())) else {
+            return;
+        };
+
+        let access_point = self.access_points.entry(bssid).or_insert_with(|| AccessPoint {
+            info: info.clone(),
+            first_seen_ms: now_ms,
+            last_seen_ms: now_ms,
+            beacons: 0,
+            probe_responses: 0,
+            rssi_max: observation.meta.rssi,
+            rx_channel: observation.meta.channel,
+        });
+        access_point.update(info, observation, now_ms);
+        if is_beacon {
+            access_point.beacons = access_point.beacons.saturating_add(1);
+        } else {
+            access_point.probe_responses = access_point.probe_responses.saturating_add(1);
+        }
+    }
+
+    // Strongest first, for the on-device list
+    pub fn by_signal(&self) -> Vec<(&MacAddress, &AccessPoint)> {
+        let mut access_points: Vec<_> = self.access_points.iter().collect();
+        access_points.sort_by(|a, b| b.1.rssi_max.cmp(&a.1.rssi_max).then(a.0.cmp(b.0)));
+        access_points
+    }
+
+    pub fn serialize_records(&self) -> Vec<u8> {
+        let mut out = Vec::with_capacity(self.access_points.len() * RECORD_LEN);
+        for (bssid, access_point) in &self.access_points {
+            access_point.write_record(bssid, &mut out);
+        }
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::observation::tests::observation;
+    use alloc::vec;
+
+    fn beacon(bssid: MacAddress, ssid: &[u8], extra: &[u8]) -> Vec<u8> {
+        let mut frame = vec![0x80, 0, 0, 0];
+        frame.extend([0xff; 6]);
+        frame.extend(bssid);
+        frame.extend(bssid);
+        frame.extend([0x10, 0]);
+        // Timestamp, beacon interval, capability
+        frame.extend([0; 8]);
+        frame.extend(100u16.to_le_bytes());
+        frame.extend(0x0411u16.to_le_bytes());
+        frame.extend([0, ssid.len() as u8]);
+        frame.extend(ssid);
+        frame.extend([3, 1, 6]);
+        frame.extend([7, 3, b'D', b'E', 0x20]);
+        frame.extend([45, 2, 0, 0]);
+        frame.extend(extra);
+        frame
+    }
+
+    #[test]
+    fn parses_beacons() {
+        // RSN with PSK and SAE, WPS with a manufacturer, HE capabilities
+        let mut extra = vec![48, 22, 1, 0, 0, 0x0f, 0xac, 4, 1, 0, 0, 0x0f, 0xac, 4, 2, 0, 0, 0x0f, 0xac, 2, 0, 0x0f, 0xac, 8];
+        extra.extend([221, 17, 0, 0x50, 0xf2, 4, 0x10, 0x4a, 0, 1, 0x10, 0x10, 0x21, 0, 4, b'A', b'C', b'M', b'E']);
+        extra.extend([255, 2, 35, 0]);
+        let frame = beacon([0, 0x11, 0x22, 0x33, 0x44, 0x55], b"HomeNet", &extra);
+        let info = parse_beacon_body(observation(&frame, -40).body()).unwrap();
+        assert_eq!(info.ssid, b"HomeNet");
+        assert_eq!(info.security, Security::Wpa2Wpa3);
+        assert_eq!(info.manufacturer.as_deref(), Some("ACME"));
+        assert!(info.ht && info.he && !info.vht && !info.hidden);
+        assert_eq!(info.country, Some(*b"DE"));
+        assert_eq!(info.channel, Some(6));
+    }
+
+    #[test]
+    fn probe_response_names_hidden_network() {
+        let mut inventory = ApInventory::new();
+        let hidden = beacon([2, 0, 0, 0, 0, 1], &[0, 0, 0], &[]);
+        inventory.observe(&observation(&hidden, -60), 20);
+        let mut response = beacon([2, 0, 0, 0, 0, 1], b"Secret", &[]);
+        response[0] = 0x50;
+        inventory.observe(&observation(&response, -70), 30);
+        inventory.observe(&observation(&hidden, -65), 40);
+
+        assert_eq!(inventory.len(), 1);
+        let (_, ap) = inventory.by_signal()[0];
+        assert_eq!(ap.ssid(), "Secret");
+        assert!(ap.info.hidden);
+        assert_eq!((ap.beacons, ap.probe_responses, ap.rssi_max), (2, 1, -60));
+    }
+
+    #[test]
+    fn records_round_trip() {
+        let ap = AccessPoint {
+            info: BeaconInfo {
+                ssid: b"net".to_vec(),
+                hidden: false,
+                channel: Some(6),
+                beacon_interval: 100,
+                capability: 0x411,
+                security: Security::Wpa2Wpa3,
+                ht: true,
+                vht: false,
+                he: true,
+                country: Some(*b"US"),
+                manufacturer: Some("ACME".to_string()),
+            },
+            first_seen_ms: 1,
+            last_seen_ms: 99,
+            beacons: 7,
+            probe_responses: 2,
+            rssi_max: -41,
+            rx_channel: 6,
+        };
+        let mut record = Vec::new();
+        ap.write_record(&[1, 2, 3, 4, 5, 6], &mut record);
+        assert_eq!(record.len(), RECORD_LEN);
+        assert_eq!(AccessPoint::read_record(&record), Some(([1, 2, 3, 4, 5, 6], ap)));
+    }
+}
@@ -0,0 +1,174 @@
+// The menu the device starts in, and the access point list shown after a scan
+// The serial console and the settings store belong to the firmware; the menu reaches them
+// through `MenuHost`.
+
+use alloc::{format, string::String, vec, vec::Vec};
+
+use anyhow::Result;
+use log::info;
+
+use crate::ap::ApInventory;
+use crate::gesture::ButtonEvent;
+use crate::hal::{Board, Clock, InputDevice, Screen};
+use crate::menu::{Editor, Menu, MenuEvent, MenuItem, MenuOutcome};
+use crate::settings::{Settings, MIN_RSSI_RANGE_DBM};
+use crate::ui::{self, LIST_ROWS};
+
+// How often the button is polled while nothing happens
+const POLL_INTERVAL_MS: u32 = 100;
+// An edit left alone this long is cancelled
+const EDIT_TIMEOUT_MS: u64 = 10_000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MenuAction {
+    Scan,
+    Capture,
+    Dump,
+    Size,
+    Exit,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CommandOutcome {
+    // Nothing arrived
+    Idle,
+    // Commands ran; they may have drawn on the screen or changed settings
+    Ran,
+    // A command started a mode, the menu is left with it
+    Action(MenuAction),
+}
+
+// What the menu needs from the rest of the firmware
+pub trait MenuHost<S> {
+    // Runs the commands that arrived since the last pass, e.g. from the serial console
+    fn run_commands(&mut self, screen: &mut S, settings: &mut Settings) -> Result<CommandOutcome>;
+    // Persists a setting confirmed in the menu
+    fn save_settings(&mut self, settings: &Settings) -> Result<()>;
+}
+
+fn value(label: &'static str, key: &'static str) -> MenuItem<MenuAction> {
+    MenuItem::Value { label, key, editor: Editor::Choice }
+}
+
+fn submenu(label: &'static str, mut items: Vec<MenuItem<MenuAction>>) -> MenuItem<MenuAction> {
+    items.push(MenuItem::Back);
+    MenuItem::Submenu { label, items }
+}
+
+pub fn main_menu() -> Menu<MenuAction> {
+    let min_rssi = Editor::Number { min: MIN_RSSI_RANGE_DBM.0 as i32, max: MIN_RSSI_RANGE_DBM.1 as i32, step: 5 };
+    Menu::new("mac_sniff", vec![
+        MenuItem::Action { label: "Scan", action: MenuAction::Scan },
+        MenuItem::Action { label: "Capture", action: MenuAction::Capture },
+        submenu("Settings", vec![
+            submenu("Scan", vec![
+                value("Profile", "scan_profile"),
+                value("Time", "scan_duration"),
+                value("Save", "checkpoint"),
+                MenuItem::Value { label: "Min RSSI", key: "min_rssi", editor: min_rssi },
+                value("SSIDs", "probed_ssids"),
+                value("Count", "count"),
+                MenuItem::Value { label: "AP list", key: "ap_list", editor: Editor::Toggle },
+            ]),
+            submenu("Radio", vec![
+                value("Region", "domain"),
+                value("Dwell ms", "dwell_ms"),
+            ]),
+            submenu("Capture", vec![
+                value("Format", "cap_format"),
+                value("Snaplen", "cap_snaplen"),
+                MenuItem::Value { label: "Headers", key: "cap_headers", editor: Editor::Toggle },
+                value("Time", "cap_time"),
+            ]),
+            submenu("Schedule", vec![
+                value("Interval", "sched_interval"),
+                value("Cycles", "sched_cycles"),
+                value("Profile", "sched_profile"),
+            ]),
+            submenu("Display", vec![
+                value("Level", "brightness"),
+            ]),
+            value("Dump", "dump_format"),
+        ]),
+        submenu("Files", vec![
+            MenuItem::Action { label: "Dump", action: MenuAction::Dump },
+            MenuItem::Action { label: "Size", action: MenuAction::Size },
+        ]),
+        MenuItem::Action { label: "Exit", action: MenuAction::Exit },
+    ])
+}
+
+// Runs the menu until an action is picked. Confirmed edits, from the menu or the host's
+// commands, are saved right away; an edit nobody confirms is undone after a while.
+pub fn run_menu<S: Screen>(
+    board: &mut Board<S, impl InputDevice, impl Clock>,
+    settings: &mut Settings,
+    host: &mut impl MenuHost<S>,
+) -> Result<MenuAction> {
+    let mut menu = main_menu();
+    ui::draw_menu(&mut board.screen, &menu.view(settings))?;
+    let mut last_press_ms = board.clock.now_ms();
+    loop {
+        match host.run_commands(&mut board.screen, settings)? {
+            CommandOutcome::Action(action) => {
+                board.screen.clear()?;
+                return Ok(action);
+            },
+            CommandOutcome::Ran => ui::draw_menu(&mut board.screen, &menu.view(settings))?,
+            CommandOutcome::Idle => {},
+        }
+
+        let event = match board.input.poll() {
+            ButtonEvent::ShortPress => MenuEvent::Next,
+            ButtonEvent::LongPress => MenuEvent::Select,
+            ButtonEvent::None if menu.editing() && board.clock.now_ms() - last_press_ms >= EDIT_TIMEOUT_MS => {
+                info!("Edit timed out, value restored");
+                MenuEvent::Cancel
+            },
+            ButtonEvent::None => {
+                board.clock.delay_ms(POLL_INTERVAL_MS);
+                continue;
+            },
+        };
+        last_press_ms = board.clock.now_ms();
+        match menu.handle(event, settings) {
+            MenuOutcome::Action(action) => {
+                info!("Menu action {:?}", action);
+                board.screen.clear()?;
+                return Ok(action);
+            },
+            MenuOutcome::Changed(key) => {
+                info!("Setting {} changed to {}", key, settings.get(key).unwrap_or_default());
+                host.save_settings(settings)?;
+                board.screen.set_brightness(settings.brightness)?;
+            },
+            MenuOutcome::Redraw | MenuOutcome::None => {},
+        }
+        ui::draw_menu(&mut board.screen, &menu.view(settings))?;
+    }
+}
+
+// Strongest access points first; short press scrolls one row, long press leaves the list
+pub fn run_ap_list(board: &mut Board<impl Screen, impl InputDevice, impl Clock>, inventory: &ApInventory) -> Result<()> {
+    let rows: Vec<String> = inventory.by_signal().iter()
+        .map(|(_, access_point)| {
+            let ssid: String = access_point.ssid().chars().take(11).collect();
+            format!("{:<11} {:>2} {}", ssid, access_point.channel(), access_point.info.security.label())
+        })
+        .collect();
+    let mut first = 0;
+
+    loop {
+        let last = (first + LIST_ROWS).min(rows.len());
+        ui::draw_list(&mut board.screen, &format!("APs {}-{} of {}", first + 1, last, rows.len()), &rows, first)?;
+        loop {
+            match board.input.poll() {
+                ButtonEvent::LongPress => return Ok(()),
+                ButtonEvent::ShortPress => break,
+                ButtonEvent::None => {},
+            }
+            board.clock.delay_ms(POLL_INTERVAL_MS);
+        }
+        first = if first + LIST_ROWS < rows.len() { first + 1 } else { 0 };
+    }
+}
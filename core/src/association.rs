@@ -0,0 +1,190 @@
+// Station to BSSID associations inferred from data frames
+//
+// Only infrastructure traffic is used: ToDS frames go from a station (Address2) to its AP
+// (Address1 = BSSID) and FromDS frames come from the AP (Address2 = BSSID) to a station
+// (Address1). IBSS (neither bit) and WDS/mesh (both bits) frames say nothing about who is
+// associated with whom.
+
+use alloc::{collections::BTreeMap, vec::Vec};
+
+use crate::frame::{FrameType, MacAddress};
+use crate::mac_addr::{self, AddressKind};
+use crate::observation::Observation;
+
+// Serialized size of one record, see `Association::write_record`
+pub const RECORD_LEN: usize = 6 + 6 + 4 * 4 + 1;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Association {
+    // Milliseconds since the start of the scan
+    pub first_seen_ms: u32,
+    pub last_seen_ms: u32,
+    // Frames sent by the station to the AP
+    pub uplink_frames: u32,
+    // Frames sent by the AP to the station
+    pub downlink_frames: u32,
+    // Strongest uplink frame; None until the station itself was heard
+    pub station_rssi_max: Option<i8>,
+}
+
+impl Association {
+    // Layout: station, bssid, first_seen_ms, last_seen_ms, uplink_frames, downlink_frames,
+    // station_rssi_max (0 when the station was never heard transmitting)
+    pub fn write_record(&self, station: &MacAddress, bssid: &MacAddress, out: &mut Vec<u8>) {
+        out.extend_from_slice(station);
+        out.extend_from_slice(bssid);
+        out.extend_from_slice(&self.first_seen_ms.to_le_bytes());
+        out.extend_from_slice(&self.last_seen_ms.to_le_bytes());
+        out.extend_from_slice(&self.uplink_frames.to_le_bytes());
+        out.extend_from_slice(&self.downlink_frames.to_le_bytes());
+        out.push(self.station_rssi_max.unwrap_or(0) as u8);
+    }
+
+    // Inverse of `write_record`: (station, bssid, association)
+    pub fn read_record(record: &[u8]) -> Option<(MacAddress, MacAddress, Association)> {
+        if record.len() < RECORD_LEN {
+            return None;
+        }
+        let u32_at = |offset: usize| u32::from_le_bytes([record[offset], record[offset + 1], record[offset + 2], record[offset + 3]]);
+        let mut station = [0u8; 6];
+        station.copy_from_slice(&record[..6]);
+        let mut bssid = [0u8; 6];
+        bssid.copy_from_slice(&record[6..12]);
+        let uplink_frames = u32_at(20);
+        let association = Association {
+            first_seen_ms: u32_at(12),
+            last_seen_ms: u32_at(16),
+            uplink_frames,
+            downlink_frames: u32_at(24),
+            // Only uplink frames carry the station's signal
+            station_rssi_max: (uplink_frames > 0).then_some(record[28] as i8),
+        };
+        Some((station, bssid, association))
+    }
+}
+
+// (station, bssid) of an infrastructure data frame and whether it was sent by the station
+fn station_and_bssid(observation: &Observation) -> Option<(MacAddress, MacAddress, bool)> {
+    let header = &observation.header;
+    if header.frame_type() != FrameType::Data {
+        return None;
+    }
+    let frame_control = header.frame_control;
+    let (station, bssid, uplink) = match (frame_control.to_ds(), frame_control.from_ds()) {
+        (true, false) => (header.addr2?, header.addr1, true),
+        (false, true) => (header.addr1, header.addr2?, false),
+        _ => return None,
+    };
+    // Group addressed downlink frames have no single station behind them
+    let unicast = |mac: &MacAddress| mac_addr::classify(mac).kind == AddressKind::Unicast;
+    if !unicast(&station) || !unicast(&bssid) {
+        return None;
+    }
+    Some((station, bssid, uplink))
+}
+
+#[derive(Debug, Default)]
+pub struct AssociationTable {
+    associations: BTreeMap<(MacAddress, MacAddress), Association>,
+}
+
+impl AssociationTable {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.associations.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.associations.is_empty()
+    }
+
+    pub fn observe(&mut self, observation: &Observation, now_ms: u32) {
+        let Some((station, bssid, uplink)) = station_and_bssid(observation) else {
+            return;
+        };
+        let association = self.associations.entry((station, bssid)).or_insert_with(|| Association {
+            first_seen_ms: now_ms,
+            last_seen_ms: now_ms,
+            uplink_frames: 0,
+            downlink_frames: 0,
+            station_rssi_max: None,
+        });
+        association.last_seen_ms = now_ms;
+        if uplink {
+            association.uplink_frames = association.uplink_frames.saturating_add(1);
+            let rssi = observation.meta.rssi;
+            association.station_rssi_max = Some(association.station_rssi_max.map_or(rssi, |max| max.max(rssi)));
+        } else {
+            association.downlink_frames = association.downlink_frames.saturating_add(1);
+        }
+    }
+
+    // Stations seen talking to each BSSID, ordered by BSSID
+    pub fn stations_by_bssid(&self) -> BTreeMap<MacAddress, Vec<MacAddress>> {
+        let mut networks: BTreeMap<MacAddress, Vec<MacAddress>> = BTreeMap::new();
+        for (station, bssid) in self.associations.keys() {
+            networks.entry(*bssid).or_default().push(*station);
+        }
+        for stations in networks.values_mut() {
+            stations.sort();
+        }
+        networks
+    }
+
+    pub fn serialize_records(&self) -> Vec<u8> {
+        let mut out = Vec::with_capacity(self.associations.len() * RECORD_LEN);
+        for ((station, bssid), association) in &self.associations {
+            association.write_record(station, bssid, &mut out);
+        }
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::observation::tests::observation;
+    use alloc::vec;
+
+    // Data frame with the given ToDS/FromDS flags
+    fn data_frame(flags: u8, addr1: MacAddress, addr2: MacAddress, addr3: MacAddress) -> Vec<u8> {
+        let mut frame = vec![0x08, flags, 0, 0];
+        frame.extend(addr1);
+        frame.extend(addr2);
+        frame.extend(addr3);
+        frame.extend([0, 0]);
+        frame
+    }
+
+    #[test]
+    fn pairs_stations_with_access_points() {
+        let ap = [0x00, 0x11, 0x22, 0x33, 0x44, 0x55];
+        let station = [0x3c, 0, 0, 0, 0, 1];
+        let mut table = AssociationTable::new();
+        table.observe(&observation(&data_frame(0x01, ap, station, [0xff; 6]), -50), 1);
+        table.observe(&observation(&data_frame(0x02, station, ap, ap), -30), 2);
+        // Multicast from the AP and frames outside infrastructure mode are not associations
+        table.observe(&observation(&data_frame(0x02, [0x01, 0, 0x5e, 0, 0, 1], ap, ap), -30), 3);
+        table.observe(&observation(&data_frame(0x00, station, ap, ap), -30), 4);
+
+        assert_eq!(table.len(), 1);
+        assert_eq!(table.stations_by_bssid()[&ap], vec![station]);
+        let records = table.serialize_records();
+        let (read_station, read_bssid, association) = Association::read_record(&records).unwrap();
+        assert_eq!((read_station, read_bssid), (station, ap));
+        assert_eq!((association.uplink_frames, association.downlink_frames), (1, 1));
+        assert_eq!(association.station_rssi_max, Some(-50));
+    }
+
+    #[test]
+    fn records_round_trip() {
+        let association = Association { first_seen_ms: 3, last_seen_ms: 4, uplink_frames: 5, downlink_frames: 6, station_rssi_max: Some(-60) };
+        let mut record = Vec::new();
+        association.write_record(&[1; 6], &[2; 6], &mut record);
+        assert_eq!(record.len(), RECORD_LEN);
+        assert_eq!(Association::read_record(&record), Some(([1; 6], [2; 6], association)));
+    }
+}
@@ -0,0 +1,246 @@
+// Capture mode: write every received frame to pcap/pcapng files in storage
+// Files are rotated at a fixed size; when storage is full the oldest capture file of this
+// session is deleted to make room for the next one.
+
+use alloc::{collections::VecDeque, format, string::String, vec::Vec};
+use core::time::Duration;
+
+use anyhow::Result;
+use log::{error, info};
+
+use crate::channel_hop::{ChannelHopper, ChannelPlan};
+use crate::gesture::ButtonEvent;
+use crate::hal::{Board, Clock, InputDevice, PacketSource, Screen, Storage};
+use crate::observation::{Observation, SignalMode};
+use crate::pcap::{self, CaptureOptions, RadioInfo};
+use crate::scan_duration::ScanDuration;
+
+const CAPTURE_FILE_MAX_BYTES: usize = 16 * 1024;
+const CAPTURE_LOOP_DELAY_MS: u32 = 10;
+const STATUS_INTERVAL_MS: u64 = 3000;
+// Records are appended to storage in blocks of about this size rather than one by one
+const WRITE_BUFFER_BYTES: usize = 1024;
+
+pub struct CaptureSummary {
+    pub frames: u32,
+    pub bytes: usize,
+    pub files_written: u32,
+    pub files_deleted: u32,
+}
+
+fn radio_info(observation: &Observation) -> RadioInfo {
+    let meta = &observation.meta;
+    RadioInfo {
+        channel: meta.channel,
+        rssi: meta.rssi,
+        noise: meta.noise_floor,
+        rate_500kbps: meta.rate_500kbps(),
+        mcs: if meta.sig_mode == SignalMode::Ht { Some(meta.mcs) } else { None },
+        wide_bandwidth: meta.wide_bandwidth,
+        bad_fcs: meta.fcs_failed,
+    }
+}
+
+struct CaptureFile {
+    name: String,
+    written: usize,
+    // Records not appended to the file yet
+    pending: Vec<u8>,
+}
+
+struct Rotator {
+    options: CaptureOptions,
+    session: u64,
+    next_index: u32,
+    current: Option<CaptureFile>,
+    // Files written by this session, oldest first
+    files: VecDeque<String>,
+    summary: CaptureSummary,
+}
+
+impl Rotator {
+    fn new(options: CaptureOptions, session: u64) -> Self {
+        Rotator {
+            options,
+            session,
+            next_index: 0,
+            current: None,
+            files: VecDeque::new(),
+            summary: CaptureSummary { frames: 0, bytes: 0, files_written: 0, files_deleted: 0 },
+        }
+    }
+
+    fn open_next(&mut self, storage: &mut impl Storage) -> Result<bool> {
+        self.finish(storage)?;
+
+        // Free space for a whole file up front so writes do not fail half way through a record
+        while !storage.has_enough_space(CAPTURE_FILE_MAX_BYTES)? {
+            let Some(oldest) = self.files.pop_front() else {
+                error!("Storage full and no capture files left to rotate out");
+                return Ok(false);
+            };
+            info!("Storage full, deleting {}", oldest);
+            storage.delete(&oldest)?;
+            self.summary.files_deleted += 1;
+        }
+
+        let name = format!("cap_{}_{}.{}", self.session, self.next_index, self.options.format.extension());
+        self.next_index += 1;
+        let header = pcap::file_header(&self.options);
+        storage.write(&name, &header)?;
+        info!("Capturing to {}", name);
+
+        self.files.push_back(name.clone());
+        self.summary.files_written += 1;
+        self.summary.bytes += header.len();
+        self.current = Some(CaptureFile { name, written: header.len(), pending: Vec::new() });
+        Ok(true)
+    }
+
+    // Returns false once nothing more can be written
+    fn write_record(&mut self, storage: &mut impl Storage, record: &[u8]) -> Result<bool> {
+        let needs_rotation = match &self.current {
+            Some(current) => current.written + record.len() > CAPTURE_FILE_MAX_BYTES,
+            None => true,
+        };
+        if needs_rotation && !self.open_next(storage)? {
+            return Ok(false);
+        }
+
+        if let Some(current) = self.current.as_mut() {
+            current.pending.extend_from_slice(record);
+            current.written += record.len();
+            if current.pending.len() >= WRITE_BUFFER_BYTES {
+                storage.append(&current.name, &current.pending)?;
+                current.pending.clear();
+            }
+            self.summary.frames += 1;
+            self.summary.bytes += record.len();
+        }
+        Ok(true)
+    }
+
+    // Writes out what is still buffered for the current file
+    fn finish(&mut self, storage: &mut impl Storage) -> Result<()> {
+        if let Some(current) = self.current.take() {
+            if !current.pending.is_empty() {
+                storage.append(&current.name, &current.pending)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+pub fn run_capture(
+    board: &mut Board<impl Screen, impl InputDevice, impl Clock>,
+    packets: &mut impl PacketSource,
+    storage: &mut impl Storage,
+    options: CaptureOptions,
+    channel_plan: &ChannelPlan,
+    duration: ScanDuration,
+) -> Result<CaptureSummary> {
+    let start_ms = board.clock.now_ms();
+    let start_unix_us = board.clock.unix_time_us();
+    let mut first_radio_timestamp: Option<u32> = None;
+    let mut hopper = ChannelHopper::new(channel_plan);
+    let mut last_check_in_ms = start_ms;
+
+    let mut rotator = Rotator::new(options, start_unix_us / 1_000_000);
+
+    'capture: loop {
+        let elapsed = Duration::from_millis(board.clock.now_ms() - start_ms);
+        if duration.as_duration().is_some_and(|duration| elapsed >= duration) {
+            break;
+        }
+        if let Some(channel) = hopper.poll(elapsed.as_millis() as u64) {
+            if let Err(e) = packets.set_channel(channel) {
+                error!("{}", e);
+            }
+        }
+
+        if board.input.poll() == ButtonEvent::LongPress {
+            info!("Capture stopped by long press");
+            break;
+        }
+
+        while let Some(observation) = packets.next_observation() {
+            // Radio timestamps are relative to boot; anchor them to the wall clock at start
+            let base = *first_radio_timestamp.get_or_insert(observation.meta.timestamp_us);
+            let timestamp_us = start_unix_us + observation.meta.timestamp_us.wrapping_sub(base) as u64;
+
+            let frame = observation.frame.as_slice();
+            let captured = pcap::captured_len(&options, frame.len(), observation.header.header_len);
+            let record = pcap::packet_record(
+                options.format,
+                timestamp_us,
+                &radio_info(&observation),
+                &frame[..captured],
+                observation.frame_len(),
+            );
+            if !rotator.write_record(storage, &record)? {
+                break 'capture;
+            }
+        }
+
+        if board.clock.now_ms() - last_check_in_ms >= STATUS_INTERVAL_MS {
+            let summary = &rotator.summary;
+            info!("Captured {} frames, {} bytes in {} files", summary.frames, summary.bytes, summary.files_written);
+            let screen = &mut board.screen;
+            screen.clear()?;
+            let time = match duration.as_duration() {
+                Some(duration) => format!("Capture {}s left", duration.saturating_sub(elapsed).as_secs()),
+                None => format!("Capture {}s", elapsed.as_secs()),
+            };
+            screen.draw_text(10, 5, &time, true)?;
+            screen.draw_text(10, 20, &format!("Frames: {}", summary.frames), true)?;
+            screen.draw_text(10, 32, &format!("Files: {} (-{})", summary.files_written, summary.files_deleted), true)?;
+            screen.draw_text(10, 44, &format!("Channel: {}", hopper.current_channel().unwrap_or(0)), true)?;
+            screen.draw_text(10, 54, &format!("Dropped: {}", packets.dropped()), true)?;
+            screen.flush()?;
+            last_check_in_ms = board.clock.now_ms();
+        }
+
+        board.clock.delay_ms(CAPTURE_LOOP_DELAY_MS);
+    }
+
+    rotator.finish(storage)?;
+    Ok(rotator.summary)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pcap::CaptureFormat;
+    use crate::sim::MemoryStorage;
+    use alloc::{string::ToString, vec};
+
+    const OPTIONS: CaptureOptions = CaptureOptions { format: CaptureFormat::Pcap, snaplen: 320, headers_only: false };
+
+    #[test]
+    fn rotates_out_the_oldest_file() {
+        let mut storage = MemoryStorage::new(2 * CAPTURE_FILE_MAX_BYTES + 100);
+        let mut rotator = Rotator::new(OPTIONS, 7);
+        let record = vec![0x5a; 1000];
+        for _ in 0..40 {
+            assert!(rotator.write_record(&mut storage, &record).unwrap());
+        }
+        rotator.finish(&mut storage).unwrap();
+
+        // 16 records fit in a file, so the third file needed the room of the first
+        assert_eq!((rotator.summary.files_written, rotator.summary.files_deleted), (3, 1));
+        assert_eq!(storage.list().unwrap(), ["cap_7_1.pcap", "cap_7_2.pcap"]);
+        assert_eq!(storage.size("cap_7_2.pcap").unwrap(), 24 + 8 * 1000);
+    }
+
+    #[test]
+    fn full_storage_without_capture_files_stops() {
+        let mut storage = MemoryStorage::new(CAPTURE_FILE_MAX_BYTES);
+        // Files from other modes are never rotated out
+        storage.write("scan_1_0000.bin", &[0; 100]).unwrap();
+        let mut rotator = Rotator::new(OPTIONS, 7);
+        assert!(!rotator.open_next(&mut storage).unwrap());
+        assert!(!rotator.write_record(&mut storage, &[0; 10]).unwrap());
+        assert_eq!(storage.list().unwrap(), ["scan_1_0000.bin".to_string()]);
+        assert_eq!((rotator.summary.files_written, rotator.summary.frames), (0, 0));
+    }
+}
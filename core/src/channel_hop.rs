@@ -0,0 +1,253 @@
+// Channel hopping for the promiscuous scan
+// The hop plan is a pure function of the configuration so it can be checked on the host;
+// ChannelHopper only needs a millisecond clock to decide when to move on.
+
+use alloc::{vec, vec::Vec};
+
+use anyhow::Result;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RegulatoryDomain {
+    // Americas: channels 1-11
+    Fcc,
+    // Europe and most of the world: channels 1-13
+    Etsi,
+    // Japan: channels 1-14 (14 is 802.11b only)
+    Japan,
+}
+
+impl RegulatoryDomain {
+    pub const ALL: [RegulatoryDomain; 3] = [RegulatoryDomain::Fcc, RegulatoryDomain::Etsi, RegulatoryDomain::Japan];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            RegulatoryDomain::Fcc => "FCC",
+            RegulatoryDomain::Etsi => "ETSI",
+            RegulatoryDomain::Japan => "Japan",
+        }
+    }
+
+    pub fn next(&self) -> RegulatoryDomain {
+        let idx = Self::ALL.iter().position(|domain| domain == self).unwrap_or(0);
+        Self::ALL[(idx + 1) % Self::ALL.len()]
+    }
+
+    pub fn id(&self) -> u8 {
+        Self::ALL.iter().position(|domain| domain == self).unwrap_or(0) as u8
+    }
+
+    pub fn from_id(id: u8) -> Option<RegulatoryDomain> {
+        Self::ALL.get(id as usize).copied()
+    }
+
+    pub fn channels(&self) -> &'static [u8] {
+        const ALL: [u8; 14] = [1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14];
+        match self {
+            RegulatoryDomain::Fcc => &ALL[..11],
+            RegulatoryDomain::Etsi => &ALL[..13],
+            RegulatoryDomain::Japan => &ALL[..],
+        }
+    }
+
+    pub fn allows(&self, channel: u8) -> bool {
+        self.channels().contains(&channel)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ChannelPlan {
+    pub domain: RegulatoryDomain,
+    // Channels to visit in order; empty means every channel in the domain
+    pub channels: Vec<u8>,
+    pub dwell_ms: u32,
+    // (channel, multiplier) pairs that stretch the dwell on busy channels
+    pub weights: Vec<(u8, u32)>,
+}
+
+impl ChannelPlan {
+    // Visit every channel in the domain, lingering twice as long on the common
+    // non-overlapping channels where most APs live
+    pub fn for_domain(domain: RegulatoryDomain, dwell_ms: u32) -> Self {
+        ChannelPlan {
+            domain,
+            channels: Vec::new(),
+            dwell_ms,
+            weights: vec![(1, 2), (6, 2), (11, 2)],
+        }
+    }
+
+    // Visit only `channels`, in that order, with the same dwell on each. Unlike the lenient
+    // `build_hop_plan`, a list that is empty, repeats a channel or leaves the domain is refused.
+    pub fn with_channels(domain: RegulatoryDomain, channels: &[u8], dwell_ms: u32) -> Result<Self> {
+        if channels.is_empty() {
+            return Err(anyhow::anyhow!("Channel list is empty"));
+        }
+        if dwell_ms == 0 {
+            return Err(anyhow::anyhow!("Dwell time must be at least 1 ms"));
+        }
+        for (idx, channel) in channels.iter().enumerate() {
+            if !domain.allows(*channel) {
+                return Err(anyhow::anyhow!("Channel {} is not allowed in {}", channel, domain.label()));
+            }
+            if channels[..idx].contains(channel) {
+                return Err(anyhow::anyhow!("Channel {} is listed twice", channel));
+            }
+        }
+        Ok(ChannelPlan { domain, channels: channels.to_vec(), dwell_ms, weights: Vec::new() })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct HopStep {
+    pub channel: u8,
+    pub dwell_ms: u32,
+}
+
+// Expand a plan into the concrete sequence of hops. Channels outside the regulatory
+// domain and duplicates are dropped, and zero weights are treated as 1.
+pub fn build_hop_plan(plan: &ChannelPlan) -> Vec<HopStep> {
+    let requested: &[u8] = if plan.channels.is_empty() {
+        plan.domain.channels()
+    } else {
+        &plan.channels
+    };
+
+    let mut steps: Vec<HopStep> = Vec::with_capacity(requested.len());
+    for &channel in requested {
+        if !plan.domain.allows(channel) || steps.iter().any(|step| step.channel == channel) {
+            continue;
+        }
+        let weight = plan.weights.iter()
+            .find(|(weighted_channel, _)| *weighted_channel == channel)
+            .map(|(_, weight)| (*weight).max(1))
+            .unwrap_or(1);
+        steps.push(HopStep {
+            channel,
+            dwell_ms: plan.dwell_ms.saturating_mul(weight),
+        });
+    }
+    steps
+}
+
+pub struct ChannelHopper {
+    steps: Vec<HopStep>,
+    index: usize,
+    step_started_ms: Option<u64>,
+}
+
+impl ChannelHopper {
+    pub fn new(plan: &ChannelPlan) -> Self {
+        ChannelHopper {
+            steps: build_hop_plan(plan),
+            index: 0,
+            step_started_ms: None,
+        }
+    }
+
+    pub fn current_channel(&self) -> Option<u8> {
+        self.steps.get(self.index).map(|step| step.channel)
+    }
+
+    // Returns the channel to tune to when a hop is due, None when the radio should stay put.
+    // The first poll always returns the first channel of the plan.
+    pub fn poll(&mut self, now_ms: u64) -> Option<u8> {
+        let step = *self.steps.get(self.index)?;
+        match self.step_started_ms {
+            None => {
+                self.step_started_ms = Some(now_ms);
+                Some(step.channel)
+            },
+            Some(started) if now_ms.saturating_sub(started) >= step.dwell_ms as u64 => {
+                if self.steps.len() == 1 {
+                    self.step_started_ms = Some(now_ms);
+                    return None;
+                }
+                self.index = (self.index + 1) % self.steps.len();
+                self.step_started_ms = Some(now_ms);
+                self.current_channel()
+            },
+            Some(_) => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn plan_follows_domain() {
+        let steps = build_hop_plan(&ChannelPlan::for_domain(RegulatoryDomain::Fcc, 100));
+        assert_eq!(steps.len(), 11);
+        assert_eq!(steps[0], HopStep { channel: 1, dwell_ms: 200 });
+        assert_eq!(steps[1], HopStep { channel: 2, dwell_ms: 100 });
+
+        let plan = ChannelPlan { domain: RegulatoryDomain::Fcc, channels: vec![6, 13, 6, 1], dwell_ms: 50, weights: vec![(1, 0)] };
+        let channels: Vec<u8> = build_hop_plan(&plan).iter().map(|step| step.channel).collect();
+        assert_eq!(channels, [6, 1]);
+    }
+
+    #[test]
+    fn default_plan_covers_channels_1_to_13() {
+        let steps = build_hop_plan(&ChannelPlan::for_domain(RegulatoryDomain::Etsi, 250));
+        let channels: Vec<u8> = steps.iter().map(|step| step.channel).collect();
+        assert_eq!(channels, (1..=13).collect::<Vec<u8>>());
+        for step in steps {
+            let expected = if [1, 6, 11].contains(&step.channel) { 500 } else { 250 };
+            assert_eq!(step.dwell_ms, expected, "channel {}", step.channel);
+        }
+        assert_eq!(build_hop_plan(&ChannelPlan::for_domain(RegulatoryDomain::Japan, 250)).last().unwrap().channel, 14);
+    }
+
+    #[test]
+    fn custom_plan_with_dwell_times() {
+        let mut plan = ChannelPlan::with_channels(RegulatoryDomain::Fcc, &[11, 1, 6], 120).unwrap();
+        plan.weights = vec![(6, 3)];
+        assert_eq!(build_hop_plan(&plan), [
+            HopStep { channel: 11, dwell_ms: 120 },
+            HopStep { channel: 1, dwell_ms: 120 },
+            HopStep { channel: 6, dwell_ms: 360 },
+        ]);
+    }
+
+    #[test]
+    fn rejects_bad_channel_lists() {
+        assert!(ChannelPlan::with_channels(RegulatoryDomain::Etsi, &[], 100).is_err());
+        assert!(ChannelPlan::with_channels(RegulatoryDomain::Etsi, &[1, 14], 100).is_err());
+        assert!(ChannelPlan::with_channels(RegulatoryDomain::Fcc, &[12], 100).is_err());
+        assert!(ChannelPlan::with_channels(RegulatoryDomain::Etsi, &[0], 100).is_err());
+        assert!(ChannelPlan::with_channels(RegulatoryDomain::Etsi, &[6, 1, 6], 100).is_err());
+        assert!(ChannelPlan::with_channels(RegulatoryDomain::Etsi, &[6], 0).is_err());
+        assert!(ChannelPlan::with_channels(RegulatoryDomain::Japan, &[14], 100).is_ok());
+    }
+
+    #[test]
+    fn wraps_around_after_the_last_channel() {
+        let mut plan = ChannelPlan::with_channels(RegulatoryDomain::Etsi, &[1, 6, 11], 100).unwrap();
+        plan.weights = vec![(11, 2)];
+        let mut hopper = ChannelHopper::new(&plan);
+        let mut hops = Vec::new();
+        for now_ms in (0..=900).step_by(50) {
+            if let Some(channel) = hopper.poll(now_ms) {
+                hops.push((now_ms, channel));
+            }
+        }
+        assert_eq!(hops, [(0, 1), (100, 6), (200, 11), (400, 1), (500, 6), (600, 11), (800, 1), (900, 6)]);
+
+        // A single channel never retunes
+        let mut hopper = ChannelHopper::new(&ChannelPlan::with_channels(RegulatoryDomain::Etsi, &[6], 100).unwrap());
+        assert_eq!(hopper.poll(0), Some(6));
+        assert!((1..1000).all(|now_ms| hopper.poll(now_ms).is_none()));
+    }
+
+    #[test]
+    fn hops_after_dwell() {
+        let plan = ChannelPlan { domain: RegulatoryDomain::Etsi, channels: vec![1, 6], dwell_ms: 100, weights: Vec::new() };
+        let mut hopper = ChannelHopper::new(&plan);
+        assert_eq!(hopper.poll(0), Some(1));
+        assert_eq!(hopper.poll(99), None);
+        assert_eq!(hopper.poll(100), Some(6));
+        assert_eq!(hopper.poll(250), Some(1));
+        assert_eq!(hopper.current_channel(), Some(1));
+    }
+}
@@ -0,0 +1,78 @@
+// Dump mode: send every file in storage to the host over a `Link`
+// (`mac_sniff_host receive`, or tools/receive_dump.py). See dump_protocol.rs for the framing.
+
+use alloc::format;
+use core::time::Duration;
+
+use anyhow::Result;
+use log::{error, info};
+
+use crate::dump_protocol::{DumpConfig, DumpFormat, DumpSender, Link};
+use crate::hal::{Screen, Storage};
+
+const CHUNK_SIZE: usize = 128;
+const REPLY_TIMEOUT: Duration = Duration::from_secs(2);
+const MAX_ATTEMPTS: u32 = 5;
+
+pub struct DumpSummary {
+    pub files: usize,
+    pub bytes: usize,
+    pub retransmits: u32,
+}
+
+// `only` limits the dump to one file
+pub fn run_dump(
+    screen: &mut impl Screen,
+    storage: &impl Storage,
+    link: &mut impl Link,
+    format: DumpFormat,
+    only: Option<&str>,
+) -> Result<DumpSummary> {
+    info!("Starting storage dump to USB");
+    screen.clear()?;
+    screen.draw_text(5, 5, "Dumping files...", true)?;
+    screen.flush()?;
+
+    let mut files = storage.list()?;
+    if let Some(only) = only {
+        files.retain(|name| name == only);
+        if files.is_empty() {
+            return Err(anyhow::anyhow!("No such file {}", only));
+        }
+    }
+    if files.is_empty() {
+        return Err(anyhow::anyhow!("No files to dump"));
+    }
+    // Show number of files found
+    screen.draw_text(5, 20, &format!("Found {} files", files.len()), true)?;
+    screen.flush()?;
+
+    let config = DumpConfig { format, chunk_size: CHUNK_SIZE, reply_timeout: REPLY_TIMEOUT, max_attempts: MAX_ATTEMPTS };
+    let mut sender = DumpSender::begin(link, config, files.len())?;
+    let mut summary = DumpSummary { files: 0, bytes: 0, retransmits: 0 };
+    for (idx, name) in files.iter().enumerate() {
+        let content = match storage.read(name) {
+            Ok(content) => content,
+            Err(e) => {
+                error!("Failed to read file {}: {}", name, e);
+                continue;
+            }
+        };
+
+        screen.clear()?;
+        screen.draw_text(5, 5, "Dumping files...", true)?;
+        screen.draw_text(5, 20, &format!("File {}/{}", idx + 1, files.len()), true)?;
+        screen.draw_text(5, 30, &format!("Size: {} bytes", content.len()), true)?;
+        screen.flush()?;
+
+        let stats = sender.send_file(name, &content)?;
+        if stats.resumed_from > 0 {
+            info!("{} resumed at byte {}", name, stats.resumed_from);
+        }
+        summary.files += 1;
+        summary.bytes += content.len();
+        summary.retransmits += stats.retransmits;
+    }
+    sender.end(summary.bytes)?;
+    Ok(summary)
+}
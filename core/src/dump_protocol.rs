@@ -0,0 +1,521 @@
+// Line based protocol for sending SPIFFS files over the serial console
+//
+//   device                                  host
+//   MAC_SNIFF_DUMP_BEGIN:2
+//   NUM_FILES:<n>
+//   FILE_BEGIN:<size>:<crc32>:<hex|base64>:<path>:<crc32> ->
+//                                      <-   RESUME <offset>
+//   CHUNK:<seq>:<offset>:<data>:<crc32> ->
+//                                      <-   ACK <seq> | NAK <seq>
+//   ...
+//   FILE_END                           ->
+//                                      <-   DONE | FAIL
+//   MAC_SNIFF_DUMP_END
+//   TOTAL_BYTES:<n>
+//
+// FILE_BEGIN and CHUNK lines end in a CRC of everything between the first and the last colon,
+// so a damaged header is caught as well as damaged data. The first CRC of FILE_BEGIN covers
+// the whole file. Anything that is NAKed or not answered in time is sent again, and RESUME lets
+// the host keep what it already has from an earlier, interrupted transfer. Lines that are not
+// part of the protocol (log output) are ignored by both sides.
+
+use alloc::{collections::BTreeMap, format, string::{String, ToString}, vec::Vec};
+use core::time::Duration;
+
+use anyhow::Result;
+
+pub const PROTOCOL_VERSION: u32 = 2;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DumpFormat {
+    Hex,
+    Base64,
+}
+
+impl DumpFormat {
+    pub const ALL: [DumpFormat; 2] = [DumpFormat::Hex, DumpFormat::Base64];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            DumpFormat::Hex => "Hex",
+            DumpFormat::Base64 => "Base64",
+        }
+    }
+
+    pub fn next(&self) -> DumpFormat {
+        let idx = Self::ALL.iter().position(|format| format == self).unwrap_or(0);
+        Self::ALL[(idx + 1) % Self::ALL.len()]
+    }
+
+    pub fn id(&self) -> u8 {
+        Self::ALL.iter().position(|format| format == self).unwrap_or(0) as u8
+    }
+
+    pub fn from_id(id: u8) -> Option<DumpFormat> {
+        Self::ALL.get(id as usize).copied()
+    }
+
+    // Name announced in the FORMAT: line
+    pub fn protocol_name(&self) -> &'static str {
+        match self {
+            DumpFormat::Hex => "hex",
+            DumpFormat::Base64 => "base64",
+        }
+    }
+
+    pub fn from_protocol_name(name: &str) -> Option<DumpFormat> {
+        Self::ALL.iter().copied().find(|format| format.protocol_name() == name)
+    }
+
+    pub fn encode(&self, data: &[u8]) -> String {
+        match self {
+            DumpFormat::Hex => data.iter().map(|byte| format!("{:02x}", byte)).collect(),
+            DumpFormat::Base64 => base64_encode(data),
+        }
+    }
+
+    pub fn decode(&self, text: &str) -> Option<Vec<u8>> {
+        match self {
+            DumpFormat::Hex => {
+                if text.len() % 2 != 0 {
+                    return None;
+                }
+                (0..text.len()).step_by(2).map(|idx| u8::from_str_radix(text.get(idx..idx + 2)?, 16).ok()).collect()
+            },
+            DumpFormat::Base64 => base64_decode(text),
+        }
+    }
+}
+
+const BASE64_ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+// Standard alphabet with padding
+fn base64_encode(data: &[u8]) -> String {
+    let mut out = String::with_capacity(data.len().div_ceil(3) * 4);
+    for chunk in data.chunks(3) {
+        let bytes = [chunk[0], *chunk.get(1).unwrap_or(&0), *chunk.get(2).unwrap_or(&0)];
+        let bits = (bytes[0] as u32) << 16 | (bytes[1] as u32) << 8 | bytes[2] as u32;
+        for idx in 0..4 {
+            if idx <= chunk.len() {
+                out.push(BASE64_ALPHABET[(bits >> (18 - 6 * idx) & 0x3f) as usize] as char);
+            } else {
+                out.push('=');
+            }
+        }
+    }
+    out
+}
+
+fn base64_decode(text: &str) -> Option<Vec<u8>> {
+    if text.len() % 4 != 0 {
+        return None;
+    }
+    let mut out = Vec::with_capacity(text.len() / 4 * 3);
+    for quad in text.as_bytes().chunks(4) {
+        let padding = quad.iter().rev().take_while(|&&byte| byte == b'=').count();
+        if padding > 2 {
+            return None;
+        }
+        let mut bits = 0u32;
+        for &byte in &quad[..4 - padding] {
+            let value = BASE64_ALPHABET.iter().position(|&symbol| symbol == byte)? as u32;
+            bits = bits << 6 | value;
+        }
+        bits <<= 6 * padding as u32;
+        out.extend_from_slice(&bits.to_be_bytes()[1..4 - padding]);
+    }
+    Some(out)
+}
+
+// CRC-32 (IEEE 802.3), the same as zlib.crc32 on the host
+pub fn crc32(data: &[u8]) -> u32 {
+    let mut crc = 0xffff_ffffu32;
+    for &byte in data {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 { crc >> 1 ^ 0xedb8_8320 } else { crc >> 1 };
+        }
+    }
+    !crc
+}
+
+// One line based connection to the other side; lines have no trailing newline
+pub trait Link {
+    fn send(&mut self, line: &str) -> Result<()>;
+    // None when nothing arrived within `timeout`
+    fn receive(&mut self, timeout: Duration) -> Result<Option<String>>;
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DumpConfig {
+    pub format: DumpFormat,
+    pub chunk_size: usize,
+    // How long to wait for an ACK, NAK or RESUME
+    pub reply_timeout: Duration,
+    // Attempts per chunk before the transfer is given up
+    pub max_attempts: u32,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct FileStats {
+    pub resumed_from: usize,
+    pub chunks: u32,
+    pub retransmits: u32,
+}
+
+// "<body>:<crc32 of body>"
+fn with_crc(body: &str) -> String {
+    format!("{}:{:08x}", body, crc32(body.as_bytes()))
+}
+
+// The body of a line written by `with_crc`, if its CRC matches
+fn check_crc(line: &str) -> Option<&str> {
+    let (body, crc) = line.rsplit_once(':')?;
+    (u32::from_str_radix(crc, 16).ok()? == crc32(body.as_bytes())).then_some(body)
+}
+
+// Device side of the protocol
+pub struct DumpSender<'a, L: Link> {
+    link: &'a mut L,
+    config: DumpConfig,
+}
+
+impl<'a, L: Link> DumpSender<'a, L> {
+    pub fn begin(link: &'a mut L, config: DumpConfig, num_files: usize) -> Result<Self> {
+        link.send(&format!("MAC_SNIFF_DUMP_BEGIN:{}", PROTOCOL_VERSION))?;
+        link.send(&format!("NUM_FILES:{}", num_files))?;
+        Ok(DumpSender { link, config })
+    }
+
+    // Sends `lines` and waits for a reply accepted by `parse`, skipping anything else. The lines
+    // are sent again when no accepted reply arrives in time, up to `max_attempts` times.
+    fn request<T>(&mut self, lines: &[&str], parse: impl Fn(&str) -> Option<T>) -> Result<(Option<T>, u32)> {
+        for attempt in 0..self.config.max_attempts {
+            for line in lines {
+                self.link.send(line)?;
+            }
+            while let Some(line) = self.link.receive(self.config.reply_timeout)? {
+                if let Some(reply) = parse(line.trim()) {
+                    return Ok((Some(reply), attempt));
+                }
+            }
+        }
+        Ok((None, self.config.max_attempts))
+    }
+
+    pub fn send_file(&mut self, path: &str, content: &[u8]) -> Result<FileStats> {
+        let header = format!("FILE_BEGIN:{}", with_crc(&format!("{}:{:08x}:{}:{}",
+            content.len(),
+            crc32(content),
+            self.config.format.protocol_name(),
+            path
+        )));
+        let mut stats = FileStats::default();
+        // A failed whole-file check restarts the file once
+        for _ in 0..2 {
+            let (resume, retries) = self.request(&[&header], |line| line.strip_prefix("RESUME ")?.parse::<usize>().ok())?;
+            stats.retransmits += retries;
+            let resume = resume.ok_or_else(|| anyhow::anyhow!("No reply from host for {}", path))?;
+            let mut offset = if resume <= content.len() { resume } else { 0 };
+            stats.resumed_from = offset;
+
+            let mut seq = 0u32;
+            while offset < content.len() {
+                let end = (offset + self.config.chunk_size).min(content.len());
+                let line = format!("CHUNK:{}", with_crc(&format!("{}:{}:{}", seq, offset, self.config.format.encode(&content[offset..end]))));
+                let mut acked = false;
+                // A NAK is answered at once; only a missing reply waits for the timeout
+                for _ in 0..self.config.max_attempts {
+                    let (reply, retries) = self.request(&[&line], |line| {
+                        let (ack, reply_seq) = line.split_once(' ')?;
+                        let reply_seq: u32 = reply_seq.parse().ok()?;
+                        (reply_seq == seq && (ack == "ACK" || ack == "NAK")).then_some(ack == "ACK")
+                    })?;
+                    stats.retransmits += retries;
+                    match reply {
+                        Some(true) => {
+                            acked = true;
+                            break;
+                        },
+                        Some(false) => stats.retransmits += 1,
+                        None => break,
+                    }
+                }
+                if !acked {
+                    return Err(anyhow::anyhow!("Chunk {} of {} was not acknowledged", seq, path));
+                }
+                stats.chunks += 1;
+                offset = end;
+                seq += 1;
+            }
+
+            let (verdict, retries) = self.request(&["FILE_END"], |line| match line {
+                "DONE" => Some(true),
+                "FAIL" => Some(false),
+                _ => None,
+            })?;
+            stats.retransmits += retries;
+            match verdict {
+                Some(true) => return Ok(stats),
+                Some(false) => continue,
+                None => return Err(anyhow::anyhow!("No verdict from host for {}", path)),
+            }
+        }
+        Err(anyhow::anyhow!("Host rejected {} twice", path))
+    }
+
+    pub fn end(self, total_bytes: usize) -> Result<()> {
+        self.link.send("MAC_SNIFF_DUMP_END")?;
+        self.link.send(&format!("TOTAL_BYTES:{}", total_bytes))
+    }
+}
+
+// The receiving side, used by `mac_sniff_host receive` and mirrored by tools/receive_dump.py.
+// The firmware only sends; `sim::Loopback` wires both sides together so the protocol can be
+// exercised without a serial port.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ReceivedFile {
+    pub path: String,
+    pub data: Vec<u8>,
+}
+
+struct IncomingFile {
+    path: String,
+    size: usize,
+    crc: u32,
+    format: DumpFormat,
+    data: Vec<u8>,
+}
+
+// Name a received file is stored under on the host; SPIFFS has no directories. None for a
+// name that could leave the output directory on some host, like "..", "a\\b" or "C:x".
+pub fn file_name(path: &str) -> Option<&str> {
+    let name = path.rsplit('/').next()?;
+    if name.is_empty() || name == "." || name == ".." || name.contains(['\\', ':']) {
+        return None;
+    }
+    Some(name)
+}
+
+// Host side of the protocol as a state machine: feed it every line from the device and send
+// back whatever it returns
+pub struct DumpReceiver {
+    // Data already received in an earlier, interrupted transfer, keyed by `file_name`
+    partial: BTreeMap<String, Vec<u8>>,
+    current: Option<IncomingFile>,
+    pub files: Vec<ReceivedFile>,
+    pub finished: bool,
+}
+
+impl DumpReceiver {
+    pub fn new(partial: BTreeMap<String, Vec<u8>>) -> Self {
+        DumpReceiver { partial, current: None, files: Vec::new(), finished: false }
+    }
+
+    // What has arrived of the file in progress, to keep for a later RESUME
+    pub fn in_progress(&self) -> Option<(&str, &[u8])> {
+        self.current.as_ref().map(|file| (file.path.as_str(), file.data.as_slice()))
+    }
+
+    pub fn handle_line(&mut self, line: &str) -> Option<String> {
+        let line = line.trim();
+        if let Some(header) = line.strip_prefix("FILE_BEGIN:") {
+            let mut fields = check_crc(header)?.splitn(4, ':');
+            let size: usize = fields.next()?.parse().ok()?;
+            let crc = u32::from_str_radix(fields.next()?, 16).ok()?;
+            let format = DumpFormat::from_protocol_name(fields.next()?)?;
+            let path = fields.next()?;
+            // Never answered, so the sender gives up on the file
+            let name = file_name(path)?;
+            // A repeated header keeps what already arrived for the file
+            let mut data = match self.current.take() {
+                Some(file) if file.path == path => file.data,
+                _ => self.partial.remove(name).unwrap_or_default(),
+            };
+            if data.len() > size {
+                data.clear();
+            }
+            let reply = format!("RESUME {}", data.len());
+            self.current = Some(IncomingFile { path: path.to_string(), size, crc, format, data });
+            return Some(reply);
+        } else if let Some(chunk) = line.strip_prefix("CHUNK:") {
+            return self.handle_chunk(chunk);
+        } else if line == "FILE_END" {
+            let file = self.current.take()?;
+            if file.data.len() == file.size && file.crc == crc32(&file.data) {
+                self.files.push(ReceivedFile { path: file.path, data: file.data });
+                return Some("DONE".to_string());
+            }
+            return Some("FAIL".to_string());
+        } else if line == "MAC_SNIFF_DUMP_END" {
+            self.finished = true;
+        }
+        None
+    }
+
+    fn handle_chunk(&mut self, chunk: &str) -> Option<String> {
+        let seq: u32 = chunk.split(':').next()?.parse().ok()?;
+        let nak = Some(format!("NAK {}", seq));
+        let Some(body) = check_crc(chunk) else {
+            return nak;
+        };
+        let mut fields = body.splitn(3, ':').skip(1);
+        let (Some(offset), Some(data)) = (fields.next().and_then(|offset| offset.parse::<usize>().ok()), fields.next()) else {
+            return nak;
+        };
+        let Some(file) = self.current.as_mut() else {
+            return nak;
+        };
+        let Some(data) = file.format.decode(data) else {
+            return nak;
+        };
+        // A chunk sent again because its ACK was lost is acknowledged without appending it twice
+        if offset + data.len() <= file.data.len() {
+            return Some(format!("ACK {}", seq));
+        }
+        if offset != file.data.len() {
+            return nak;
+        }
+        file.data.extend_from_slice(&data);
+        Some(format!("ACK {}", seq))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sim::Loopback;
+    use alloc::{boxed::Box, vec};
+
+    fn config(format: DumpFormat) -> DumpConfig {
+        DumpConfig { format, chunk_size: 50, reply_timeout: Duration::from_millis(1), max_attempts: 4 }
+    }
+
+    fn files() -> Vec<(String, Vec<u8>)> {
+        vec![
+            ("/spffs/a.bin".to_string(), (0..1000u32).map(|idx| (idx * 7) as u8).collect()),
+            ("/spffs/empty".to_string(), Vec::new()),
+            ("/spffs/c".to_string(), vec![1, 2, 3, 4, 5]),
+        ]
+    }
+
+    fn send_all(link: &mut Loopback, format: DumpFormat) -> Vec<FileStats> {
+        let files = files();
+        let mut sender = DumpSender::begin(link, config(format), files.len()).unwrap();
+        let stats = files.iter().map(|(path, data)| sender.send_file(path, data).unwrap()).collect();
+        sender.end(0).unwrap();
+        stats
+    }
+
+    fn received(link: &Loopback) -> Vec<(String, Vec<u8>)> {
+        link.receiver.files.iter().map(|file| (file.path.clone(), file.data.clone())).collect()
+    }
+
+    #[test]
+    fn encodings() {
+        assert_eq!(crc32(b"123456789"), 0xcbf4_3926);
+        assert_eq!(DumpFormat::Base64.encode(b"M"), "TQ==");
+        assert_eq!(DumpFormat::Base64.encode(b"Ma"), "TWE=");
+        assert_eq!(DumpFormat::Base64.encode(b"Man"), "TWFu");
+        for format in DumpFormat::ALL {
+            for len in 0..10u8 {
+                let data: Vec<u8> = (0..len).map(|byte| byte.wrapping_mul(37)).collect();
+                assert_eq!(format.decode(&format.encode(&data)), Some(data));
+            }
+        }
+        assert_eq!(DumpFormat::Hex.decode("abc"), None);
+        assert_eq!(DumpFormat::Base64.decode("T==="), None);
+    }
+
+    #[test]
+    fn clean_transfer() {
+        for format in DumpFormat::ALL {
+            let mut link = Loopback::new(DumpReceiver::new(BTreeMap::new()));
+            let stats = send_all(&mut link, format);
+            assert!(link.receiver.finished);
+            assert_eq!(received(&link), files());
+            assert_eq!((stats[0].chunks, stats[0].retransmits), (20, 0));
+        }
+    }
+
+    #[test]
+    fn damaged_lines_are_sent_again() {
+        for period in 2..9 {
+            for offset in 0..period {
+                let mut link = Loopback::new(DumpReceiver::new(BTreeMap::new()));
+                link.corrupt = Box::new(move |idx| idx % period == offset);
+                let stats = send_all(&mut link, DumpFormat::Base64);
+                assert_eq!(received(&link), files(), "every {}th line from {}", period, offset);
+                assert!(stats.iter().map(|stats| stats.retransmits).sum::<u32>() > 0);
+            }
+        }
+    }
+
+    #[test]
+    fn damaged_chunk_is_naked_and_sent_again() {
+        // Lines 0-2 are the preamble and the first FILE_BEGIN, line 3 is chunk 0
+        let mut link = Loopback::new(DumpReceiver::new(BTreeMap::new()));
+        link.corrupt = Box::new(|idx| idx == 3 || idx == 9);
+        let stats = send_all(&mut link, DumpFormat::Hex);
+        assert_eq!(received(&link), files());
+        assert_eq!((stats[0].chunks, stats[0].retransmits), (20, 2));
+        assert_eq!(&link.history[..4], ["RESUME 0", "NAK 0", "ACK 0", "ACK 1"]);
+        assert_eq!(link.history.iter().filter(|reply| reply.starts_with("NAK")).count(), 2);
+    }
+
+    #[test]
+    fn resumes_after_the_link_drops() {
+        let files = files();
+        let (path, data) = &files[0];
+        let mut link = Loopback::new(DumpReceiver::new(BTreeMap::new()));
+        // Chunks 0-6 get through, then the cable is pulled
+        link.lost = Box::new(|idx| idx >= 10);
+        let mut sender = DumpSender::begin(&mut link, config(DumpFormat::Base64), 1).unwrap();
+        assert!(sender.send_file(path, data).is_err());
+        let (partial_path, partial_data) = link.receiver.in_progress().unwrap();
+        assert_eq!((partial_path, partial_data), (path.as_str(), &data[..350]));
+        assert!(link.receiver.files.is_empty());
+
+        // The host keeps what arrived under the file name and a new transfer picks it up
+        let mut partial = BTreeMap::new();
+        partial.insert(file_name(partial_path).unwrap().to_string(), partial_data.to_vec());
+        let mut link = Loopback::new(DumpReceiver::new(partial));
+        let stats = send_all(&mut link, DumpFormat::Base64);
+        assert_eq!((stats[0].resumed_from, stats[0].chunks, stats[0].retransmits), (350, 13, 0));
+        assert_eq!(link.history[0], "RESUME 350");
+        assert_eq!(received(&link), files);
+    }
+
+    #[test]
+    fn unsafe_file_names_are_refused() {
+        assert_eq!(file_name("/spiffs/scan_1_0000.bin"), Some("scan_1_0000.bin"));
+        assert_eq!(file_name("cap_0.pcap"), Some("cap_0.pcap"));
+        for path in ["", "/spiffs/", "..", "/spiffs/..", ".", "..\\..\\evil.bin", "a\\b", "C:evil", "/spiffs/x:y"] {
+            assert_eq!(file_name(path), None, "{}", path);
+        }
+
+        let mut link = Loopback::new(DumpReceiver::new(BTreeMap::new()));
+        let mut sender = DumpSender::begin(&mut link, config(DumpFormat::Hex), 1).unwrap();
+        assert!(sender.send_file("..\\..\\evil.bin", b"data").is_err());
+        assert!(link.history.is_empty());
+        assert!(link.receiver.in_progress().is_none());
+    }
+
+    #[test]
+    fn resumes_partial_files() {
+        let mut partial = BTreeMap::new();
+        partial.insert("a.bin".to_string(), files()[0].1[..300].to_vec());
+        let mut link = Loopback::new(DumpReceiver::new(partial));
+        let stats = send_all(&mut link, DumpFormat::Hex);
+        assert_eq!((stats[0].resumed_from, stats[0].chunks), (300, 14));
+        assert_eq!(received(&link), files());
+
+        // Data that does not match the file fails the whole-file check and starts over
+        let mut partial = BTreeMap::new();
+        partial.insert("a.bin".to_string(), vec![9; 300]);
+        let mut link = Loopback::new(DumpReceiver::new(partial));
+        let stats = send_all(&mut link, DumpFormat::Base64);
+        assert_eq!(stats[0].resumed_from, 0);
+        assert_eq!(received(&link), files());
+    }
+}
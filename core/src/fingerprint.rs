@@ -0,0 +1,198 @@
+// Probe request fingerprinting
+//
+// Phones rotate locally administered MACs, but the information elements they put in probe
+// requests (rates, HT/VHT capabilities, extended capabilities, vendor IEs and their order)
+// stay the same across rotations. Probes from randomized MACs are grouped into clusters that
+// share a fingerprint and continue each other's sequence numbers; each cluster is an
+// estimated physical device.
+
+use alloc::{vec, vec::Vec};
+
+use crate::frame::MacAddress;
+use crate::ie::{self, IE_DS_PARAMETER_SET, IE_EXTENDED_CAPABILITIES, IE_EXTENDED_SUPPORTED_RATES,
+    IE_HT_CAPABILITIES, IE_SSID, IE_SUPPORTED_RATES, IE_VENDOR_SPECIFIC, IE_VHT_CAPABILITIES};
+
+// How far the sequence number may advance between two MACs of the same device
+const MAX_SEQUENCE_GAP: u16 = 64;
+// How long after its last probe a cluster may still absorb a new MAC
+const MAX_CLUSTER_IDLE_MS: u32 = 60_000;
+
+// FNV-1a, stable across builds so fingerprints can be compared between scans
+pub fn fnv1a(hash: u64, bytes: &[u8]) -> u64 {
+    bytes.iter().fold(hash, |hash, b| (hash ^ *b as u64).wrapping_mul(0x0000_0100_0000_01B3))
+}
+
+pub const FNV_OFFSET: u64 = 0xCBF2_9CE4_8422_2325;
+
+// Hash of the parts of a probe request body that identify the device model/driver rather
+// than the network it is looking for
+pub fn probe_fingerprint(ies: &[u8]) -> u64 {
+    let mut hash = FNV_OFFSET;
+    for element in ie::elements(ies) {
+        // SSID and current channel vary per probe
+        if element.id == IE_SSID || element.id == IE_DS_PARAMETER_SET {
+            continue;
+        }
+        hash = fnv1a(hash, &[element.id]);
+        match element.id {
+            IE_SUPPORTED_RATES | IE_EXTENDED_SUPPORTED_RATES | IE_HT_CAPABILITIES
+            | IE_VHT_CAPABILITIES | IE_EXTENDED_CAPABILITIES => {
+                hash = fnv1a(hash, element.data);
+            },
+            IE_VENDOR_SPECIFIC => {
+                // Vendor payloads often carry per-probe nonces (e.g. WPS UUIDs); keep OUI + type
+                if let Some((oui, vendor_type)) = element.vendor_oui() {
+                    hash = fnv1a(hash, &oui);
+                    hash = fnv1a(hash, &[vendor_type]);
+                }
+            },
+            _ => {
+                if let Some(ext_id) = element.extension_id() {
+                    hash = fnv1a(hash, &[ext_id]);
+                }
+            },
+        }
+    }
+    hash
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Cluster {
+    pub fingerprint: u64,
+    pub macs: Vec<MacAddress>,
+    last_sequence: u16,
+    last_seen_ms: u32,
+}
+
+#[derive(Debug, Default)]
+pub struct DeviceClusterer {
+    clusters: Vec<Cluster>,
+}
+
+impl DeviceClusterer {
+    pub fn new() -> Self {
+        DeviceClusterer::default()
+    }
+
+    // Feed a probe request sent from a locally administered `mac`
+    pub fn observe_probe(&mut self, mac: MacAddress, fingerprint: u64, sequence: u16, now_ms: u32) {
+        if let Some(cluster) = self.clusters.iter_mut().find(|cluster| cluster.macs.contains(&mac)) {
+            cluster.last_sequence = sequence;
+            cluster.last_seen_ms = now_ms;
+            return;
+        }
+
+        // A new MAC continues an existing device when the fingerprint matches and the
+        // 12-bit sequence counter picks up where that device left off
+        let continued = self.clusters.iter_mut().find(|cluster| {
+            cluster.fingerprint == fingerprint
+                && now_ms.saturating_sub(cluster.last_seen_ms) <= MAX_CLUSTER_IDLE_MS
+                && sequence.wrapping_sub(cluster.last_sequence) & 0x0FFF <= MAX_SEQUENCE_GAP
+        });
+
+        match continued {
+            Some(cluster) => {
+                cluster.macs.push(mac);
+                cluster.last_sequence = sequence;
+                cluster.last_seen_ms = now_ms;
+            },
+            None => self.clusters.push(Cluster {
+                fingerprint,
+                macs: vec![mac],
+                last_sequence: sequence,
+                last_seen_ms: now_ms,
+            }),
+        }
+    }
+
+    pub fn clusters(&self) -> &[Cluster] {
+        &self.clusters
+    }
+
+    pub fn clustered_macs(&self) -> usize {
+        self.clusters.iter().map(|cluster| cluster.macs.len()).sum()
+    }
+
+    // Replace every clustered randomized MAC in `unique_macs` by its cluster
+    pub fn estimated_devices(&self, unique_macs: usize) -> usize {
+        unique_macs.saturating_sub(self.clustered_macs()) + self.clusters.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MAC_A: MacAddress = [0xda, 0xa1, 0x19, 0x2c, 0x31, 0x5e];
+    const MAC_B: MacAddress = [0x3e, 0x07, 0x88, 0x10, 0x42, 0x9c];
+    const MAC_C: MacAddress = [0x7a, 0x55, 0x0d, 0xe3, 0x21, 0x04];
+
+    fn probe(ssid: &[u8], channel: u8, vendor: [u8; 4], vendor_payload: &[u8]) -> Vec<u8> {
+        let mut ies = vec![IE_SSID, ssid.len() as u8];
+        ies.extend_from_slice(ssid);
+        ies.extend_from_slice(&[IE_SUPPORTED_RATES, 4, 0x82, 0x84, 0x8b, 0x96]);
+        ies.extend_from_slice(&[IE_DS_PARAMETER_SET, 1, channel]);
+        ies.extend_from_slice(&[IE_HT_CAPABILITIES, 2, 0x2d, 0x01]);
+        ies.extend_from_slice(&[IE_VENDOR_SPECIFIC, 4 + vendor_payload.len() as u8]);
+        ies.extend_from_slice(&vendor);
+        ies.extend_from_slice(vendor_payload);
+        ies
+    }
+
+    const WPS: [u8; 4] = [0x00, 0x50, 0xf2, 0x04];
+
+    #[test]
+    fn fingerprint_ignores_per_probe_fields() {
+        let base = probe_fingerprint(&probe(b"home", 1, WPS, &[0x10, 0x47, 0xaa]));
+        assert_eq!(probe_fingerprint(&probe(b"", 11, WPS, &[0x10, 0x47, 0xbb, 0xcc])), base);
+        // Another vendor type or OUI is another driver
+        assert_ne!(probe_fingerprint(&probe(b"home", 1, [0x00, 0x50, 0xf2, 0x08], &[0x10, 0x47, 0xaa])), base);
+        assert_ne!(probe_fingerprint(&probe(b"home", 1, [0x00, 0x17, 0xf2, 0x04], &[0x10, 0x47, 0xaa])), base);
+        let mut other_rates = probe(b"home", 1, WPS, &[0x10, 0x47, 0xaa]);
+        other_rates[8] = 0x0c;
+        assert_ne!(probe_fingerprint(&other_rates), base);
+    }
+
+    #[test]
+    fn continued_sequence_merges() {
+        let mut clusterer = DeviceClusterer::new();
+        clusterer.observe_probe(MAC_A, 1, 100, 0);
+        // The same MAC only moves its cluster on
+        clusterer.observe_probe(MAC_A, 1, 110, 1_000);
+        clusterer.observe_probe(MAC_B, 1, 110 + MAX_SEQUENCE_GAP, 2_000);
+        assert_eq!(clusterer.clusters().len(), 1);
+        assert_eq!(clusterer.clusters()[0].macs, [MAC_A, MAC_B]);
+
+        // Same sequence, other fingerprint
+        clusterer.observe_probe(MAC_C, 2, 180, 3_000);
+        assert_eq!(clusterer.clusters().len(), 2);
+    }
+
+    #[test]
+    fn gaps_wraparound_and_idle_clusters() {
+        let merges = |last_sequence: u16, sequence: u16, idle_ms: u32| {
+            let mut clusterer = DeviceClusterer::new();
+            clusterer.observe_probe(MAC_A, 1, last_sequence, 1_000);
+            clusterer.observe_probe(MAC_B, 1, sequence, 1_000 + idle_ms);
+            clusterer.clusters().len() == 1
+        };
+        assert!(!merges(100, 100 + MAX_SEQUENCE_GAP + 1, 0));
+        // The counter is 12 bits, so 4090 is followed by 0
+        assert!(merges(4090, 10, 0));
+        assert!(!merges(4090, 4090 - 5, 0));
+        assert!(merges(100, 101, MAX_CLUSTER_IDLE_MS));
+        assert!(!merges(100, 101, MAX_CLUSTER_IDLE_MS + 1));
+    }
+
+    #[test]
+    fn estimates_devices() {
+        let mut clusterer = DeviceClusterer::new();
+        clusterer.observe_probe(MAC_A, 1, 100, 0);
+        clusterer.observe_probe(MAC_B, 1, 104, 500);
+        clusterer.observe_probe(MAC_C, 1, 2_000, 1_000);
+        assert_eq!(clusterer.clustered_macs(), 3);
+        // 7 universal MACs plus three randomized ones from two devices
+        assert_eq!(clusterer.estimated_devices(10), 9);
+        assert_eq!(DeviceClusterer::new().estimated_devices(10), 10);
+    }
+}
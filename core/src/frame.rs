@@ -0,0 +1,440 @@
+// 802.11 MAC header parsing
+// Kept free of esp-idf types so it can be built and checked on the host.
+
+pub type MacAddress = [u8; 6];
+
+// Length of the frame check sequence the radio appends to every captured frame
+pub const FCS_LEN: usize = 4;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FrameType {
+    Management,
+    Control,
+    Data,
+    Extension,
+}
+
+impl FrameType {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            FrameType::Management => "Management",
+            FrameType::Control => "Control",
+            FrameType::Data => "Data",
+            FrameType::Extension => "Extension",
+        }
+    }
+}
+
+// Control frame subtypes that only carry a receiver address (10 byte header)
+const CTRL_SUBTYPE_WRAPPER: u8 = 0x7;
+const CTRL_SUBTYPE_PS_POLL: u8 = 0xA;
+const CTRL_SUBTYPE_CTS: u8 = 0xC;
+const CTRL_SUBTYPE_ACK: u8 = 0xD;
+const CTRL_SUBTYPE_CF_END: u8 = 0xE;
+const CTRL_SUBTYPE_CF_END_ACK: u8 = 0xF;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FrameControl(pub u16);
+
+impl FrameControl {
+    pub fn protocol_version(&self) -> u8 {
+        (self.0 & 0x0003) as u8
+    }
+
+    pub fn frame_type(&self) -> FrameType {
+        match (self.0 & 0x000C) >> 2 {
+            0 => FrameType::Management,
+            1 => FrameType::Control,
+            2 => FrameType::Data,
+            _ => FrameType::Extension,
+        }
+    }
+
+    pub fn subtype(&self) -> u8 {
+        ((self.0 & 0x00F0) >> 4) as u8
+    }
+
+    pub fn to_ds(&self) -> bool {
+        self.0 & 0x0100 != 0
+    }
+
+    pub fn from_ds(&self) -> bool {
+        self.0 & 0x0200 != 0
+    }
+
+    pub fn more_fragments(&self) -> bool {
+        self.0 & 0x0400 != 0
+    }
+
+    pub fn retry(&self) -> bool {
+        self.0 & 0x0800 != 0
+    }
+
+    pub fn power_management(&self) -> bool {
+        self.0 & 0x1000 != 0
+    }
+
+    pub fn more_data(&self) -> bool {
+        self.0 & 0x2000 != 0
+    }
+
+    pub fn protected(&self) -> bool {
+        self.0 & 0x4000 != 0
+    }
+
+    // +HTC/Order bit; signals an HT Control field on QoS data and management frames
+    pub fn order(&self) -> bool {
+        self.0 & 0x8000 != 0
+    }
+
+    // QoS data subtypes have bit 3 of the subtype set
+    pub fn is_qos_data(&self) -> bool {
+        self.frame_type() == FrameType::Data && self.subtype() & 0x8 != 0
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SequenceControl(pub u16);
+
+impl SequenceControl {
+    pub fn fragment_number(&self) -> u8 {
+        (self.0 & 0x000F) as u8
+    }
+
+    pub fn sequence_number(&self) -> u16 {
+        self.0 >> 4
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FrameError {
+    TooShort { needed: usize, len: usize },
+    UnsupportedVersion(u8),
+}
+
+impl core::fmt::Display for FrameError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            FrameError::TooShort { needed, len } => write!(f, "frame too short: need {} bytes, got {}", needed, len),
+            FrameError::UnsupportedVersion(v) => write!(f, "unsupported protocol version {}", v),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MacHeader {
+    pub frame_control: FrameControl,
+    pub duration: u16,
+    pub addr1: MacAddress,
+    pub addr2: Option<MacAddress>,
+    pub addr3: Option<MacAddress>,
+    pub sequence_control: Option<SequenceControl>,
+    pub addr4: Option<MacAddress>,
+    pub qos_control: Option<u16>,
+    pub ht_control: Option<u32>,
+    // Number of bytes the header occupies; the frame body starts here
+    pub header_len: usize,
+}
+
+fn read_u16(buf: &[u8], offset: usize) -> u16 {
+    u16::from_le_bytes([buf[offset], buf[offset + 1]])
+}
+
+fn read_u32(buf: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes([buf[offset], buf[offset + 1], buf[offset + 2], buf[offset + 3]])
+}
+
+fn read_mac(buf: &[u8], offset: usize) -> MacAddress {
+    let mut mac = [0u8; 6];
+    mac.copy_from_slice(&buf[offset..offset + 6]);
+    mac
+}
+
+fn ensure_len(buf: &[u8], needed: usize) -> Result<(), FrameError> {
+    if buf.len() < needed {
+        return Err(FrameError::TooShort { needed, len: buf.len() });
+    }
+    Ok(())
+}
+
+// Parse the MAC header at the start of `buf`. `buf` must be trimmed to the real
+// frame length (see `frame_len_from_sig_len`) so that short control frames are not
+// read past their end.
+pub fn parse_header(buf: &[u8]) -> Result<MacHeader, FrameError> {
+    // Frame control + duration + address 1 is the smallest valid header (ACK/CTS)
+    ensure_len(buf, 10)?;
+
+    let frame_control = FrameControl(read_u16(buf, 0));
+    if frame_control.protocol_version() != 0 {
+        return Err(FrameError::UnsupportedVersion(frame_control.protocol_version()));
+    }
+
+    let mut header = MacHeader {
+        frame_control,
+        duration: read_u16(buf, 2),
+        addr1: read_mac(buf, 4),
+        addr2: None,
+        addr3: None,
+        sequence_control: None,
+        addr4: None,
+        qos_control: None,
+        ht_control: None,
+        header_len: 10,
+    };
+
+    match frame_control.frame_type() {
+        FrameType::Control => {
+            match frame_control.subtype() {
+                CTRL_SUBTYPE_CTS | CTRL_SUBTYPE_ACK | CTRL_SUBTYPE_WRAPPER => {},
+                _ => {
+                    ensure_len(buf, 16)?;
+                    header.addr2 = Some(read_mac(buf, 10));
+                    header.header_len = 16;
+                }
+            }
+        },
+        FrameType::Management | FrameType::Data => {
+            ensure_len(buf, 24)?;
+            header.addr2 = Some(read_mac(buf, 10));
+            header.addr3 = Some(read_mac(buf, 16));
+            header.sequence_control = Some(SequenceControl(read_u16(buf, 22)));
+            let mut offset = 24;
+
+            if frame_control.frame_type() == FrameType::Data {
+                if frame_control.to_ds() && frame_control.from_ds() {
+                    ensure_len(buf, offset + 6)?;
+                    header.addr4 = Some(read_mac(buf, offset));
+                    offset += 6;
+                }
+                if frame_control.is_qos_data() {
+                    ensure_len(buf, offset + 2)?;
+                    header.qos_control = Some(read_u16(buf, offset));
+                    offset += 2;
+                }
+            }
+
+            // HT Control is present on QoS data and management frames with the order bit set
+            let has_ht_control = frame_control.order()
+                && (frame_control.is_qos_data() || frame_control.frame_type() == FrameType::Management);
+            if has_ht_control {
+                ensure_len(buf, offset + 4)?;
+                header.ht_control = Some(read_u32(buf, offset));
+                offset += 4;
+            }
+
+            header.header_len = offset;
+        },
+        FrameType::Extension => {
+            // DMG beacons and friends; only address 1 is common to all of them
+        },
+    }
+
+    Ok(header)
+}
+
+// The driver reports the signal length including the trailing FCS
+pub fn frame_len_from_sig_len(sig_len: usize) -> usize {
+    sig_len.saturating_sub(FCS_LEN)
+}
+
+impl MacHeader {
+    pub fn frame_type(&self) -> FrameType {
+        self.frame_control.frame_type()
+    }
+
+    pub fn subtype(&self) -> u8 {
+        self.frame_control.subtype()
+    }
+
+    // Traffic identifier (user priority) from the QoS control field
+    pub fn tid(&self) -> Option<u8> {
+        self.qos_control.map(|qos| (qos & 0x000F) as u8)
+    }
+
+    // RA: the station that should receive this frame over the air
+    pub fn receiver(&self) -> MacAddress {
+        self.addr1
+    }
+
+    // TA: the station that put this frame on the air
+    pub fn transmitter(&self) -> Option<MacAddress> {
+        self.addr2
+    }
+
+    // SA: the station that originated the MSDU
+    pub fn source(&self) -> Option<MacAddress> {
+        match self.frame_type() {
+            FrameType::Data => match (self.frame_control.to_ds(), self.frame_control.from_ds()) {
+                (false, false) | (true, false) => self.addr2,
+                (false, true) => self.addr3,
+                (true, true) => self.addr4,
+            },
+            FrameType::Management => self.addr2,
+            _ => None,
+        }
+    }
+
+    // DA: the final recipient of the MSDU
+    pub fn destination(&self) -> Option<MacAddress> {
+        match self.frame_type() {
+            FrameType::Data => match (self.frame_control.to_ds(), self.frame_control.from_ds()) {
+                (false, false) | (false, true) => Some(self.addr1),
+                (true, false) | (true, true) => self.addr3,
+            },
+            FrameType::Management => Some(self.addr1),
+            _ => None,
+        }
+    }
+
+    pub fn bssid(&self) -> Option<MacAddress> {
+        match self.frame_type() {
+            FrameType::Data => match (self.frame_control.to_ds(), self.frame_control.from_ds()) {
+                (false, false) => self.addr3,
+                (false, true) => self.addr2,
+                (true, false) => Some(self.addr1),
+                // WDS/mesh frames do not carry a BSSID
+                (true, true) => None,
+            },
+            FrameType::Management => self.addr3,
+            FrameType::Control => match self.subtype() {
+                CTRL_SUBTYPE_PS_POLL => Some(self.addr1),
+                CTRL_SUBTYPE_CF_END | CTRL_SUBTYPE_CF_END_ACK => self.addr2,
+                _ => None,
+            },
+            FrameType::Extension => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::{string::String, vec, vec::Vec};
+
+    #[test]
+    fn control_frame_has_no_transmitter() {
+        // ACK: frame control, duration, receiver
+        let frame = [0xd4, 0x00, 0, 0, 1, 2, 3, 4, 5, 6];
+        let header = parse_header(&frame).unwrap();
+        assert_eq!(header.frame_type(), FrameType::Control);
+        assert_eq!(header.transmitter(), None);
+        assert_eq!(header.header_len, 10);
+    }
+
+    #[test]
+    fn qos_data_to_ds() {
+        let mut frame = vec![0x88, 0x01, 0, 0];
+        frame.extend([1; 6]);
+        frame.extend([2; 6]);
+        frame.extend([3; 6]);
+        frame.extend([0x10, 0]);
+        frame.extend([5, 0]);
+        let header = parse_header(&frame).unwrap();
+        assert_eq!(header.bssid(), Some([1; 6]));
+        assert_eq!(header.source(), Some([2; 6]));
+        assert_eq!(header.destination(), Some([3; 6]));
+        assert_eq!(header.qos_control, Some(5));
+        assert_eq!(header.sequence_control.unwrap().sequence_number(), 1);
+        assert_eq!(header.header_len, 26);
+    }
+
+    // Frames as the radio hands them over, FCS already trimmed off
+    const BEACON: &str = "80000000ffffffffffff00146c7e408000146c7e4080702b\
+        8d1a4e0b000000006400110400066f6666696365010882848b960c12182403010b";
+    const PROBE_REQUEST: &str = "40000000ffffffffffffdaa1192c315effffffffffff904e\
+        0000010402040b1632080c1218243048606c";
+    const ACK: &str = "d400000000146c7e4080";
+    const CTS: &str = "c400b600dca6320a1b2c";
+    // To the AP, protected, TID 6, followed by the CCMP header
+    const QOS_DATA: &str = "88412c0000146c7e4080dca6320a1b2c01005e0000fb601f\
+        06002a0000200000000000";
+    // WDS/mesh: both DS bits, third fragment with more to follow, TID 5
+    const QOS_DATA_4ADDR: &str = "88070000f4f26d010203f4f26d040506dca6320a1b2ca312\
+        3c5ab4aabbcc0500aaaa030000000800";
+
+    fn hex(text: &str) -> Vec<u8> {
+        let text: String = text.split_whitespace().collect();
+        (0..text.len()).step_by(2).map(|idx| u8::from_str_radix(&text[idx..idx + 2], 16).unwrap()).collect()
+    }
+
+    fn address_count(header: &MacHeader) -> usize {
+        1 + [header.addr2, header.addr3, header.addr4].iter().filter(|addr| addr.is_some()).count()
+    }
+
+    #[test]
+    fn captured_beacon() {
+        let header = parse_header(&hex(BEACON)).unwrap();
+        let fc = header.frame_control;
+        assert_eq!((fc.protocol_version(), fc.frame_type(), fc.subtype()), (0, FrameType::Management, 8));
+        assert!(!fc.to_ds() && !fc.from_ds() && !fc.retry() && !fc.protected());
+        assert_eq!(address_count(&header), 3);
+        assert_eq!(header.receiver(), [0xff; 6]);
+        assert_eq!(header.transmitter(), Some([0x00, 0x14, 0x6c, 0x7e, 0x40, 0x80]));
+        assert_eq!(header.bssid(), header.transmitter());
+        let seq = header.sequence_control.unwrap();
+        assert_eq!((seq.sequence_number(), seq.fragment_number()), (0x2b7, 0));
+        assert_eq!((header.tid(), header.header_len), (None, 24));
+    }
+
+    #[test]
+    fn captured_probe_request() {
+        let header = parse_header(&hex(PROBE_REQUEST)).unwrap();
+        assert_eq!((header.frame_type(), header.subtype()), (FrameType::Management, 4));
+        assert_eq!(address_count(&header), 3);
+        assert_eq!(header.source(), Some([0xda, 0xa1, 0x19, 0x2c, 0x31, 0x5e]));
+        assert_eq!(header.destination(), Some([0xff; 6]));
+        assert_eq!(header.bssid(), Some([0xff; 6]));
+        let seq = header.sequence_control.unwrap();
+        assert_eq!((seq.sequence_number(), seq.fragment_number()), (0x4e9, 0));
+        assert_eq!(header.header_len, 24);
+    }
+
+    #[test]
+    fn captured_short_control_frames() {
+        for (frame, subtype, duration) in [(ACK, CTRL_SUBTYPE_ACK, 0), (CTS, CTRL_SUBTYPE_CTS, 0xb6)] {
+            let frame = hex(frame);
+            assert_eq!(frame.len(), 10);
+            let header = parse_header(&frame).unwrap();
+            assert_eq!((header.frame_type(), header.subtype(), header.duration), (FrameType::Control, subtype, duration));
+            assert_eq!(address_count(&header), 1);
+            assert_eq!((header.transmitter(), header.bssid(), header.sequence_control), (None, None, None));
+            assert_eq!(header.header_len, 10);
+        }
+    }
+
+    #[test]
+    fn captured_qos_data() {
+        let header = parse_header(&hex(QOS_DATA)).unwrap();
+        let fc = header.frame_control;
+        assert_eq!((fc.frame_type(), fc.subtype()), (FrameType::Data, 8));
+        assert!(fc.is_qos_data() && fc.to_ds() && !fc.from_ds() && fc.protected());
+        assert_eq!(address_count(&header), 3);
+        assert_eq!(header.bssid(), Some([0x00, 0x14, 0x6c, 0x7e, 0x40, 0x80]));
+        assert_eq!(header.source(), Some([0xdc, 0xa6, 0x32, 0x0a, 0x1b, 0x2c]));
+        assert_eq!(header.destination(), Some([0x01, 0x00, 0x5e, 0x00, 0x00, 0xfb]));
+        let seq = header.sequence_control.unwrap();
+        assert_eq!((seq.sequence_number(), seq.fragment_number()), (0x1f6, 0));
+        assert_eq!((header.tid(), header.header_len), (Some(6), 26));
+    }
+
+    #[test]
+    fn captured_four_address_qos_data() {
+        let header = parse_header(&hex(QOS_DATA_4ADDR)).unwrap();
+        let fc = header.frame_control;
+        assert!(fc.is_qos_data() && fc.to_ds() && fc.from_ds() && fc.more_fragments());
+        assert_eq!(address_count(&header), 4);
+        assert_eq!(header.receiver(), [0xf4, 0xf2, 0x6d, 0x01, 0x02, 0x03]);
+        assert_eq!(header.transmitter(), Some([0xf4, 0xf2, 0x6d, 0x04, 0x05, 0x06]));
+        assert_eq!(header.destination(), Some([0xdc, 0xa6, 0x32, 0x0a, 0x1b, 0x2c]));
+        assert_eq!(header.source(), Some([0x3c, 0x5a, 0xb4, 0xaa, 0xbb, 0xcc]));
+        assert_eq!(header.bssid(), None);
+        let seq = header.sequence_control.unwrap();
+        assert_eq!((seq.sequence_number(), seq.fragment_number()), (0x12a, 3));
+        assert_eq!((header.tid(), header.header_len), (Some(5), 32));
+    }
+
+    #[test]
+    fn truncated_header() {
+        assert!(parse_header(&[0x88, 0x01, 0, 0, 1, 2, 3]).is_err());
+    }
+}
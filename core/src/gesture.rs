@@ -0,0 +1,100 @@
+// Decodes presses of the PRG button into short and long presses
+// Fed with the button level and a millisecond clock, from an interrupt on every edge or from
+// polling. A long press is reported as soon as the button has been held long enough, without
+// waiting for the release.
+
+// Long press duration in milliseconds
+pub const LONG_PRESS_DURATION_MS: u32 = 2000;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ButtonEvent {
+    None,
+    ShortPress,
+    LongPress,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum State {
+    Released,
+    Pressed { since_ms: u32 },
+    // The long press was reported; the release that ends it is not a press of its own
+    LongPressed,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct GestureDecoder {
+    state: State,
+}
+
+impl Default for GestureDecoder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl GestureDecoder {
+    pub const fn new() -> Self {
+        GestureDecoder { state: State::Released }
+    }
+
+    pub fn is_pressed(&self) -> bool {
+        self.state != State::Released
+    }
+
+    pub fn update(&mut self, pressed: bool, now_ms: u32) -> ButtonEvent {
+        match (self.state, pressed) {
+            (State::Released, true) => {
+                self.state = State::Pressed { since_ms: now_ms };
+                ButtonEvent::None
+            },
+            (State::Pressed { since_ms }, true) if now_ms.wrapping_sub(since_ms) >= LONG_PRESS_DURATION_MS => {
+                self.state = State::LongPressed;
+                ButtonEvent::LongPress
+            },
+            (State::Pressed { since_ms }, false) => {
+                self.state = State::Released;
+                if now_ms.wrapping_sub(since_ms) >= LONG_PRESS_DURATION_MS {
+                    ButtonEvent::LongPress
+                } else {
+                    ButtonEvent::ShortPress
+                }
+            },
+            (State::LongPressed, false) => {
+                self.state = State::Released;
+                ButtonEvent::None
+            },
+            _ => ButtonEvent::None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn short_and_long_presses() {
+        let mut decoder = GestureDecoder::new();
+        assert_eq!(decoder.update(true, 100), ButtonEvent::None);
+        assert_eq!(decoder.update(true, 500), ButtonEvent::None);
+        assert_eq!(decoder.update(false, 600), ButtonEvent::ShortPress);
+        assert!(!decoder.is_pressed());
+
+        // Reported while still held, and only once
+        assert_eq!(decoder.update(true, 1000), ButtonEvent::None);
+        assert_eq!(decoder.update(true, 3000), ButtonEvent::LongPress);
+        assert_eq!(decoder.update(true, 4000), ButtonEvent::None);
+        assert_eq!(decoder.update(false, 4100), ButtonEvent::None);
+
+        // Edges only, as from the interrupt
+        assert_eq!(decoder.update(true, 5000), ButtonEvent::None);
+        assert_eq!(decoder.update(false, 7500), ButtonEvent::LongPress);
+    }
+
+    #[test]
+    fn clock_wraps() {
+        let mut decoder = GestureDecoder::new();
+        decoder.update(true, u32::MAX - 100);
+        assert_eq!(decoder.update(false, 200), ButtonEvent::ShortPress);
+    }
+}
@@ -0,0 +1,70 @@
+// What the modes need from the board
+// The firmware implements these on top of esp-idf (promiscuous Wi-Fi, SPIFFS, the SSD1306 and
+// the PRG button); sim.rs has in-memory versions so whole workflows run in host tests.
+
+use alloc::{string::String, vec::Vec};
+
+use anyhow::Result;
+
+use crate::gesture::ButtonEvent;
+use crate::observation::Observation;
+use crate::settings::Brightness;
+
+// Received frames, queued until the mode drains them. Dropping the source ends reception.
+pub trait PacketSource {
+    fn next_observation(&mut self) -> Option<Observation>;
+    fn set_channel(&mut self, channel: u8) -> Result<()>;
+    // Observations lost because the queue was full
+    fn dropped(&self) -> u32;
+}
+
+// A flat file store; names have no directories
+pub trait Storage {
+    fn list(&self) -> Result<Vec<String>>;
+    fn size(&self, name: &str) -> Result<usize>;
+    fn read(&self, name: &str) -> Result<Vec<u8>>;
+    // Creates the file, replacing one that already exists
+    fn write(&mut self, name: &str, data: &[u8]) -> Result<()>;
+    fn append(&mut self, name: &str, data: &[u8]) -> Result<()>;
+    fn delete(&mut self, name: &str) -> Result<()>;
+    // (total, used) bytes
+    fn space(&self) -> Result<(usize, usize)>;
+
+    fn has_enough_space(&self, needed_bytes: usize) -> Result<bool> {
+        let (total, used) = self.space()?;
+        Ok(total.saturating_sub(used) >= needed_bytes)
+    }
+}
+
+// A 128x64 monochrome screen; nothing shows until `flush`
+pub trait Screen {
+    fn clear(&mut self) -> Result<()>;
+    // `color` false draws dark text, for use on a filled rectangle
+    fn draw_text(&mut self, x: i32, y: i32, text: &str, color: bool) -> Result<()>;
+    fn draw_rect(&mut self, x: i32, y: i32, width: i32, height: i32, color: bool) -> Result<()>;
+    fn fill_rect(&mut self, x: i32, y: i32, width: i32, height: i32, color: bool) -> Result<()>;
+    fn flush(&mut self) -> Result<()>;
+    fn set_brightness(&mut self, brightness: Brightness) -> Result<()>;
+}
+
+// The single button
+pub trait InputDevice {
+    // The press completed since the last call, `ButtonEvent::None` when there was none
+    fn poll(&mut self) -> ButtonEvent;
+}
+
+pub trait Clock {
+    // Milliseconds since boot
+    fn now_ms(&self) -> u64;
+    // Wall clock time; starts at the epoch unless something set it
+    fn unix_time_us(&self) -> u64;
+    fn delay_ms(&self, ms: u32);
+}
+
+// The parts every mode uses. The radio and storage are only brought up by the modes that need
+// them, so they are passed separately.
+pub struct Board<S, I, C> {
+    pub screen: S,
+    pub input: I,
+    pub clock: C,
+}
@@ -0,0 +1,108 @@
+// 802.11 information element (tag/length/value) parsing for management frame bodies
+
+pub const IE_SSID: u8 = 0;
+pub const IE_SUPPORTED_RATES: u8 = 1;
+pub const IE_DS_PARAMETER_SET: u8 = 3;
+pub const IE_COUNTRY: u8 = 7;
+pub const IE_EXTENDED_SUPPORTED_RATES: u8 = 50;
+pub const IE_HT_CAPABILITIES: u8 = 45;
+pub const IE_RSN: u8 = 48;
+pub const IE_EXTENDED_CAPABILITIES: u8 = 127;
+pub const IE_VHT_CAPABILITIES: u8 = 191;
+pub const IE_VENDOR_SPECIFIC: u8 = 221;
+pub const IE_EXTENSION: u8 = 255;
+// Element ID extensions
+pub const EXT_HE_CAPABILITIES: u8 = 35;
+
+// Management subtypes whose body is made of information elements
+pub const SUBTYPE_PROBE_REQUEST: u8 = 4;
+pub const SUBTYPE_PROBE_RESPONSE: u8 = 5;
+pub const SUBTYPE_BEACON: u8 = 8;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct InformationElement<'a> {
+    pub id: u8,
+    pub data: &'a [u8],
+}
+
+impl<'a> InformationElement<'a> {
+    // Element ID extension (IE 255) carries its real ID in the first data byte
+    pub fn extension_id(&self) -> Option<u8> {
+        if self.id == IE_EXTENSION {
+            self.data.first().copied()
+        } else {
+            None
+        }
+    }
+
+    // OUI and vendor type of a vendor specific element
+    pub fn vendor_oui(&self) -> Option<([u8; 3], u8)> {
+        if self.id != IE_VENDOR_SPECIFIC || self.data.len() < 4 {
+            return None;
+        }
+        Some(([self.data[0], self.data[1], self.data[2]], self.data[3]))
+    }
+}
+
+// Iterates the elements in `buf`, stopping quietly at the first element that does not fit
+// (captured bodies are truncated to a fixed size)
+pub struct InformationElements<'a> {
+    buf: &'a [u8],
+}
+
+impl<'a> Iterator for InformationElements<'a> {
+    type Item = InformationElement<'a>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.buf.len() < 2 {
+            return None;
+        }
+        let id = self.buf[0];
+        let len = self.buf[1] as usize;
+        if self.buf.len() < 2 + len {
+            self.buf = &[];
+            return None;
+        }
+        let data = &self.buf[2..2 + len];
+        self.buf = &self.buf[2 + len..];
+        Some(InformationElement { id, data })
+    }
+}
+
+pub fn elements(buf: &[u8]) -> InformationElements<'_> {
+    InformationElements { buf }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::vec::Vec;
+
+    #[test]
+    fn parses_elements() {
+        let body = [
+            IE_SSID, 3, b'a', b'b', b'c',
+            IE_EXTENSION, 2, EXT_HE_CAPABILITIES, 0x01,
+            IE_VENDOR_SPECIFIC, 5, 0x00, 0x50, 0xf2, 0x04, 0x10,
+            IE_VENDOR_SPECIFIC, 3, 0x00, 0x50, 0xf2,
+        ];
+        let elements: Vec<_> = elements(&body).collect();
+        assert_eq!(elements.len(), 4);
+        assert_eq!(elements[0], InformationElement { id: IE_SSID, data: b"abc" });
+        assert_eq!((elements[0].extension_id(), elements[1].extension_id()), (None, Some(EXT_HE_CAPABILITIES)));
+        assert_eq!(elements[2].vendor_oui(), Some(([0x00, 0x50, 0xf2], 0x04)));
+        // Too short to carry a vendor type
+        assert_eq!(elements[3].vendor_oui(), None);
+    }
+
+    #[test]
+    fn stops_at_truncated_element() {
+        // The rates element claims 8 bytes but the capture ends after 2
+        let body = [IE_SSID, 0, IE_SUPPORTED_RATES, 8, 0x82, 0x84];
+        let ids: Vec<u8> = elements(&body).map(|element| element.id).collect();
+        assert_eq!(ids, [IE_SSID]);
+        // A lone tag byte without its length
+        assert_eq!(elements(&[IE_SSID, 0, IE_HT_CAPABILITIES]).count(), 1);
+        assert_eq!(elements(&[]).count(), 0);
+    }
+}
@@ -0,0 +1,41 @@
+// Everything in mac_sniff that does not touch the hardware: 802.11 frame parsing, per-device
+// statistics, the scan file and capture formats, the dump protocol, the menu and button state
+// machines, and the scan, capture and dump modes themselves, which reach the board through the
+// traits in hal.rs. It only needs `alloc`, so it builds for the ESP32-S3 firmware and is tested
+// on the host with `cargo test`, where sim.rs (the `sim` feature) stands in for the board.
+
+#![no_std]
+
+extern crate alloc;
+#[cfg(test)]
+extern crate std;
+
+pub mod ap;
+pub mod app;
+pub mod association;
+pub mod capture;
+pub mod channel_hop;
+pub mod dump;
+pub mod dump_protocol;
+pub mod fingerprint;
+pub mod frame;
+pub mod gesture;
+pub mod hal;
+pub mod ie;
+pub mod mac_addr;
+pub mod menu;
+pub mod observation;
+pub mod oui;
+pub mod pcap;
+pub mod probe_ssid;
+pub mod ring;
+pub mod scan;
+pub mod scan_duration;
+pub mod scan_file;
+pub mod scan_profile;
+pub mod schedule;
+pub mod settings;
+#[cfg(any(test, feature = "sim"))]
+pub mod sim;
+pub mod stats;
+pub mod ui;
@@ -0,0 +1,198 @@
+// MAC address classification and the policy for which addresses count as devices
+
+use crate::frame::MacAddress;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AddressKind {
+    // 00:00:00:00:00:00, seen in malformed or placeholder frames
+    Null,
+    Broadcast,
+    // Group address (I/G bit set), e.g. 01:00:5e IPv4 or 33:33 IPv6 multicast
+    Multicast,
+    Unicast,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AddressClass {
+    pub kind: AddressKind,
+    // U/L bit set: randomized or otherwise locally assigned rather than an IEEE OUI
+    pub locally_administered: bool,
+}
+
+pub fn classify(mac: &MacAddress) -> AddressClass {
+    let kind = if mac.iter().all(|b| *b == 0x00) {
+        AddressKind::Null
+    } else if mac.iter().all(|b| *b == 0xFF) {
+        AddressKind::Broadcast
+    } else if mac[0] & 0x01 != 0 {
+        AddressKind::Multicast
+    } else {
+        AddressKind::Unicast
+    };
+
+    AddressClass {
+        kind,
+        locally_administered: mac[0] & 0x02 != 0,
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CountPolicy {
+    pub unicast_universal: bool,
+    pub unicast_local: bool,
+    pub multicast: bool,
+    pub broadcast: bool,
+    pub null: bool,
+}
+
+impl CountPolicy {
+    // Only unicast addresses can belong to a single device
+    pub const DEVICES: CountPolicy = CountPolicy {
+        unicast_universal: true,
+        unicast_local: true,
+        multicast: false,
+        broadcast: false,
+        null: false,
+    };
+
+    // Leaves out randomized and other locally administered addresses, which can make one
+    // device show up many times
+    pub const UNIVERSAL: CountPolicy = CountPolicy {
+        unicast_universal: true,
+        unicast_local: false,
+        multicast: false,
+        broadcast: false,
+        null: false,
+    };
+
+    // Every address seen, as older firmware counted them
+    pub const EVERY_ADDRESS: CountPolicy = CountPolicy {
+        unicast_universal: true,
+        unicast_local: true,
+        multicast: true,
+        broadcast: true,
+        null: true,
+    };
+
+    // The policies that can be picked in the settings
+    pub const ALL: [CountPolicy; 3] = [CountPolicy::DEVICES, CountPolicy::UNIVERSAL, CountPolicy::EVERY_ADDRESS];
+
+    pub fn label(&self) -> &'static str {
+        match Self::ALL.iter().position(|policy| policy == self) {
+            Some(0) => "Unicast",
+            Some(1) => "Universal",
+            Some(2) => "All",
+            _ => "Custom",
+        }
+    }
+
+    pub fn next(&self) -> CountPolicy {
+        let idx = Self::ALL.iter().position(|policy| policy == self).unwrap_or(0);
+        Self::ALL[(idx + 1) % Self::ALL.len()]
+    }
+
+    pub fn id(&self) -> u8 {
+        Self::ALL.iter().position(|policy| policy == self).unwrap_or(0) as u8
+    }
+
+    pub fn from_id(id: u8) -> Option<CountPolicy> {
+        Self::ALL.get(id as usize).copied()
+    }
+
+    pub fn counts(&self, class: &AddressClass) -> bool {
+        match class.kind {
+            AddressKind::Null => self.null,
+            AddressKind::Broadcast => self.broadcast,
+            AddressKind::Multicast => self.multicast,
+            AddressKind::Unicast if class.locally_administered => self.unicast_local,
+            AddressKind::Unicast => self.unicast_universal,
+        }
+    }
+
+    pub fn counts_mac(&self, mac: &MacAddress) -> bool {
+        self.counts(&classify(mac))
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct AddressSummary {
+    // Addresses accepted by the count policy
+    pub devices: usize,
+    pub unicast_universal: usize,
+    pub unicast_local: usize,
+    pub multicast: usize,
+    pub broadcast: usize,
+    pub null: usize,
+}
+
+impl AddressSummary {
+    pub fn from_macs<'a>(macs: impl IntoIterator<Item = &'a MacAddress>, policy: &CountPolicy) -> Self {
+        let mut summary = AddressSummary::default();
+        for mac in macs {
+            let class = classify(mac);
+            match class.kind {
+                AddressKind::Null => summary.null += 1,
+                AddressKind::Broadcast => summary.broadcast += 1,
+                AddressKind::Multicast => summary.multicast += 1,
+                AddressKind::Unicast if class.locally_administered => summary.unicast_local += 1,
+                AddressKind::Unicast => summary.unicast_universal += 1,
+            }
+            if policy.counts(&class) {
+                summary.devices += 1;
+            }
+        }
+        summary
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn kind(mac: MacAddress) -> (AddressKind, bool) {
+        let class = classify(&mac);
+        (class.kind, class.locally_administered)
+    }
+
+    #[test]
+    fn classifies_addresses() {
+        assert_eq!(kind([0x00, 0x14, 0x6c, 0x7e, 0x40, 0x80]), (AddressKind::Unicast, false));
+        // Randomized: the U/L bit is set in the first octet (x2, x6, xA, xE)
+        for first in [0x02, 0x3e, 0xda, 0xfa] {
+            assert_eq!(kind([first, 0xa1, 0x19, 0x2c, 0x31, 0x5e]), (AddressKind::Unicast, true), "{:02x}", first);
+        }
+        // IPv4 and IPv6 multicast, spanning tree
+        assert_eq!(kind([0x01, 0x00, 0x5e, 0x00, 0x00, 0xfb]), (AddressKind::Multicast, false));
+        assert_eq!(kind([0x33, 0x33, 0x00, 0x00, 0x00, 0x01]), (AddressKind::Multicast, true));
+        assert_eq!(kind([0x01, 0x80, 0xc2, 0x00, 0x00, 0x00]), (AddressKind::Multicast, false));
+        assert_eq!(kind([0xff; 6]), (AddressKind::Broadcast, true));
+        assert_eq!(kind([0x00; 6]), (AddressKind::Null, false));
+        // Only a fully set address is broadcast
+        assert_eq!(kind([0xff, 0xff, 0xff, 0xff, 0xff, 0xfe]).0, AddressKind::Multicast);
+    }
+
+    #[test]
+    fn policies_pick_classes() {
+        let macs = [
+            [0x00, 0x14, 0x6c, 0x7e, 0x40, 0x80],
+            [0xda, 0xa1, 0x19, 0x2c, 0x31, 0x5e],
+            [0x01, 0x00, 0x5e, 0x00, 0x00, 0xfb],
+            [0xff; 6],
+            [0x00; 6],
+        ];
+        let counted = |policy: CountPolicy| macs.iter().filter(|mac| policy.counts_mac(mac)).count();
+        assert_eq!((counted(CountPolicy::DEVICES), counted(CountPolicy::UNIVERSAL), counted(CountPolicy::EVERY_ADDRESS)), (2, 1, 5));
+
+        let summary = AddressSummary::from_macs(&macs, &CountPolicy::DEVICES);
+        assert_eq!(summary, AddressSummary { devices: 2, unicast_universal: 1, unicast_local: 1, multicast: 1, broadcast: 1, null: 1 });
+    }
+
+    #[test]
+    fn policy_choices() {
+        for policy in CountPolicy::ALL {
+            assert_eq!(CountPolicy::from_id(policy.id()), Some(policy));
+        }
+        assert_eq!(CountPolicy::EVERY_ADDRESS.next(), CountPolicy::DEVICES);
+        assert_eq!(CountPolicy { null: true, ..CountPolicy::DEVICES }.label(), "Custom");
+    }
+}
@@ -0,0 +1,365 @@
+// Generic menu tree driven by a declarative definition
+//
+// Navigation only knows about button events and a `MenuValues` store, so the same tree can be
+// driven by the button on the device or by a script on the host. Rendering is left to the
+// caller through `Menu::view`.
+
+use alloc::{format, string::{String, ToString}, vec::Vec};
+
+// Rows that fit under the title line
+pub const VISIBLE_ROWS: usize = 5;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Editor {
+    // Cycles through the values the setting accepts
+    Choice,
+    // Steps up, wrapping from max back to min
+    Number { min: i32, max: i32, step: i32 },
+    // "On" / "Off"
+    Toggle,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MenuItem<A> {
+    Action { label: &'static str, action: A },
+    Submenu { label: &'static str, items: Vec<MenuItem<A>> },
+    Value { label: &'static str, key: &'static str, editor: Editor },
+    // Returns to the parent menu
+    Back,
+}
+
+impl<A> MenuItem<A> {
+    pub fn label(&self) -> &'static str {
+        match self {
+            MenuItem::Action { label, .. } | MenuItem::Submenu { label, .. } | MenuItem::Value { label, .. } => label,
+            MenuItem::Back => "Back",
+        }
+    }
+}
+
+// Where menu values are read from and written to, keyed by the `key` of `MenuItem::Value`
+pub trait MenuValues {
+    fn value(&self, key: &str) -> String;
+    fn set_value(&mut self, key: &str, value: &str);
+    // The value after the current one for `Editor::Choice`
+    fn next_choice(&mut self, key: &str);
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MenuEvent {
+    // Short press: move to the next item, or the next value while editing
+    Next,
+    // Long press: open the item, or confirm the value while editing
+    Select,
+    // Restore the value being edited, or leave the submenu
+    Cancel,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MenuOutcome<A> {
+    None,
+    Redraw,
+    Action(A),
+    // An edited value was confirmed
+    Changed(&'static str),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MenuView {
+    pub title: String,
+    pub rows: Vec<String>,
+    // Index into `rows` of the highlighted row
+    pub highlighted: usize,
+    pub editing: bool,
+}
+
+pub struct Menu<A> {
+    title: &'static str,
+    root: Vec<MenuItem<A>>,
+    // Indices of the open submenus, outermost first
+    path: Vec<usize>,
+    selected: usize,
+    first_visible: usize,
+    editing: bool,
+    // The value when editing began, put back on cancel
+    before_edit: String,
+}
+
+impl<A: Copy> Menu<A> {
+    pub fn new(title: &'static str, root: Vec<MenuItem<A>>) -> Self {
+        Menu { title, root, path: Vec::new(), selected: 0, first_visible: 0, editing: false, before_edit: String::new() }
+    }
+
+    fn items(&self) -> &[MenuItem<A>] {
+        items_at(&self.root, &self.path)
+    }
+
+    fn open(&mut self, selected: usize) {
+        self.selected = selected;
+        self.first_visible = selected.saturating_sub(VISIBLE_ROWS - 1);
+    }
+
+    // Land on the submenu that was just closed
+    fn close(&mut self) -> MenuOutcome<A> {
+        let Some(parent) = self.path.pop() else {
+            return MenuOutcome::None;
+        };
+        self.open(parent);
+        MenuOutcome::Redraw
+    }
+
+    pub fn editing(&self) -> bool {
+        self.editing
+    }
+
+    pub fn handle(&mut self, event: MenuEvent, values: &mut impl MenuValues) -> MenuOutcome<A> {
+        let Some(item) = self.items().get(self.selected) else {
+            return MenuOutcome::None;
+        };
+
+        if self.editing {
+            let MenuItem::Value { key, editor, .. } = *item else {
+                self.editing = false;
+                return MenuOutcome::Redraw;
+            };
+            return match event {
+                MenuEvent::Next => {
+                    step_value(values, key, editor);
+                    MenuOutcome::Redraw
+                },
+                MenuEvent::Select => {
+                    self.editing = false;
+                    MenuOutcome::Changed(key)
+                },
+                MenuEvent::Cancel => {
+                    values.set_value(key, &self.before_edit);
+                    self.editing = false;
+                    MenuOutcome::Redraw
+                },
+            };
+        }
+
+        match event {
+            MenuEvent::Next => {
+                let next = (self.selected + 1) % self.items().len();
+                self.selected = next;
+                if next == 0 {
+                    self.first_visible = 0;
+                } else if next >= self.first_visible + VISIBLE_ROWS {
+                    self.first_visible = next + 1 - VISIBLE_ROWS;
+                }
+                MenuOutcome::Redraw
+            },
+            MenuEvent::Select => match item {
+                MenuItem::Action { action, .. } => MenuOutcome::Action(*action),
+                MenuItem::Submenu { .. } => {
+                    self.path.push(self.selected);
+                    self.open(0);
+                    MenuOutcome::Redraw
+                },
+                MenuItem::Value { key, .. } => {
+                    self.before_edit = values.value(key);
+                    self.editing = true;
+                    MenuOutcome::Redraw
+                },
+                MenuItem::Back => self.close(),
+            },
+            MenuEvent::Cancel => self.close(),
+        }
+    }
+
+    pub fn view(&self, values: &impl MenuValues) -> MenuView {
+        // A submenu is titled with the label that opened it
+        let title = match self.path.split_last() {
+            Some((&last, parents)) => items_at(&self.root, parents).get(last).map(MenuItem::label).unwrap_or(self.title),
+            None => self.title,
+        };
+        let rows = self.items().iter()
+            .skip(self.first_visible)
+            .take(VISIBLE_ROWS)
+            .map(|item| match item {
+                MenuItem::Value { label, key, .. } => format!("{}: {}", label, values.value(key)),
+                MenuItem::Submenu { label, .. } => format!("{} >", label),
+                item => item.label().to_string(),
+            })
+            .collect();
+        MenuView { title: title.to_string(), rows, highlighted: self.selected - self.first_visible, editing: self.editing }
+    }
+}
+
+fn items_at<'a, A>(root: &'a [MenuItem<A>], path: &[usize]) -> &'a [MenuItem<A>] {
+    let mut items = root;
+    for &idx in path {
+        if let Some(MenuItem::Submenu { items: children, .. }) = items.get(idx) {
+            items = children;
+        }
+    }
+    items
+}
+
+fn step_value(values: &mut impl MenuValues, key: &str, editor: Editor) {
+    match editor {
+        Editor::Choice => values.next_choice(key),
+        Editor::Number { min, max, step } => {
+            let current: i32 = values.value(key).parse().unwrap_or(min);
+            let next = if current + step > max { min } else { current + step };
+            values.set_value(key, &next.to_string());
+        },
+        Editor::Toggle => {
+            let next = if values.value(key) == "On" { "Off" } else { "On" };
+            values.set_value(key, next);
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::settings::Settings;
+    use alloc::vec;
+
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    enum Action {
+        Scan,
+        Sleep,
+    }
+
+    fn menu() -> Menu<Action> {
+        Menu::new("Menu", vec![
+            MenuItem::Action { label: "Scan", action: Action::Scan },
+            MenuItem::Submenu { label: "Settings", items: vec![
+                MenuItem::Value { label: "Min RSSI", key: "min_rssi", editor: Editor::Number { min: -100, max: -30, step: 5 } },
+                MenuItem::Value { label: "AP list", key: "ap_list", editor: Editor::Toggle },
+                MenuItem::Value { label: "Profile", key: "scan_profile", editor: Editor::Choice },
+                MenuItem::Value { label: "Dwell", key: "dwell_ms", editor: Editor::Choice },
+                MenuItem::Value { label: "Brightness", key: "brightness", editor: Editor::Choice },
+                MenuItem::Back,
+            ] },
+            MenuItem::Action { label: "Sleep", action: Action::Sleep },
+        ])
+    }
+
+    #[test]
+    fn navigate_and_edit() {
+        let mut settings = Settings::default();
+        let mut menu = menu();
+        assert_eq!(menu.handle(MenuEvent::Select, &mut settings), MenuOutcome::Action(Action::Scan));
+
+        menu.handle(MenuEvent::Next, &mut settings);
+        menu.handle(MenuEvent::Select, &mut settings);
+        let view = menu.view(&settings);
+        assert_eq!((view.title.as_str(), view.rows[0].as_str(), view.highlighted), ("Settings", "Min RSSI: -90", 0));
+
+        // Short presses step the value while editing; a long press confirms it
+        menu.handle(MenuEvent::Select, &mut settings);
+        menu.handle(MenuEvent::Next, &mut settings);
+        assert!(menu.view(&settings).editing);
+        assert_eq!(menu.handle(MenuEvent::Select, &mut settings), MenuOutcome::Changed("min_rssi"));
+        assert_eq!(settings.min_rssi_dbm, -85);
+
+        menu.handle(MenuEvent::Next, &mut settings);
+        menu.handle(MenuEvent::Select, &mut settings);
+        menu.handle(MenuEvent::Next, &mut settings);
+        menu.handle(MenuEvent::Select, &mut settings);
+        assert!(!settings.show_ap_list);
+    }
+
+    #[test]
+    fn scrolls_and_goes_back() {
+        let mut settings = Settings::default();
+        let mut menu = menu();
+        menu.handle(MenuEvent::Next, &mut settings);
+        menu.handle(MenuEvent::Select, &mut settings);
+        for _ in 0..5 {
+            menu.handle(MenuEvent::Next, &mut settings);
+        }
+        let view = menu.view(&settings);
+        assert_eq!(view.rows.len(), VISIBLE_ROWS);
+        assert_eq!((view.rows[4].as_str(), view.highlighted), ("Back", 4));
+
+        menu.handle(MenuEvent::Select, &mut settings);
+        let view = menu.view(&settings);
+        assert_eq!((view.title.as_str(), view.rows[1].as_str(), view.highlighted), ("Menu", "Settings >", 1));
+        menu.handle(MenuEvent::Next, &mut settings);
+        assert_eq!(menu.handle(MenuEvent::Select, &mut settings), MenuOutcome::Action(Action::Sleep));
+    }
+
+    #[test]
+    fn short_press_wraps() {
+        let mut settings = Settings::default();
+        let mut menu = menu();
+        for expected in [1, 2, 0, 1] {
+            assert_eq!(menu.handle(MenuEvent::Next, &mut settings), MenuOutcome::Redraw);
+            assert_eq!(menu.view(&settings).highlighted, expected);
+        }
+
+        // Past the last row of a scrolled submenu, back to the top
+        menu.handle(MenuEvent::Select, &mut settings);
+        for _ in 0..6 {
+            menu.handle(MenuEvent::Next, &mut settings);
+        }
+        let view = menu.view(&settings);
+        assert_eq!((view.rows[0].as_str(), view.highlighted), ("Min RSSI: -90", 0));
+    }
+
+    #[test]
+    fn long_press_enters_and_leaves_submenus() {
+        let mut settings = Settings::default();
+        let mut menu = menu();
+        menu.handle(MenuEvent::Next, &mut settings);
+        assert_eq!(menu.handle(MenuEvent::Select, &mut settings), MenuOutcome::Redraw);
+        assert_eq!(menu.view(&settings).title, "Settings");
+
+        // Back lands on the submenu it closed
+        for _ in 0..5 {
+            menu.handle(MenuEvent::Next, &mut settings);
+        }
+        assert_eq!(menu.handle(MenuEvent::Select, &mut settings), MenuOutcome::Redraw);
+        let view = menu.view(&settings);
+        assert_eq!((view.title.as_str(), view.highlighted), ("Menu", 1));
+
+        // Cancel leaves from any row, and does nothing at the top
+        menu.handle(MenuEvent::Select, &mut settings);
+        menu.handle(MenuEvent::Next, &mut settings);
+        assert_eq!(menu.handle(MenuEvent::Cancel, &mut settings), MenuOutcome::Redraw);
+        let view = menu.view(&settings);
+        assert_eq!((view.title.as_str(), view.highlighted), ("Menu", 1));
+        assert_eq!(menu.handle(MenuEvent::Cancel, &mut settings), MenuOutcome::None);
+        assert_eq!(menu.view(&settings).highlighted, 1);
+    }
+
+    #[test]
+    fn editor_confirms_or_cancels() {
+        let mut settings = Settings::default();
+        let mut menu = menu();
+        menu.handle(MenuEvent::Next, &mut settings);
+        menu.handle(MenuEvent::Select, &mut settings);
+
+        // Numbers wrap from max back to min
+        settings.min_rssi_dbm = -35;
+        menu.handle(MenuEvent::Select, &mut settings);
+        menu.handle(MenuEvent::Next, &mut settings);
+        assert_eq!(settings.min_rssi_dbm, -30);
+        menu.handle(MenuEvent::Next, &mut settings);
+        assert_eq!(settings.min_rssi_dbm, -100);
+        assert_eq!(menu.handle(MenuEvent::Select, &mut settings), MenuOutcome::Changed("min_rssi"));
+        assert!(!menu.editing());
+
+        // Cancel puts back the value from when editing began
+        menu.handle(MenuEvent::Select, &mut settings);
+        menu.handle(MenuEvent::Next, &mut settings);
+        menu.handle(MenuEvent::Next, &mut settings);
+        assert_eq!(menu.handle(MenuEvent::Cancel, &mut settings), MenuOutcome::Redraw);
+        assert_eq!((settings.min_rssi_dbm, menu.editing()), (-100, false));
+        assert_eq!(menu.view(&settings).title, "Settings");
+
+        menu.handle(MenuEvent::Next, &mut settings);
+        menu.handle(MenuEvent::Next, &mut settings);
+        let profile = settings.scan_profile;
+        menu.handle(MenuEvent::Select, &mut settings);
+        menu.handle(MenuEvent::Next, &mut settings);
+        assert_ne!(settings.scan_profile, profile);
+        menu.handle(MenuEvent::Cancel, &mut settings);
+        assert_eq!(settings.scan_profile, profile);
+    }
+}
@@ -0,0 +1,170 @@
+// What the scan loop learns about a single captured frame
+// Built in the promiscuous callback from wifi_pkt_rx_ctrl_t and the parsed MAC header.
+
+use crate::frame::MacHeader;
+use crate::ring::SpscRing;
+
+// Observations waiting for the scan loop; at ~400 bytes each this is about 25 KB of .bss
+pub const OBSERVATION_RING_CAPACITY: usize = 64;
+pub type ObservationRing = SpscRing<Observation, OBSERVATION_RING_CAPACITY>;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SignalMode {
+    NonHt,
+    Ht,
+    Vht,
+    Unknown(u8),
+}
+
+impl From<u8> for SignalMode {
+    fn from(value: u8) -> Self {
+        match value {
+            0 => SignalMode::NonHt,
+            1 => SignalMode::Ht,
+            3 => SignalMode::Vht,
+            other => SignalMode::Unknown(other),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RxMeta {
+    pub rssi: i8,
+    pub noise_floor: i8,
+    pub channel: u8,
+    // 0: none, 1: above primary, 2: below primary
+    pub secondary_channel: u8,
+    // Legacy rate index; only meaningful for non-HT frames
+    pub rate: u8,
+    pub sig_mode: SignalMode,
+    // Only meaningful for HT/VHT frames
+    pub mcs: u8,
+    // True for 40 MHz channel bandwidth
+    pub wide_bandwidth: bool,
+    // Length of the frame as reported by the driver, including FCS
+    pub sig_len: u16,
+    // Local radio time in microseconds
+    pub timestamp_us: u32,
+    // The frame failed its FCS check; addresses and body may be corrupt
+    pub fcs_failed: bool,
+}
+
+// Frames are copied out of the driver buffer so they can be parsed (and captured) in the scan
+// loop instead of on the Wi-Fi task. Longer frames are truncated.
+pub const MAX_FRAME_LEN: usize = 320;
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub struct FrameBytes {
+    len: u16,
+    bytes: [u8; MAX_FRAME_LEN],
+}
+
+impl FrameBytes {
+    pub fn new(data: &[u8]) -> Self {
+        let mut frame = FrameBytes {
+            len: 0,
+            bytes: [0u8; MAX_FRAME_LEN],
+        };
+        let len = data.len().min(MAX_FRAME_LEN);
+        frame.bytes[..len].copy_from_slice(&data[..len]);
+        frame.len = len as u16;
+        frame
+    }
+
+    pub fn as_slice(&self) -> &[u8] {
+        &self.bytes[..self.len as usize]
+    }
+}
+
+impl core::fmt::Debug for FrameBytes {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(f, "FrameBytes({} bytes)", self.len)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Observation {
+    pub meta: RxMeta,
+    pub header: MacHeader,
+    // Start of the frame, header included
+    pub frame: FrameBytes,
+}
+
+impl RxMeta {
+    // Convert the driver's legacy rate index (wifi_phy_rate_t) to 500 kb/s units
+    pub fn rate_500kbps(&self) -> Option<u8> {
+        if self.sig_mode != SignalMode::NonHt {
+            return None;
+        }
+        match self.rate {
+            0x00 => Some(2),
+            0x01 | 0x05 => Some(4),
+            0x02 | 0x06 => Some(11),
+            0x03 | 0x07 => Some(22),
+            0x08 => Some(96),
+            0x09 => Some(48),
+            0x0A => Some(24),
+            0x0B => Some(12),
+            0x0C => Some(108),
+            0x0D => Some(72),
+            0x0E => Some(36),
+            0x0F => Some(18),
+            _ => None,
+        }
+    }
+}
+
+impl Observation {
+    pub fn rssi_at_least(&self, min_rssi: i8) -> bool {
+        self.meta.rssi >= min_rssi
+    }
+
+    // Frame body (information elements for most management frames), possibly truncated
+    pub fn body(&self) -> &[u8] {
+        let frame = self.frame.as_slice();
+        &frame[self.header.header_len.min(frame.len())..]
+    }
+
+    // Length of the frame on air without FCS
+    pub fn frame_len(&self) -> usize {
+        crate::frame::frame_len_from_sig_len(self.meta.sig_len as usize)
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::frame::parse_header;
+
+    // A received frame as the sniffer would queue it
+    pub fn observation(frame: &[u8], rssi: i8) -> Observation {
+        let meta = RxMeta {
+            rssi,
+            noise_floor: -95,
+            channel: 6,
+            secondary_channel: 0,
+            rate: 0,
+            sig_mode: SignalMode::NonHt,
+            mcs: 0,
+            wide_bandwidth: false,
+            sig_len: (frame.len() + 4) as u16,
+            timestamp_us: 0,
+            fcs_failed: false,
+        };
+        Observation { meta, header: parse_header(frame).unwrap(), frame: FrameBytes::new(frame) }
+    }
+
+    #[test]
+    fn body_follows_header() {
+        let mut frame = alloc::vec![0x40, 0x00, 0, 0];
+        frame.extend([0xff; 6]);
+        frame.extend([2; 6]);
+        frame.extend([0xff; 6]);
+        frame.extend([0x10, 0]);
+        frame.extend([0, 4, b't', b'e', b's', b't']);
+        let observation = observation(&frame, -50);
+        assert_eq!(observation.body(), &[0, 4, b't', b'e', b's', b't']);
+        assert_eq!(observation.frame_len(), frame.len());
+        assert!(observation.rssi_at_least(-60) && !observation.rssi_at_least(-40));
+    }
+}
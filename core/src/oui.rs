@@ -0,0 +1,104 @@
+// Vendor lookup in the IEEE MA-L, MA-M and MA-S registries
+// The tables are generated by build.rs from data/oui. Each registry is sorted by prefix, so a
+// lookup is one binary search per registry, most specific (MA-S) first: MA-M and MA-S blocks
+// are carved out of MA-L prefixes registered to the IEEE itself.
+
+use alloc::{collections::BTreeMap, format, string::String, vec::Vec};
+
+use crate::frame::MacAddress;
+
+mod table {
+    include!(concat!(env!("OUT_DIR"), "/oui_table.rs"));
+}
+
+fn vendor_name(id: u16) -> &'static str {
+    // Walk forward from the closest checkpoint
+    let id = id as usize;
+    let checkpoint = id / table::VENDOR_NAME_CHECKPOINT;
+    let first = checkpoint * table::VENDOR_NAME_CHECKPOINT;
+    let start = table::VENDOR_NAME_CHECKPOINTS[checkpoint] as usize
+        + table::VENDOR_NAME_LENS[first..id].iter().map(|len| *len as usize).sum::<usize>();
+    &table::VENDOR_NAMES[start..start + table::VENDOR_NAME_LENS[id] as usize]
+}
+
+// Short vendor name ("Apple", "Samsung") of a universally administered address
+pub fn lookup(mac: &MacAddress) -> Option<&'static str> {
+    // Locally administered (e.g. randomized) addresses are not registered with the IEEE
+    if mac[0] & 0x02 != 0 {
+        return None;
+    }
+    // The group bit is not part of the assignment
+    let address = u64::from_be_bytes([0, 0, mac[0] & !0x01, mac[1], mac[2], mac[3], mac[4], mac[5]]);
+
+    if let Ok(idx) = table::MA_S_PREFIXES.binary_search(&(address >> 12)) {
+        return Some(vendor_name(table::MA_S_NAMES[idx]));
+    }
+    if let Ok(idx) = table::MA_M_PREFIXES.binary_search(&((address >> 20) as u32)) {
+        return Some(vendor_name(table::MA_M_NAMES[idx]));
+    }
+    if let Ok(idx) = table::MA_L_PREFIXES.binary_search(&((address >> 24) as u32)) {
+        return Some(vendor_name(table::MA_L_NAMES[idx]));
+    }
+    None
+}
+
+// Number of addresses per known vendor, most common first
+pub fn vendor_counts<'a>(macs: impl IntoIterator<Item = &'a MacAddress>) -> Vec<(&'static str, usize)> {
+    let mut counts: BTreeMap<&'static str, usize> = BTreeMap::new();
+    for vendor in macs.into_iter().filter_map(lookup) {
+        *counts.entry(vendor).or_insert(0) += 1;
+    }
+    let mut counts: Vec<_> = counts.into_iter().collect();
+    counts.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(b.0)));
+    counts
+}
+
+// "Apple 12, Samsung 7" with as many vendors as fit in `max_chars`
+pub fn format_top_vendors(counts: &[(&str, usize)], max_chars: usize) -> String {
+    let mut out = String::new();
+    for (vendor, count) in counts {
+        let entry = format!("{} {}", vendor, count);
+        let separator = if out.is_empty() { "" } else { ", " };
+        if out.len() + separator.len() + entry.len() > max_chars {
+            break;
+        }
+        out.push_str(separator);
+        out.push_str(&entry);
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lookups() {
+        assert_eq!(lookup(&[0x00, 0x1a, 0x11, 1, 2, 3]), Some("Google"));
+        // The group bit is ignored, the local bit is never registered
+        assert_eq!(lookup(&[0x01, 0x1a, 0x11, 1, 2, 3]), Some("Google"));
+        assert_eq!(lookup(&[0x02, 0x1a, 0x11, 1, 2, 3]), None);
+    }
+
+    #[test]
+    fn more_specific_registries_win() {
+        // C8:5C:E2 and 8C:1F:64 are MA-L blocks of the IEEE Registration Authority
+        assert_eq!(lookup(&[0xc8, 0x5c, 0xe2, 0x70, 0x00, 0x00]), Some("SYNERGY SYSTEMS"));
+        assert_eq!(lookup(&[0xc8, 0x5c, 0xe2, 0x7f, 0xff, 0xff]), Some("SYNERGY SYSTEMS"));
+        assert_eq!(lookup(&[0xc8, 0x5c, 0xe2, 0xf0, 0x00, 0x01]), Some("IEEE Registratio"));
+        // MA-S prefixes are 36 bits, so the fifth byte's high nibble picks the vendor
+        assert_eq!(lookup(&[0x8c, 0x1f, 0x64, 0xd0, 0xf0, 0x00]), Some("Mecco"));
+        assert_eq!(lookup(&[0x8c, 0x1f, 0x64, 0xd0, 0xff, 0xff]), Some("Mecco"));
+        assert_eq!(lookup(&[0x8c, 0x1f, 0x64, 0xd0, 0xe1, 0x23]), Some("Labforge"));
+    }
+
+    #[test]
+    fn top_vendors() {
+        let macs = [[0x00, 0x1a, 0x11, 0, 0, 1], [0x00, 0x1a, 0x11, 0, 0, 2], [0x02, 0, 0, 0, 0, 1]];
+        let counts = vendor_counts(macs.iter());
+        assert_eq!(counts, [("Google", 2)]);
+        let counts = [("Apple", 12), ("Samsung", 7), ("Google", 1)];
+        assert_eq!(format_top_vendors(&counts, 20), "Apple 12, Samsung 7");
+        assert_eq!(format_top_vendors(&counts, 5), "");
+    }
+}
@@ -0,0 +1,392 @@
+// pcap / pcapng writer for captured 802.11 frames
+// Frames are written with the IEEE 802.11 + radiotap link type so Wireshark shows signal
+// strength, channel and rate alongside the decoded frame.
+
+use alloc::vec::Vec;
+
+pub const LINKTYPE_IEEE802_11_RADIOTAP: u16 = 127;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CaptureFormat {
+    Pcap,
+    PcapNg,
+}
+
+impl CaptureFormat {
+    pub const ALL: [CaptureFormat; 2] = [CaptureFormat::Pcap, CaptureFormat::PcapNg];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            CaptureFormat::Pcap => "pcap",
+            CaptureFormat::PcapNg => "pcapng",
+        }
+    }
+
+    pub fn next(&self) -> CaptureFormat {
+        let idx = Self::ALL.iter().position(|format| format == self).unwrap_or(0);
+        Self::ALL[(idx + 1) % Self::ALL.len()]
+    }
+
+    pub fn id(&self) -> u8 {
+        Self::ALL.iter().position(|format| format == self).unwrap_or(0) as u8
+    }
+
+    pub fn from_id(id: u8) -> Option<CaptureFormat> {
+        Self::ALL.get(id as usize).copied()
+    }
+
+    pub fn extension(&self) -> &'static str {
+        match self {
+            CaptureFormat::Pcap => "pcap",
+            CaptureFormat::PcapNg => "pcapng",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CaptureOptions {
+    pub format: CaptureFormat,
+    // Maximum number of 802.11 bytes kept per frame (radiotap header not included)
+    pub snaplen: u32,
+    // Keep only the MAC header of each frame
+    pub headers_only: bool,
+}
+
+// Per-frame radio information written into the radiotap header
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RadioInfo {
+    pub channel: u8,
+    pub rssi: i8,
+    pub noise: i8,
+    // Legacy rate in 500 kb/s units, when known
+    pub rate_500kbps: Option<u8>,
+    // HT MCS index, when the frame was HT
+    pub mcs: Option<u8>,
+    pub wide_bandwidth: bool,
+    pub bad_fcs: bool,
+}
+
+// Radiotap present bits
+const RADIOTAP_FLAGS: u32 = 1 << 1;
+const RADIOTAP_RATE: u32 = 1 << 2;
+const RADIOTAP_CHANNEL: u32 = 1 << 3;
+const RADIOTAP_DBM_ANTSIGNAL: u32 = 1 << 5;
+const RADIOTAP_DBM_ANTNOISE: u32 = 1 << 6;
+const RADIOTAP_MCS: u32 = 1 << 19;
+
+const FLAG_BAD_FCS: u8 = 0x40;
+const CHANNEL_FLAG_2GHZ: u16 = 0x0080;
+const MCS_KNOWN_BANDWIDTH: u8 = 0x01;
+const MCS_KNOWN_INDEX: u8 = 0x02;
+const MCS_FLAG_BW40: u8 = 0x01;
+
+pub fn channel_frequency_mhz(channel: u8) -> u16 {
+    match channel {
+        14 => 2484,
+        1..=13 => 2407 + 5 * channel as u16,
+        _ => 0,
+    }
+}
+
+fn pad_to(out: &mut Vec<u8>, start: usize, align: usize) {
+    while (out.len() - start) % align != 0 {
+        out.push(0);
+    }
+}
+
+pub fn radiotap_header(radio: &RadioInfo) -> Vec<u8> {
+    let mut present = RADIOTAP_FLAGS | RADIOTAP_CHANNEL | RADIOTAP_DBM_ANTSIGNAL | RADIOTAP_DBM_ANTNOISE;
+    if radio.rate_500kbps.is_some() {
+        present |= RADIOTAP_RATE;
+    }
+    if radio.mcs.is_some() {
+        present |= RADIOTAP_MCS;
+    }
+
+    let mut out = Vec::with_capacity(24);
+    out.push(0); // version
+    out.push(0); // pad
+    out.extend_from_slice(&0u16.to_le_bytes()); // length, patched below
+    out.extend_from_slice(&present.to_le_bytes());
+
+    // Fields follow in present-bit order, each aligned to its natural size
+    // flags: FCS already stripped, but we still know whether it matched
+    out.push(if radio.bad_fcs { FLAG_BAD_FCS } else { 0 });
+    if let Some(rate) = radio.rate_500kbps {
+        out.push(rate);
+    }
+    pad_to(&mut out, 0, 2);
+    out.extend_from_slice(&channel_frequency_mhz(radio.channel).to_le_bytes());
+    out.extend_from_slice(&CHANNEL_FLAG_2GHZ.to_le_bytes());
+    out.push(radio.rssi as u8);
+    out.push(radio.noise as u8);
+    if let Some(mcs) = radio.mcs {
+        out.push(MCS_KNOWN_BANDWIDTH | MCS_KNOWN_INDEX);
+        out.push(if radio.wide_bandwidth { MCS_FLAG_BW40 } else { 0 });
+        out.push(mcs);
+    }
+
+    let len = out.len() as u16;
+    out[2..4].copy_from_slice(&len.to_le_bytes());
+    out
+}
+
+// Bytes of `frame` that go into the capture, given the parsed header length
+pub fn captured_len(options: &CaptureOptions, frame_len: usize, header_len: usize) -> usize {
+    let limit = if options.headers_only { header_len } else { options.snaplen as usize };
+    frame_len.min(limit)
+}
+
+// Everything written before the first packet
+pub fn file_header(options: &CaptureOptions) -> Vec<u8> {
+    // The snaplen in the file covers the radiotap header as well
+    let snaplen = options.snaplen.saturating_add(64);
+    let mut out = Vec::new();
+    match options.format {
+        CaptureFormat::Pcap => {
+            out.extend_from_slice(&0xA1B2_C3D4u32.to_le_bytes());
+            out.extend_from_slice(&2u16.to_le_bytes());
+            out.extend_from_slice(&4u16.to_le_bytes());
+            out.extend_from_slice(&0i32.to_le_bytes()); // thiszone
+            out.extend_from_slice(&0u32.to_le_bytes()); // sigfigs
+            out.extend_from_slice(&snaplen.to_le_bytes());
+            out.extend_from_slice(&(LINKTYPE_IEEE802_11_RADIOTAP as u32).to_le_bytes());
+        },
+        CaptureFormat::PcapNg => {
+            // Section header block
+            let shb_len = 28u32;
+            out.extend_from_slice(&0x0A0D_0D0Au32.to_le_bytes());
+            out.extend_from_slice(&shb_len.to_le_bytes());
+            out.extend_from_slice(&0x1A2B_3C4Du32.to_le_bytes());
+            out.extend_from_slice(&1u16.to_le_bytes());
+            out.extend_from_slice(&0u16.to_le_bytes());
+            out.extend_from_slice(&(-1i64).to_le_bytes()); // section length unknown
+            out.extend_from_slice(&shb_len.to_le_bytes());
+
+            // Interface description block; default timestamp resolution is microseconds
+            let idb_len = 20u32;
+            out.extend_from_slice(&1u32.to_le_bytes());
+            out.extend_from_slice(&idb_len.to_le_bytes());
+            out.extend_from_slice(&LINKTYPE_IEEE802_11_RADIOTAP.to_le_bytes());
+            out.extend_from_slice(&0u16.to_le_bytes());
+            out.extend_from_slice(&snaplen.to_le_bytes());
+            out.extend_from_slice(&idb_len.to_le_bytes());
+        },
+    }
+    out
+}
+
+// One packet record. `frame` is the already truncated 802.11 data and `frame_len` the
+// length of the frame on air (without FCS).
+pub fn packet_record(format: CaptureFormat, timestamp_us: u64, radio: &RadioInfo, frame: &[u8], frame_len: usize) -> Vec<u8> {
+    let mut data = radiotap_header(radio);
+    let original = data.len() + frame_len.max(frame.len());
+    data.extend_from_slice(frame);
+    raw_packet_record(format, timestamp_us, &data, original)
+}
+
+// One packet record of link layer data that already starts with its radiotap header
+pub fn raw_packet_record(format: CaptureFormat, timestamp_us: u64, data: &[u8], original_len: usize) -> Vec<u8> {
+    let captured = data.len() as u32;
+    let original = original_len as u32;
+
+    let mut out = Vec::with_capacity(32 + data.len());
+    match format {
+        CaptureFormat::Pcap => {
+            out.extend_from_slice(&((timestamp_us / 1_000_000) as u32).to_le_bytes());
+            out.extend_from_slice(&((timestamp_us % 1_000_000) as u32).to_le_bytes());
+            out.extend_from_slice(&captured.to_le_bytes());
+            out.extend_from_slice(&original.to_le_bytes());
+            out.extend_from_slice(data);
+        },
+        CaptureFormat::PcapNg => {
+            // Enhanced packet block, data padded to 32 bits
+            let padded = (data.len() + 3) & !3;
+            let block_len = (32 + padded) as u32;
+            out.extend_from_slice(&6u32.to_le_bytes());
+            out.extend_from_slice(&block_len.to_le_bytes());
+            out.extend_from_slice(&0u32.to_le_bytes()); // interface id
+            out.extend_from_slice(&((timestamp_us >> 32) as u32).to_le_bytes());
+            out.extend_from_slice(&(timestamp_us as u32).to_le_bytes());
+            out.extend_from_slice(&captured.to_le_bytes());
+            out.extend_from_slice(&original.to_le_bytes());
+            out.extend_from_slice(data);
+            out.resize(out.len() + padded - data.len(), 0);
+            out.extend_from_slice(&block_len.to_le_bytes());
+        },
+    }
+    out
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Packet {
+    pub timestamp_us: u64,
+    // Radiotap header followed by the 802.11 frame
+    pub data: Vec<u8>,
+    pub original_len: usize,
+}
+
+fn le_u32(buf: &[u8], offset: usize) -> Option<u32> {
+    let bytes = buf.get(offset..offset + 4)?;
+    Some(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+}
+
+// Reads back a file written by this module. None if it is not a little endian pcap or pcapng
+// file, or is cut short; a capture that was interrupted mid-packet keeps its whole packets.
+pub fn read_capture(buf: &[u8]) -> Option<(CaptureFormat, Vec<Packet>)> {
+    let mut packets = Vec::new();
+    match le_u32(buf, 0)? {
+        0xA1B2_C3D4 => {
+            let mut offset = 24;
+            while let (Some(seconds), Some(micros), Some(captured), Some(original)) =
+                (le_u32(buf, offset), le_u32(buf, offset + 4), le_u32(buf, offset + 8), le_u32(buf, offset + 12))
+            {
+                let Some(data) = buf.get(offset + 16..offset + 16 + captured as usize) else {
+                    break;
+                };
+                packets.push(Packet {
+                    timestamp_us: seconds as u64 * 1_000_000 + micros as u64,
+                    data: data.to_vec(),
+                    original_len: original as usize,
+                });
+                offset += 16 + captured as usize;
+            }
+            Some((CaptureFormat::Pcap, packets))
+        },
+        0x0A0D_0D0A => {
+            let mut offset = 0;
+            while let (Some(block_type), Some(block_len)) = (le_u32(buf, offset), le_u32(buf, offset + 4)) {
+                let block_len = block_len as usize;
+                if block_len < 12 || offset + block_len > buf.len() {
+                    break;
+                }
+                if block_type == 6 {
+                    let (high, low, captured, original) = (
+                        le_u32(buf, offset + 12)?,
+                        le_u32(buf, offset + 16)?,
+                        le_u32(buf, offset + 20)? as usize,
+                        le_u32(buf, offset + 24)?,
+                    );
+                    let data = buf.get(offset + 28..offset + 28 + captured)?;
+                    packets.push(Packet {
+                        timestamp_us: (high as u64) << 32 | low as u64,
+                        data: data.to_vec(),
+                        original_len: original as usize,
+                    });
+                }
+                offset += block_len;
+            }
+            Some((CaptureFormat::PcapNg, packets))
+        },
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::{vec, vec::Vec};
+
+    fn hex(text: &str) -> Vec<u8> {
+        let text: alloc::string::String = text.split_whitespace().collect();
+        (0..text.len()).step_by(2).map(|idx| u8::from_str_radix(&text[idx..idx + 2], 16).unwrap()).collect()
+    }
+
+    const ACK: [u8; 10] = [0xd4, 0x00, 0x00, 0x00, 0x00, 0x14, 0x6c, 0x7e, 0x40, 0x80];
+    const TIMESTAMP_US: u64 = 1_700_000_000_250_000;
+
+    fn legacy_radio() -> RadioInfo {
+        RadioInfo { channel: 6, rssi: -40, noise: -95, rate_500kbps: Some(2), mcs: None, wide_bandwidth: false, bad_fcs: false }
+    }
+
+    #[test]
+    fn pcap_file_header() {
+        let options = CaptureOptions { format: CaptureFormat::Pcap, snaplen: 320, headers_only: false };
+        // Magic, version 2.4, zone, sigfigs, snaplen 320 + 64, link type 127
+        assert_eq!(file_header(&options), hex("d4c3b2a1 0200 0400 00000000 00000000 80010000 7f000000"));
+    }
+
+    #[test]
+    fn pcapng_file_header() {
+        let options = CaptureOptions { format: CaptureFormat::PcapNg, snaplen: 320, headers_only: false };
+        assert_eq!(file_header(&options), hex("
+            0a0d0d0a 1c000000 4d3c2b1a 0100 0000 ffffffffffffffff 1c000000
+            01000000 14000000 7f00 0000 80010000 14000000
+        "));
+    }
+
+    #[test]
+    fn radiotap_fields() {
+        // Flags, rate, channel 6 (2437 MHz, 2.4 GHz), signal -40 dBm, noise -95 dBm
+        assert_eq!(radiotap_header(&legacy_radio()), hex("0000 1000 6e000000 00 02 8509 8000 d8 a1"));
+
+        // HT MCS 7 at 40 MHz with a bad FCS: no rate, a pad byte before the channel, MCS last
+        let radio = RadioInfo { channel: 11, rssi: -70, noise: -95, rate_500kbps: None, mcs: Some(7), wide_bandwidth: true, bad_fcs: true };
+        assert_eq!(radiotap_header(&radio), hex("0000 1300 6a000800 40 00 9e09 8000 ba a1 03 01 07"));
+        assert_eq!((channel_frequency_mhz(1), channel_frequency_mhz(14), channel_frequency_mhz(36)), (2412, 2484, 0));
+    }
+
+    #[test]
+    fn pcap_packet_record() {
+        let record = packet_record(CaptureFormat::Pcap, TIMESTAMP_US, &legacy_radio(), &ACK, ACK.len());
+        let mut expected = hex("00f15365 90d00300 1a000000 1a000000 0000 1000 6e000000 00 02 8509 8000 d8 a1");
+        expected.extend(ACK);
+        assert_eq!(record, expected);
+    }
+
+    #[test]
+    fn pcapng_packet_record() {
+        // 26 bytes of data padded to 28, original length 10 bytes longer than captured
+        let record = packet_record(CaptureFormat::PcapNg, TIMESTAMP_US, &legacy_radio(), &ACK, ACK.len() + 10);
+        let mut expected = hex("06000000 3c000000 00000000 240a0600 90102218 1a000000 24000000 0000 1000 6e000000 00 02 8509 8000 d8 a1");
+        expected.extend(ACK);
+        expected.extend(hex("0000 3c000000"));
+        assert_eq!(record, expected);
+    }
+
+    #[test]
+    fn truncation() {
+        let options = CaptureOptions { format: CaptureFormat::Pcap, snaplen: 64, headers_only: false };
+        assert_eq!((captured_len(&options, 300, 24), captured_len(&options, 40, 24)), (64, 40));
+        let options = CaptureOptions { headers_only: true, ..options };
+        assert_eq!((captured_len(&options, 300, 26), captured_len(&options, 10, 10)), (26, 10));
+    }
+
+    #[test]
+    fn captures_round_trip() {
+        // Beacon with an SSID
+        let mut frame = vec![0x80, 0x00, 0, 0];
+        frame.extend([0xff; 6]);
+        frame.extend([2; 6]);
+        frame.extend([2; 6]);
+        frame.extend([0x10, 0]);
+        frame.extend([0; 8]);
+        frame.extend([100, 0, 0x11, 0]);
+        frame.extend([0, 4, b't', b'e', b's', b't']);
+
+        for format in [CaptureFormat::Pcap, CaptureFormat::PcapNg] {
+            let options = CaptureOptions { format, snaplen: 320, headers_only: false };
+            let mut capture = file_header(&options);
+            let radio = RadioInfo { channel: 6, rssi: -40, noise: -95, rate_500kbps: Some(2), mcs: None, wide_bandwidth: false, bad_fcs: false };
+            capture.extend(packet_record(format, 1_700_000_000_000_000, &radio, &frame, frame.len()));
+            let radio = RadioInfo { channel: 11, rssi: -70, noise: -95, rate_500kbps: None, mcs: Some(7), wide_bandwidth: true, bad_fcs: true };
+            capture.extend(packet_record(format, 1_700_000_000_100_000, &radio, &frame[..24], frame.len()));
+
+            let (read_format, packets) = read_capture(&capture).unwrap();
+            assert_eq!(read_format, format);
+            assert_eq!(packets.len(), 2);
+            assert_eq!(packets[1].timestamp_us, 1_700_000_000_100_000);
+            // Radiotap header first, then the truncated frame
+            assert!(packets[1].data.ends_with(&frame[..24]));
+            assert_eq!(packets[1].original_len - packets[1].data.len(), frame.len() - 24);
+
+            let mut again = file_header(&options);
+            for packet in &packets {
+                again.extend(raw_packet_record(format, packet.timestamp_us, &packet.data, packet.original_len));
+            }
+            assert_eq!(again, capture);
+            // A packet cut off at the end of the file is left out
+            assert_eq!(read_capture(&capture[..capture.len() - 3]).unwrap().1.len(), 1);
+        }
+    }
+}
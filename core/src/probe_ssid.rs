@@ -0,0 +1,247 @@
+// SSIDs named in directed probe requests, per transmitting MAC
+//
+// A phone probing for its saved networks leaks its preferred network list. With hashing
+// enabled only a salted FNV-1a of each SSID is kept, which still lets scans from the same
+// device be compared without writing the network names to flash. Salting with the device ID
+// keeps the hashes from matching a precomputed table, but a short SSID list can still be
+// brute forced; it is a pseudonym, not encryption.
+
+use alloc::{collections::BTreeMap, format, string::{String, ToString}, vec::Vec};
+
+use crate::fingerprint::{fnv1a, FNV_OFFSET};
+use crate::frame::{FrameType, MacAddress};
+use crate::ie::{self, IE_SSID, SUBTYPE_PROBE_REQUEST};
+use crate::observation::Observation;
+
+const SSID_MAX_LEN: usize = 32;
+// Bounds memory for devices that probe for many networks (or fuzzers)
+pub const MAX_SSIDS_PER_MAC: usize = 16;
+// Serialized size of one record, see `ProbedSsids::serialize_records`
+pub const RECORD_LEN: usize = 6 + 4 + 1 + 1 + SSID_MAX_LEN;
+
+// Record flag bits
+const FLAG_HASHED: u8 = 1 << 0;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SsidPrivacy {
+    Clear,
+    // Keep a salted 64-bit hash instead of the name
+    Hashed { salt: MacAddress },
+}
+
+// Whether directed probe requests are harvested at all, and in which form
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SsidCollection {
+    Off,
+    Hashed,
+    Clear,
+}
+
+impl SsidCollection {
+    pub const ALL: [SsidCollection; 3] = [SsidCollection::Off, SsidCollection::Hashed, SsidCollection::Clear];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            SsidCollection::Off => "Off",
+            SsidCollection::Hashed => "Hashed",
+            SsidCollection::Clear => "Clear",
+        }
+    }
+
+    pub fn next(&self) -> SsidCollection {
+        let idx = Self::ALL.iter().position(|mode| mode == self).unwrap_or(0);
+        Self::ALL[(idx + 1) % Self::ALL.len()]
+    }
+
+    pub fn id(&self) -> u8 {
+        Self::ALL.iter().position(|mode| mode == self).unwrap_or(0) as u8
+    }
+
+    pub fn from_id(id: u8) -> Option<SsidCollection> {
+        Self::ALL.get(id as usize).copied()
+    }
+
+    // None when SSIDs are not collected
+    pub fn privacy(&self, salt: MacAddress) -> Option<SsidPrivacy> {
+        match self {
+            SsidCollection::Off => None,
+            SsidCollection::Hashed => Some(SsidPrivacy::Hashed { salt }),
+            SsidCollection::Clear => Some(SsidPrivacy::Clear),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ProbedSsid {
+    // The SSID, or its 8-byte little endian hash
+    pub ssid: Vec<u8>,
+    pub count: u32,
+}
+
+impl ProbedSsid {
+    pub fn write_record(&self, mac: &MacAddress, hashed: bool, out: &mut Vec<u8>) {
+        out.extend_from_slice(mac);
+        out.extend_from_slice(&self.count.to_le_bytes());
+        out.push(if hashed { FLAG_HASHED } else { 0 });
+        out.push(self.ssid.len() as u8);
+        let mut ssid = [0u8; SSID_MAX_LEN];
+        ssid[..self.ssid.len()].copy_from_slice(&self.ssid);
+        out.extend_from_slice(&ssid);
+    }
+
+    // Inverse of `write_record`: (mac, entry, hashed)
+    pub fn read_record(record: &[u8]) -> Option<(MacAddress, ProbedSsid, bool)> {
+        if record.len() < RECORD_LEN {
+            return None;
+        }
+        let mut mac = [0u8; 6];
+        mac.copy_from_slice(&record[..6]);
+        let count = u32::from_le_bytes([record[6], record[7], record[8], record[9]]);
+        let ssid_len = (record[11] as usize).min(SSID_MAX_LEN);
+        let entry = ProbedSsid { ssid: record[12..12 + ssid_len].to_vec(), count };
+        Some((mac, entry, record[10] & FLAG_HASHED != 0))
+    }
+
+    // Display form; hashes are shown as #<16 hex digits>
+    pub fn name(&self, hashed: bool) -> String {
+        if hashed {
+            let mut hash = [0u8; 8];
+            let len = self.ssid.len().min(8);
+            hash[..len].copy_from_slice(&self.ssid[..len]);
+            format!("#{:016x}", u64::from_le_bytes(hash))
+        } else {
+            String::from_utf8_lossy(&self.ssid).to_string()
+        }
+    }
+}
+
+// SSID of a directed probe request; wildcard probes carry an empty SSID and return None
+pub fn probed_ssid(observation: &Observation) -> Option<&[u8]> {
+    let header = &observation.header;
+    if header.frame_type() != FrameType::Management || header.subtype() != SUBTYPE_PROBE_REQUEST {
+        return None;
+    }
+    ie::elements(observation.body())
+        .find(|element| element.id == IE_SSID)
+        .map(|element| element.data)
+        .filter(|ssid| !ssid.is_empty() && ssid.iter().any(|b| *b != 0))
+}
+
+#[derive(Debug)]
+pub struct ProbedSsids {
+    privacy: SsidPrivacy,
+    by_mac: BTreeMap<MacAddress, Vec<ProbedSsid>>,
+}
+
+impl ProbedSsids {
+    pub fn new(privacy: SsidPrivacy) -> Self {
+        ProbedSsids {
+            privacy,
+            by_mac: BTreeMap::new(),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.by_mac.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.by_mac.is_empty()
+    }
+
+    pub fn privacy(&self) -> SsidPrivacy {
+        self.privacy
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&MacAddress, &[ProbedSsid])> {
+        self.by_mac.iter().map(|(mac, ssids)| (mac, ssids.as_slice()))
+    }
+
+    fn stored_form(&self, ssid: &[u8]) -> Vec<u8> {
+        let ssid = &ssid[..ssid.len().min(SSID_MAX_LEN)];
+        match self.privacy {
+            SsidPrivacy::Clear => ssid.to_vec(),
+            SsidPrivacy::Hashed { salt } => fnv1a(fnv1a(FNV_OFFSET, &salt), ssid).to_le_bytes().to_vec(),
+        }
+    }
+
+    pub fn observe(&mut self, observation: &Observation) {
+        let (Some(ssid), Some(transmitter)) = (probed_ssid(observation), observation.header.transmitter()) else {
+            return;
+        };
+        let stored = self.stored_form(ssid);
+        let ssids = self.by_mac.entry(transmitter).or_default();
+        if let Some(entry) = ssids.iter_mut().find(|entry| entry.ssid == stored) {
+            entry.count = entry.count.saturating_add(1);
+        } else if ssids.len() < MAX_SSIDS_PER_MAC {
+            ssids.push(ProbedSsid { ssid: stored, count: 1 });
+        }
+    }
+
+    // One record per (MAC, SSID): mac, count, flags (bit 0 hashed), ssid_len, ssid [32]
+    pub fn serialize_records(&self) -> Vec<u8> {
+        let hashed = matches!(self.privacy, SsidPrivacy::Hashed { .. });
+        let mut out = Vec::new();
+        for (mac, ssids) in &self.by_mac {
+            for entry in ssids {
+                entry.write_record(mac, hashed, &mut out);
+            }
+        }
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::observation::tests::observation;
+    use alloc::vec;
+
+    fn probe_request(mac: MacAddress, ssid: &[u8]) -> Vec<u8> {
+        let mut frame = vec![0x40, 0, 0, 0];
+        frame.extend([0xff; 6]);
+        frame.extend(mac);
+        frame.extend([0xff; 6]);
+        frame.extend([0, 0]);
+        frame.extend([IE_SSID, ssid.len() as u8]);
+        frame.extend(ssid);
+        // Supported rates
+        frame.extend([1, 1, 2]);
+        frame
+    }
+
+    #[test]
+    fn collects_directed_probes() {
+        for privacy in [SsidPrivacy::Clear, SsidPrivacy::Hashed { salt: [1, 2, 3, 4, 5, 6] }] {
+            let mut probed = ProbedSsids::new(privacy);
+            let phone = [0x3c, 0, 0, 0, 0, 1];
+            for ssid in [&b"Home"[..], b"Home", b"Cafe", b""] {
+                probed.observe(&observation(&probe_request(phone, ssid), -40));
+            }
+            // Wildcard probes name no network
+            probed.observe(&observation(&probe_request([0x3c, 0, 0, 0, 0, 2], b""), -40));
+
+            assert_eq!(probed.len(), 1);
+            let (mac, ssids) = probed.iter().next().unwrap();
+            assert_eq!(*mac, phone);
+            assert_eq!(ssids.len(), 2);
+            assert_eq!(ssids[0].count, 2);
+
+            let records = probed.serialize_records();
+            assert_eq!(records.len(), 2 * RECORD_LEN);
+            let (_, first, hashed) = ProbedSsid::read_record(&records[..RECORD_LEN]).unwrap();
+            assert_eq!(hashed, privacy != SsidPrivacy::Clear);
+            assert_eq!(first.name(hashed) == "Home", privacy == SsidPrivacy::Clear);
+        }
+    }
+
+    #[test]
+    fn hashed_names() {
+        let ssid = ProbedSsid { ssid: vec![1, 2, 3, 4, 5, 6, 7, 8], count: 9 };
+        let mut record = Vec::new();
+        ssid.write_record(&[3; 6], true, &mut record);
+        let (mac, back, hashed) = ProbedSsid::read_record(&record).unwrap();
+        assert_eq!((mac, &back, hashed), ([3; 6], &ssid, true));
+        assert_eq!(back.name(true), "#0807060504030201");
+    }
+}
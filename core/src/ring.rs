@@ -0,0 +1,127 @@
+// Allocation-free single-producer/single-consumer ring buffer
+// The producer is the promiscuous callback on the Wi-Fi task and the consumer is the scan
+// loop; neither side ever blocks. When the ring is full new items are dropped and counted.
+
+use core::cell::UnsafeCell;
+use core::mem::MaybeUninit;
+use core::sync::atomic::{AtomicU32, AtomicUsize, Ordering};
+
+pub struct SpscRing<T: Copy, const N: usize> {
+    slots: UnsafeCell<[MaybeUninit<T>; N]>,
+    // Free-running counters; the slot index is the counter modulo N
+    head: AtomicUsize,
+    tail: AtomicUsize,
+    dropped: AtomicU32,
+}
+
+// Slots are only written by the single producer before publishing `head` and only read by
+// the single consumer before publishing `tail`, see `push` and `pop`.
+unsafe impl<T: Copy + Send, const N: usize> Sync for SpscRing<T, N> {}
+
+impl<T: Copy, const N: usize> SpscRing<T, N> {
+    pub const fn new() -> Self {
+        SpscRing {
+            // An array of MaybeUninit needs no initialization
+            slots: UnsafeCell::new(unsafe { MaybeUninit::uninit().assume_init() }),
+            head: AtomicUsize::new(0),
+            tail: AtomicUsize::new(0),
+            dropped: AtomicU32::new(0),
+        }
+    }
+
+    /// Returns false (and counts a drop) when the ring is full.
+    ///
+    /// # Safety
+    /// Only one context may push at a time.
+    pub unsafe fn push(&self, item: T) -> bool {
+        let head = self.head.load(Ordering::Relaxed);
+        let tail = self.tail.load(Ordering::Acquire);
+        if head.wrapping_sub(tail) >= N {
+            self.dropped.fetch_add(1, Ordering::Relaxed);
+            return false;
+        }
+        let slots = &mut *self.slots.get();
+        slots[head % N].write(item);
+        self.head.store(head.wrapping_add(1), Ordering::Release);
+        true
+    }
+
+    /// # Safety
+    /// Only one context may pop at a time.
+    pub unsafe fn pop(&self) -> Option<T> {
+        let tail = self.tail.load(Ordering::Relaxed);
+        let head = self.head.load(Ordering::Acquire);
+        if tail == head {
+            return None;
+        }
+        let slots = &*self.slots.get();
+        let item = slots[tail % N].assume_init_read();
+        self.tail.store(tail.wrapping_add(1), Ordering::Release);
+        Some(item)
+    }
+
+    /// Discard queued items and zero the drop counter.
+    ///
+    /// # Safety
+    /// Must not race with `push` or `pop`, i.e. the producer has to be stopped.
+    pub unsafe fn reset(&self) {
+        self.tail.store(self.head.load(Ordering::Acquire), Ordering::Release);
+        self.dropped.store(0, Ordering::Relaxed);
+    }
+
+    pub fn dropped(&self) -> u32 {
+        self.dropped.load(Ordering::Relaxed)
+    }
+}
+
+impl<T: Copy, const N: usize> Default for SpscRing<T, N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn drops_when_full() {
+        let ring: SpscRing<u32, 4> = SpscRing::new();
+        unsafe {
+            for item in 0..6 {
+                ring.push(item);
+            }
+            assert_eq!(ring.dropped(), 2);
+            assert_eq!(ring.pop(), Some(0));
+            assert!(ring.push(6));
+            let rest: alloc::vec::Vec<u32> = core::iter::from_fn(|| ring.pop()).collect();
+            assert_eq!(rest, [1, 2, 3, 6]);
+        }
+    }
+
+    #[test]
+    fn producer_on_another_thread() {
+        static RING: SpscRing<u32, 4> = SpscRing::new();
+        const ITEMS: u32 = 100_000;
+        let producer = std::thread::spawn(|| (0..ITEMS).filter(|item| unsafe { RING.push(*item) }).count() as u32);
+
+        let mut received = 0;
+        let mut last = None;
+        loop {
+            let done = producer.is_finished();
+            match unsafe { RING.pop() } {
+                Some(item) => {
+                    // Order is kept; dropped items leave gaps
+                    assert!(last.map_or(true, |last| item > last));
+                    last = Some(item);
+                    received += 1;
+                },
+                None if done => break,
+                None => {},
+            }
+        }
+        let pushed = producer.join().unwrap();
+        assert_eq!(received, pushed);
+        assert_eq!(pushed + RING.dropped(), ITEMS);
+    }
+}
@@ -0,0 +1,396 @@
+// Scan mode: count devices, access points and associations, then save them to storage
+// The scan runs for the configured duration or until the button is held. In continuous mode
+// the results are saved and reset at the end of every checkpoint window; when storage fills up
+// the oldest file of the session is deleted, so the partition holds the most recent windows.
+// A window that sees too many addresses is saved early, whatever the checkpoint setting.
+
+use alloc::{collections::{BTreeMap, BTreeSet, VecDeque}, format, string::{String, ToString}, vec::Vec};
+use core::time::Duration;
+
+use anyhow::Result;
+use log::{debug, error, info, warn};
+
+use crate::ap::{self, ApInventory};
+use crate::association::{self, AssociationTable};
+use crate::channel_hop::{build_hop_plan, ChannelHopper, ChannelPlan};
+use crate::fingerprint::{self, DeviceClusterer};
+use crate::frame::{FrameType, MacAddress};
+use crate::gesture::ButtonEvent;
+use crate::hal::{Board, Clock, InputDevice, PacketSource, Screen, Storage};
+use crate::ie;
+use crate::mac_addr::{self, AddressKind, AddressSummary, CountPolicy};
+use crate::observation::Observation;
+use crate::oui;
+use crate::probe_ssid::{self, ProbedSsids, SsidCollection, SsidPrivacy};
+use crate::scan_duration::{format_duration, CheckpointWindow, ScanDuration};
+use crate::scan_file::{self, RecordType, ScanHeader, Section};
+use crate::stats::{self, MacStats};
+use crate::ui;
+
+// The scan loop drains every queued observation each pass, then sleeps this long
+const SCAN_LOOP_DELAY_MS: u32 = 10;
+const STATUS_INTERVAL_MS: u64 = 3000;
+// How long the final count stays up before the results are saved
+const FINAL_SCREEN_MS: u32 = 5000;
+// Characters that fit on one 128 px line in the 6x10 font
+const FINAL_SCREEN_CHARS: usize = 21;
+// Addresses a window may hold before it is saved and reset, so long scans fit in RAM
+const MAX_WINDOW_MACS: usize = 1024;
+// Distinct addresses remembered for the whole-scan count; later ones are not counted
+const MAX_SCAN_MACS: usize = 4096;
+
+pub struct ScanOptions {
+    pub duration: ScanDuration,
+    pub checkpoint: CheckpointWindow,
+    pub min_rssi_dbm: i8,
+    pub probed_ssids: SsidCollection,
+    // Which address classes count as devices in the summary and saved scan
+    pub count_policy: CountPolicy,
+    pub boot_count: u32,
+    // Factory MAC of the device; identifies it in saved scans and salts hashed SSIDs
+    pub device_id: MacAddress,
+    pub firmware_version: &'static str,
+}
+
+pub struct ScanReport {
+    // Devices seen over the whole scan, not just the last window
+    pub devices: usize,
+    // Files of this scan still in storage, oldest first; the last one has the final results
+    pub files: Vec<String>,
+    pub access_points: ApInventory,
+}
+
+// Everything learned since the scan (or the current checkpoint window) started
+struct ScanResults {
+    started_ms: u64,
+    started_unix_s: u64,
+    count_policy: CountPolicy,
+    mac_map: BTreeMap<MacAddress, MacStats>,
+    ap_inventory: ApInventory,
+    associations: AssociationTable,
+    // None when SSID collection is off
+    probed_ssids: Option<ProbedSsids>,
+}
+
+impl ScanResults {
+    fn new(clock: &impl Clock, options: &ScanOptions) -> Self {
+        ScanResults {
+            started_ms: clock.now_ms(),
+            started_unix_s: clock.unix_time_us() / 1_000_000,
+            count_policy: options.count_policy,
+            mac_map: BTreeMap::new(),
+            ap_inventory: ApInventory::new(),
+            associations: AssociationTable::new(),
+            probed_ssids: options.probed_ssids.privacy(options.device_id).map(ProbedSsids::new),
+        }
+    }
+
+    fn elapsed_ms(&self, clock: &impl Clock) -> u64 {
+        clock.now_ms().saturating_sub(self.started_ms)
+    }
+
+    fn observe(&mut self, observation: &Observation, now_ms: u32) {
+        stats::record_observation(&mut self.mac_map, observation, now_ms);
+        self.ap_inventory.observe(observation, now_ms);
+        self.associations.observe(observation, now_ms);
+        if let Some(probed_ssids) = &mut self.probed_ssids {
+            probed_ssids.observe(observation);
+        }
+    }
+
+    fn devices(&self) -> usize {
+        self.mac_map.keys().filter(|mac| self.count_policy.counts_mac(mac)).count()
+    }
+
+    fn log_summary(&self) {
+        let transmitters = self.mac_map.values().filter(|stats| stats.seen_as_transmitter()).count();
+        info!("Last window: {} unique MAC addresses ({} transmitting)", self.mac_map.len(), transmitters);
+        info!("Found {} access points, {} station associations", self.ap_inventory.len(), self.associations.len());
+        if let Some(probed_ssids) = &self.probed_ssids {
+            info!("{} devices sent directed probe requests", probed_ssids.len());
+            // Hashed SSIDs mean nothing in a log, only list names kept in clear text
+            if !probed_ssids.is_empty() && probed_ssids.privacy() == SsidPrivacy::Clear {
+                for (mac, ssids) in probed_ssids.iter() {
+                    let names: Vec<String> = ssids.iter()
+                        .map(|entry| format!("{} ({})", String::from_utf8_lossy(&entry.ssid), entry.count))
+                        .collect();
+                    debug!("  {:02x?} probed for {}", mac, names.join(", "));
+                }
+            }
+        }
+        if !self.associations.is_empty() {
+            info!("Stations per network:");
+            for (bssid, stations) in self.associations.stations_by_bssid() {
+                let ssid = self.ap_inventory.get(&bssid).map(|access_point| access_point.ssid()).unwrap_or_default();
+                info!("  {:02x?} {}: {} stations", bssid, ssid, stations.len());
+                for station in stations {
+                    debug!("    {:02x?}", station);
+                }
+            }
+        }
+    }
+
+    fn scan_file(&self, options: &ScanOptions, channel_plan: &ChannelPlan, duration_ms: u64) -> Vec<u8> {
+        let header = ScanHeader {
+            start_time_s: self.started_unix_s,
+            duration_ms: duration_ms as u32,
+            device_id: options.device_id,
+            boot_count: options.boot_count,
+            firmware_version: options.firmware_version.to_string(),
+            channel_plan: build_hop_plan(channel_plan).iter().map(|step| (step.channel, step.dwell_ms)).collect(),
+        };
+        let sections = [
+            Section::new(
+                RecordType::MacStats,
+                stats::RECORD_LEN,
+                stats::serialize_records(self.mac_map.iter().filter(|(mac, _)| self.count_policy.counts_mac(mac))),
+            ),
+            Section::new(RecordType::AccessPoint, ap::RECORD_LEN, self.ap_inventory.serialize_records()),
+            Section::new(RecordType::Association, association::RECORD_LEN, self.associations.serialize_records()),
+            Section::new(
+                RecordType::ProbedSsid,
+                probe_ssid::RECORD_LEN,
+                self.probed_ssids.as_ref().map(ProbedSsids::serialize_records).unwrap_or_default(),
+            ),
+        ];
+        scan_file::write_scan_file(&header, &sections)
+    }
+}
+
+// Distinct addresses and probe clusters over the whole scan; the windows only hold their own
+struct ScanTotals {
+    macs: BTreeSet<MacAddress>,
+    clusterer: DeviceClusterer,
+    full: bool,
+}
+
+impl ScanTotals {
+    fn new() -> Self {
+        ScanTotals { macs: BTreeSet::new(), clusterer: DeviceClusterer::new(), full: false }
+    }
+
+    fn observe(&mut self, policy: &CountPolicy, observation: &Observation, now_ms: u32) {
+        let header = &observation.header;
+        for mac in [Some(header.receiver()), header.transmitter()].into_iter().flatten() {
+            if self.macs.len() < MAX_SCAN_MACS {
+                self.macs.insert(mac);
+            } else if !self.full && !self.macs.contains(&mac) {
+                warn!("Seen {} addresses, later ones are not counted", MAX_SCAN_MACS);
+                self.full = true;
+            }
+        }
+        // Only addresses that are counted may be clustered, or the estimate could go negative
+        if header.transmitter().is_some_and(|mac| self.macs.contains(&mac)) {
+            cluster_probe_request(&mut self.clusterer, policy, observation, now_ms);
+        }
+    }
+
+    fn devices(&self, policy: &CountPolicy) -> usize {
+        self.macs.iter().filter(|mac| policy.counts_mac(mac)).count()
+    }
+
+    fn log_summary(&self, summary: &AddressSummary, estimated: usize) {
+        info!("Found {} unique MAC addresses{}", self.macs.len(), if self.full { " (limit reached)" } else { "" });
+        info!("Estimated {} physical devices ({} randomized MACs in {} probe clusters)",
+            estimated,
+            self.clusterer.clustered_macs(),
+            self.clusterer.clusters().len()
+        );
+        info!("Devices: {}, universal: {}, local: {}, multicast: {}, broadcast: {}, null: {}",
+            summary.devices,
+            summary.unicast_universal,
+            summary.unicast_local,
+            summary.multicast,
+            summary.broadcast,
+            summary.null
+        );
+    }
+}
+
+// Writes scan files, deleting the oldest file of this session when storage is full. Without a
+// wall clock every boot starts at the same time, so files are named by boot count and sequence.
+struct ScanSaver {
+    boot_count: u32,
+    next_seq: u32,
+    files: VecDeque<String>,
+    files_deleted: u32,
+}
+
+impl ScanSaver {
+    // Continues after the files already saved under this boot count; the RTC counter starts
+    // over after a power cycle, so earlier scans may have used it
+    fn new(storage: &impl Storage, boot_count: u32) -> Result<Self> {
+        let prefix = format!("scan_{}_", boot_count);
+        let next_seq = storage.list()?.iter()
+            .filter_map(|name| name.strip_prefix(&prefix)?.strip_suffix(".bin")?.parse::<u32>().ok())
+            .max()
+            .map_or(0, |seq| seq + 1);
+        Ok(ScanSaver { boot_count, next_seq, files: VecDeque::new(), files_deleted: 0 })
+    }
+
+    fn save(&mut self, storage: &mut impl Storage, data: &[u8]) -> Result<String> {
+        while !storage.has_enough_space(data.len())? {
+            let Some(oldest) = self.files.pop_front() else {
+                return Err(anyhow::anyhow!("Not enough space"));
+            };
+            info!("Storage full, deleting {}", oldest);
+            storage.delete(&oldest)?;
+            self.files_deleted += 1;
+        }
+
+        let name = format!("scan_{}_{:04}.bin", self.boot_count, self.next_seq);
+        storage.write(&name, data)?;
+        self.next_seq += 1;
+        self.files.push_back(name.clone());
+        Ok(name)
+    }
+}
+
+fn time_label(duration: &ScanDuration, elapsed: Duration) -> String {
+    match duration.as_duration() {
+        Some(total) => format!("Time left: {}", format_duration(total.saturating_sub(elapsed).as_secs())),
+        None => format!("Elapsed: {}", format_duration(elapsed.as_secs())),
+    }
+}
+
+// Consumes the packet source so reception ends with the scan. `stop_requested` is asked every
+// pass whether something other than the button, like a console command, ended the scan.
+pub fn run_scan(
+    board: &mut Board<impl Screen, impl InputDevice, impl Clock>,
+    mut packets: impl PacketSource,
+    storage: &mut impl Storage,
+    options: &ScanOptions,
+    channel_plan: &ChannelPlan,
+    mut stop_requested: impl FnMut() -> bool,
+) -> Result<ScanReport> {
+    let start_ms = board.clock.now_ms();
+    let window = options.checkpoint.as_duration();
+    let mut last_check_in_ms = start_ms;
+    let mut hopper = ChannelHopper::new(channel_plan);
+    let mut results = ScanResults::new(&board.clock, options);
+    let mut totals = ScanTotals::new();
+    let mut saver = ScanSaver::new(storage, options.boot_count)?;
+
+    info!("Scanning for {}, continuous save: {}", options.duration.label(), options.checkpoint.label());
+    loop {
+        let elapsed = Duration::from_millis(board.clock.now_ms() - start_ms);
+        if options.duration.as_duration().is_some_and(|duration| elapsed >= duration) {
+            break;
+        }
+
+        // Move to the next channel once the current dwell is over
+        if let Some(channel) = hopper.poll(elapsed.as_millis() as u64) {
+            if let Err(e) = packets.set_channel(channel) {
+                error!("{}", e);
+            }
+        }
+        // Drain every observation queued since the last pass
+        let now_ms = results.elapsed_ms(&board.clock) as u32;
+        while let Some(observation) = packets.next_observation() {
+            // Addresses in frames with a bad FCS cannot be trusted
+            if !observation.meta.fcs_failed && observation.rssi_at_least(options.min_rssi_dbm) {
+                results.observe(&observation, now_ms);
+                totals.observe(&options.count_policy, &observation, now_ms);
+            }
+        }
+
+        if board.input.poll() == ButtonEvent::LongPress {
+            info!("Scan stopped by long press after {}", format_duration(elapsed.as_secs()));
+            break;
+        }
+        if stop_requested() {
+            info!("Scan stopped from the console after {}", format_duration(elapsed.as_secs()));
+            break;
+        }
+
+        // Continuous mode: save this window and start a fresh one
+        let window_over = window.is_some_and(|window| results.elapsed_ms(&board.clock) >= window.as_millis() as u64);
+        if window_over || results.mac_map.len() >= MAX_WINDOW_MACS {
+            if !window_over {
+                info!("{} addresses in this window, saving it early", results.mac_map.len());
+            }
+            let data = results.scan_file(options, channel_plan, results.elapsed_ms(&board.clock));
+            match saver.save(storage, &data) {
+                Ok(name) => info!("Checkpoint: {} devices saved to {}", results.devices(), name),
+                Err(e) => error!("Checkpoint failed: {}", e),
+            }
+            results = ScanResults::new(&board.clock, options);
+        }
+
+        if board.clock.now_ms() - last_check_in_ms >= STATUS_INTERVAL_MS {
+            let channel = hopper.current_channel().unwrap_or(0);
+            let devices = totals.devices(&options.count_policy);
+            let estimated = totals.clusterer.estimated_devices(devices);
+            let dropped = packets.dropped();
+            let time = time_label(&options.duration, elapsed);
+            info!("{}, Devices: {} (est. {}), Unique MACs: {}, APs: {}, Channel: {}, Dropped: {}",
+                time,
+                devices,
+                estimated,
+                totals.macs.len(),
+                results.ap_inventory.len(),
+                channel,
+                dropped
+            );
+            ui::draw_status_update(&mut board.screen, &time, devices, estimated, channel, dropped)?;
+            last_check_in_ms = board.clock.now_ms();
+        }
+        board.clock.delay_ms(SCAN_LOOP_DELAY_MS);
+    }
+
+    let duration_ms = results.elapsed_ms(&board.clock);
+    info!("{} observations dropped", packets.dropped());
+    drop(packets);
+
+    let summary = AddressSummary::from_macs(&totals.macs, &options.count_policy);
+    let estimated = totals.clusterer.estimated_devices(summary.devices);
+    totals.log_summary(&summary, estimated);
+    results.log_summary();
+    let vendors = oui::vendor_counts(totals.macs.iter().filter(|mac| options.count_policy.counts_mac(mac)));
+    info!("Vendors: {}", oui::format_top_vendors(&vendors, usize::MAX));
+    ui::draw_final_count(&mut board.screen, &summary, estimated, &oui::format_top_vendors(&vendors, FINAL_SCREEN_CHARS))?;
+    board.clock.delay_ms(FINAL_SCREEN_MS);
+
+    // Save MAC addresses to a file, rotating out older windows of this scan if needed
+    info!("Attempting to save MAC addresses");
+    let data = results.scan_file(options, channel_plan, duration_ms);
+    match saver.save(storage, &data) {
+        Ok(name) => {
+            info!("Successfully saved {} MAC addresses to {}", results.devices(), name);
+            let message = if saver.files.len() > 1 {
+                format!("Saved {} files", saver.files.len())
+            } else {
+                "MAC data saved".to_string()
+            };
+            board.screen.draw_text(5, 53, &message, true)?;
+        },
+        Err(e) => {
+            error!("Failed to save MAC addresses: {}", e);
+            board.screen.draw_text(5, 53, "Save failed", true)?;
+        }
+    }
+    if saver.files_deleted > 0 {
+        info!("{} older checkpoint files were deleted to make room", saver.files_deleted);
+    }
+    board.screen.flush()?;
+
+    info!("Scan finished after {}", format_duration((board.clock.now_ms() - start_ms) / 1000));
+    Ok(ScanReport { devices: summary.devices, files: saver.files.into(), access_points: results.ap_inventory })
+}
+
+// Randomized probe requests are grouped by fingerprint so rotating MACs count once
+fn cluster_probe_request(clusterer: &mut DeviceClusterer, policy: &CountPolicy, observation: &Observation, now_ms: u32) {
+    let header = &observation.header;
+    if header.frame_type() != FrameType::Management || header.subtype() != ie::SUBTYPE_PROBE_REQUEST {
+        return;
+    }
+    let (Some(transmitter), Some(sequence)) = (header.transmitter(), header.sequence_control) else {
+        return;
+    };
+    let class = mac_addr::classify(&transmitter);
+    if class.kind != AddressKind::Unicast || !class.locally_administered || !policy.counts(&class) {
+        return;
+    }
+    let fingerprint = fingerprint::probe_fingerprint(observation.body());
+    clusterer.observe_probe(transmitter, fingerprint, sequence.sequence_number(), now_ms);
+}
@@ -0,0 +1,127 @@
+// How long a scan runs and how often a continuous scan checkpoints its results
+
+use alloc::{format, string::{String, ToString}};
+use core::time::Duration;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ScanDuration {
+    Seconds(u32),
+    // Runs until the button is held
+    UntilStopped,
+}
+
+impl ScanDuration {
+    pub const ALL: [ScanDuration; 9] = [
+        ScanDuration::Seconds(10),
+        ScanDuration::Seconds(30),
+        ScanDuration::Seconds(60),
+        ScanDuration::Seconds(5 * 60),
+        ScanDuration::Seconds(15 * 60),
+        ScanDuration::Seconds(60 * 60),
+        ScanDuration::Seconds(6 * 60 * 60),
+        ScanDuration::Seconds(24 * 60 * 60),
+        ScanDuration::UntilStopped,
+    ];
+
+    pub const DEFAULT: ScanDuration = ScanDuration::Seconds(30);
+
+    pub fn label(&self) -> String {
+        match self {
+            ScanDuration::Seconds(secs) => format_duration(*secs as u64),
+            ScanDuration::UntilStopped => "Until stopped".to_string(),
+        }
+    }
+
+    pub fn next(&self) -> ScanDuration {
+        let idx = Self::ALL.iter().position(|duration| duration == self).unwrap_or(0);
+        Self::ALL[(idx + 1) % Self::ALL.len()]
+    }
+
+    // Stable id used when the duration is persisted
+    pub fn id(&self) -> u8 {
+        Self::ALL.iter().position(|duration| duration == self).unwrap_or(0) as u8
+    }
+
+    pub fn from_id(id: u8) -> Option<ScanDuration> {
+        Self::ALL.get(id as usize).copied()
+    }
+
+    pub fn as_duration(&self) -> Option<Duration> {
+        match self {
+            ScanDuration::Seconds(secs) => Some(Duration::from_secs(*secs as u64)),
+            ScanDuration::UntilStopped => None,
+        }
+    }
+}
+
+// Continuous mode: results are saved and reset at the end of every window, so a long scan
+// leaves a series of files instead of one that is lost if power goes away
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CheckpointWindow {
+    Off,
+    Seconds(u32),
+}
+
+impl CheckpointWindow {
+    pub const ALL: [CheckpointWindow; 5] = [
+        CheckpointWindow::Off,
+        CheckpointWindow::Seconds(60),
+        CheckpointWindow::Seconds(5 * 60),
+        CheckpointWindow::Seconds(15 * 60),
+        CheckpointWindow::Seconds(60 * 60),
+    ];
+
+    pub fn label(&self) -> String {
+        match self {
+            CheckpointWindow::Off => "Off".to_string(),
+            CheckpointWindow::Seconds(secs) => format!("Every {}", format_duration(*secs as u64)),
+        }
+    }
+
+    pub fn next(&self) -> CheckpointWindow {
+        let idx = Self::ALL.iter().position(|window| window == self).unwrap_or(0);
+        Self::ALL[(idx + 1) % Self::ALL.len()]
+    }
+
+    pub fn id(&self) -> u8 {
+        Self::ALL.iter().position(|window| window == self).unwrap_or(0) as u8
+    }
+
+    pub fn from_id(id: u8) -> Option<CheckpointWindow> {
+        Self::ALL.get(id as usize).copied()
+    }
+
+    pub fn as_duration(&self) -> Option<Duration> {
+        match self {
+            CheckpointWindow::Off => None,
+            CheckpointWindow::Seconds(secs) => Some(Duration::from_secs(*secs as u64)),
+        }
+    }
+}
+
+// Compact form for the display: 45s, 5m, 1h30m, 24h
+pub fn format_duration(secs: u64) -> String {
+    let (hours, minutes, seconds) = (secs / 3600, secs % 3600 / 60, secs % 60);
+    match (hours, minutes, seconds) {
+        (0, 0, s) => format!("{}s", s),
+        (0, m, 0) => format!("{}m", m),
+        (0, m, s) => format!("{}m{:02}s", m, s),
+        (h, 0, _) => format!("{}h", h),
+        (h, m, _) => format!("{}h{:02}m", h, m),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn formats_durations() {
+        assert_eq!(format_duration(45), "45s");
+        assert_eq!(format_duration(120), "2m");
+        assert_eq!(format_duration(125), "2m05s");
+        assert_eq!(format_duration(7200), "2h");
+        assert_eq!(format_duration(3 * 3600 + 5 * 60 + 9), "3h05m");
+        assert_eq!(ScanDuration::from_id(ScanDuration::DEFAULT.id()), Some(ScanDuration::DEFAULT));
+    }
+}
@@ -0,0 +1,439 @@
+// Versioned scan file format
+//
+// Layout (all integers little endian):
+//   magic "MSNF", version u16, header_len u16 (bytes from the start of the file to the first section)
+//   start_time_s u64, duration_ms u32, device_id [u8; 6], boot_count u32, firmware_version [u8; 16]
+//   hop_count u8, hop_count x (channel u8, dwell_ms u32)
+//   section_count u16
+// followed by section_count sections of
+//   record_type u8, reserved u8, record_len u16, record_count u32, record_count x record_len bytes
+//
+// Readers skip sections whose record type they do not know, and fields appended to the
+// header in later versions are skipped via header_len. Files without the magic are the
+// headerless lists of 6-byte MACs written by older firmware.
+
+use alloc::{string::{String, ToString}, vec, vec::Vec};
+
+use crate::frame::MacAddress;
+
+pub const MAGIC: [u8; 4] = *b"MSNF";
+pub const VERSION: u16 = 1;
+const FIRMWARE_VERSION_LEN: usize = 16;
+const SECTION_HEADER_LEN: usize = 8;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RecordType {
+    // 6-byte MACs from headerless files
+    LegacyMac,
+    // stats::MacStats records
+    MacStats,
+    // ap::AccessPoint records, keyed by BSSID
+    AccessPoint,
+    // association::Association records, keyed by (station, BSSID)
+    Association,
+    // probe_ssid records, keyed by (MAC, SSID)
+    ProbedSsid,
+    Unknown(u8),
+}
+
+impl RecordType {
+    pub fn id(&self) -> u8 {
+        match self {
+            RecordType::LegacyMac => 0,
+            RecordType::MacStats => 1,
+            RecordType::AccessPoint => 2,
+            RecordType::Association => 3,
+            RecordType::ProbedSsid => 4,
+            RecordType::Unknown(id) => *id,
+        }
+    }
+
+    pub fn from_id(id: u8) -> Self {
+        match id {
+            0 => RecordType::LegacyMac,
+            1 => RecordType::MacStats,
+            2 => RecordType::AccessPoint,
+            3 => RecordType::Association,
+            4 => RecordType::ProbedSsid,
+            other => RecordType::Unknown(other),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ScanHeader {
+    pub start_time_s: u64,
+    pub duration_ms: u32,
+    pub device_id: MacAddress,
+    pub boot_count: u32,
+    pub firmware_version: String,
+    // (channel, dwell_ms) in hop order
+    pub channel_plan: Vec<(u8, u32)>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Section {
+    pub record_type: RecordType,
+    pub record_len: u16,
+    pub data: Vec<u8>,
+}
+
+impl Section {
+    pub fn new(record_type: RecordType, record_len: usize, data: Vec<u8>) -> Self {
+        Section {
+            record_type,
+            record_len: record_len as u16,
+            data,
+        }
+    }
+
+    pub fn records(&self) -> impl Iterator<Item = &[u8]> {
+        self.data.chunks_exact(self.record_len.max(1) as usize)
+    }
+
+    pub fn record_count(&self) -> usize {
+        self.data.len() / self.record_len.max(1) as usize
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ScanFile {
+    // None for legacy headerless files
+    pub header: Option<ScanHeader>,
+    pub version: u16,
+    pub sections: Vec<Section>,
+}
+
+impl ScanFile {
+    pub fn section(&self, record_type: RecordType) -> Option<&Section> {
+        self.sections.iter().find(|section| section.record_type == record_type)
+    }
+
+    // Every MAC in the file, whichever MAC-keyed record type it was saved with
+    pub fn mac_addresses(&self) -> Vec<MacAddress> {
+        self.sections.iter()
+            .filter(|section| matches!(section.record_type, RecordType::LegacyMac | RecordType::MacStats))
+            .flat_map(|section| section.records())
+            .filter(|record| record.len() >= 6)
+            .map(|record| {
+                let mut mac = [0u8; 6];
+                mac.copy_from_slice(&record[..6]);
+                mac
+            })
+            .collect()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ScanFileError {
+    Truncated { offset: usize },
+    UnsupportedVersion(u16),
+    BadRecordLength { record_type: u8, record_len: u16 },
+    NotLegacyLength(usize),
+}
+
+impl core::fmt::Display for ScanFileError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            ScanFileError::Truncated { offset } => write!(f, "scan file truncated at byte {}", offset),
+            ScanFileError::UnsupportedVersion(v) => write!(f, "unsupported scan file version {}", v),
+            ScanFileError::BadRecordLength { record_type, record_len } => {
+                write!(f, "invalid record length {} for record type {}", record_len, record_type)
+            },
+            ScanFileError::NotLegacyLength(len) => {
+                write!(f, "headerless file of {} bytes is not a list of 6-byte MACs", len)
+            },
+        }
+    }
+}
+
+impl core::error::Error for ScanFileError {}
+
+pub fn write_scan_file(header: &ScanHeader, sections: &[Section]) -> Vec<u8> {
+    let mut out = Vec::new();
+    out.extend_from_slice(&MAGIC);
+    out.extend_from_slice(&VERSION.to_le_bytes());
+    // header_len is patched once the variable length channel plan is written
+    out.extend_from_slice(&0u16.to_le_bytes());
+    out.extend_from_slice(&header.start_time_s.to_le_bytes());
+    out.extend_from_slice(&header.duration_ms.to_le_bytes());
+    out.extend_from_slice(&header.device_id);
+    out.extend_from_slice(&header.boot_count.to_le_bytes());
+
+    let mut firmware_version = [0u8; FIRMWARE_VERSION_LEN];
+    let version_bytes = header.firmware_version.as_bytes();
+    let version_len = version_bytes.len().min(FIRMWARE_VERSION_LEN);
+    firmware_version[..version_len].copy_from_slice(&version_bytes[..version_len]);
+    out.extend_from_slice(&firmware_version);
+
+    let hops = &header.channel_plan[..header.channel_plan.len().min(u8::MAX as usize)];
+    out.push(hops.len() as u8);
+    for (channel, dwell_ms) in hops {
+        out.push(*channel);
+        out.extend_from_slice(&dwell_ms.to_le_bytes());
+    }
+    out.extend_from_slice(&(sections.len() as u16).to_le_bytes());
+
+    let header_len = out.len() as u16;
+    out[6..8].copy_from_slice(&header_len.to_le_bytes());
+
+    for section in sections {
+        out.reserve(SECTION_HEADER_LEN + section.data.len());
+        out.push(section.record_type.id());
+        out.push(0);
+        out.extend_from_slice(&section.record_len.to_le_bytes());
+        out.extend_from_slice(&(section.record_count() as u32).to_le_bytes());
+        out.extend_from_slice(&section.data[..section.record_count() * section.record_len as usize]);
+    }
+
+    out
+}
+
+struct Cursor<'a> {
+    buf: &'a [u8],
+    offset: usize,
+}
+
+impl<'a> Cursor<'a> {
+    fn take(&mut self, len: usize) -> Result<&'a [u8], ScanFileError> {
+        if self.buf.len() - self.offset < len {
+            return Err(ScanFileError::Truncated { offset: self.offset });
+        }
+        let bytes = &self.buf[self.offset..self.offset + len];
+        self.offset += len;
+        Ok(bytes)
+    }
+
+    fn u8(&mut self) -> Result<u8, ScanFileError> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn u16(&mut self) -> Result<u16, ScanFileError> {
+        let bytes = self.take(2)?;
+        Ok(u16::from_le_bytes([bytes[0], bytes[1]]))
+    }
+
+    fn u32(&mut self) -> Result<u32, ScanFileError> {
+        let mut bytes = [0u8; 4];
+        bytes.copy_from_slice(self.take(4)?);
+        Ok(u32::from_le_bytes(bytes))
+    }
+
+    fn u64(&mut self) -> Result<u64, ScanFileError> {
+        let mut bytes = [0u8; 8];
+        bytes.copy_from_slice(self.take(8)?);
+        Ok(u64::from_le_bytes(bytes))
+    }
+}
+
+pub fn read_scan_file(buf: &[u8]) -> Result<ScanFile, ScanFileError> {
+    if buf.len() < MAGIC.len() || buf[..MAGIC.len()] != MAGIC {
+        return read_legacy(buf);
+    }
+
+    let mut cursor = Cursor { buf, offset: MAGIC.len() };
+    let version = cursor.u16()?;
+    if version == 0 || version > VERSION {
+        return Err(ScanFileError::UnsupportedVersion(version));
+    }
+    let header_len = cursor.u16()? as usize;
+
+    let start_time_s = cursor.u64()?;
+    let duration_ms = cursor.u32()?;
+    let mut device_id = [0u8; 6];
+    device_id.copy_from_slice(cursor.take(6)?);
+    let boot_count = cursor.u32()?;
+    let firmware_version = cursor.take(FIRMWARE_VERSION_LEN)?;
+    let firmware_version = String::from_utf8_lossy(firmware_version)
+        .trim_end_matches('\0')
+        .to_string();
+
+    let hop_count = cursor.u8()?;
+    let mut channel_plan = Vec::with_capacity(hop_count as usize);
+    for _ in 0..hop_count {
+        let channel = cursor.u8()?;
+        let dwell_ms = cursor.u32()?;
+        channel_plan.push((channel, dwell_ms));
+    }
+    let section_count = cursor.u16()?;
+
+    // Skip any header fields added by newer minor revisions
+    if header_len < cursor.offset {
+        return Err(ScanFileError::Truncated { offset: header_len });
+    }
+    cursor.take(header_len - cursor.offset)?;
+
+    let mut sections = Vec::with_capacity(section_count as usize);
+    for _ in 0..section_count {
+        let record_type = cursor.u8()?;
+        let _reserved = cursor.u8()?;
+        let record_len = cursor.u16()?;
+        let record_count = cursor.u32()? as usize;
+        if record_len == 0 {
+            return Err(ScanFileError::BadRecordLength { record_type, record_len });
+        }
+        let data_len = record_count
+            .checked_mul(record_len as usize)
+            .ok_or(ScanFileError::Truncated { offset: cursor.offset })?;
+        let data = cursor.take(data_len)?.to_vec();
+        sections.push(Section {
+            record_type: RecordType::from_id(record_type),
+            record_len,
+            data,
+        });
+    }
+
+    // Sanity check the record types whose layout we know about
+    for section in &sections {
+        let min_len = match section.record_type {
+            RecordType::MacStats => crate::stats::RECORD_LEN,
+            RecordType::AccessPoint => crate::ap::RECORD_LEN,
+            RecordType::Association => crate::association::RECORD_LEN,
+            RecordType::ProbedSsid => crate::probe_ssid::RECORD_LEN,
+            _ => continue,
+        };
+        if (section.record_len as usize) < min_len {
+            return Err(ScanFileError::BadRecordLength {
+                record_type: section.record_type.id(),
+                record_len: section.record_len,
+            });
+        }
+    }
+
+    Ok(ScanFile {
+        header: Some(ScanHeader {
+            start_time_s,
+            duration_ms,
+            device_id,
+            boot_count,
+            firmware_version,
+            channel_plan,
+        }),
+        version,
+        sections,
+    })
+}
+
+fn read_legacy(buf: &[u8]) -> Result<ScanFile, ScanFileError> {
+    if buf.len() % 6 != 0 {
+        return Err(ScanFileError::NotLegacyLength(buf.len()));
+    }
+    Ok(ScanFile {
+        header: None,
+        version: 0,
+        sections: vec![Section::new(RecordType::LegacyMac, 6, buf.to_vec())],
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn header() -> ScanHeader {
+        ScanHeader {
+            start_time_s: 1_700_000_000,
+            duration_ms: 30_000,
+            device_id: [2, 0, 0, 0, 0, 1],
+            boot_count: 3,
+            firmware_version: "0.1.0".to_string(),
+            channel_plan: vec![(1, 500), (6, 250)],
+        }
+    }
+
+    #[test]
+    fn round_trip() {
+        let sections = vec![
+            Section::new(RecordType::MacStats, 40, vec![7; 80]),
+            Section::new(RecordType::Unknown(42), 3, vec![1, 2, 3]),
+        ];
+        let file = read_scan_file(&write_scan_file(&header(), &sections)).unwrap();
+        assert_eq!(file.version, VERSION);
+        assert_eq!(file.header, Some(header()));
+        assert_eq!(file.sections, sections);
+        assert_eq!(file.section(RecordType::MacStats).unwrap().record_count(), 2);
+    }
+
+    #[test]
+    fn round_trip_every_record_type() {
+        let pattern = |len: usize, count: usize| (0..len * count).map(|idx| (idx * 31) as u8).collect::<Vec<u8>>();
+        let sections = vec![
+            Section::new(RecordType::MacStats, crate::stats::RECORD_LEN, pattern(crate::stats::RECORD_LEN, 3)),
+            Section::new(RecordType::AccessPoint, crate::ap::RECORD_LEN, pattern(crate::ap::RECORD_LEN, 2)),
+            Section::new(RecordType::Association, crate::association::RECORD_LEN, Vec::new()),
+            Section::new(RecordType::ProbedSsid, crate::probe_ssid::RECORD_LEN, pattern(crate::probe_ssid::RECORD_LEN, 1)),
+        ];
+        let no_hops = ScanHeader { channel_plan: Vec::new(), ..header() };
+        let file = read_scan_file(&write_scan_file(&no_hops, &sections)).unwrap();
+        assert_eq!(file.mac_addresses().len(), 3);
+        assert_eq!((file.header, file.sections), (Some(no_hops), sections));
+
+        // Only the first 16 bytes of the firmware version are kept
+        let long = ScanHeader { firmware_version: "0.1.0-rc.1+g1234567890".to_string(), ..header() };
+        let file = read_scan_file(&write_scan_file(&long, &[])).unwrap();
+        assert_eq!(file.header.unwrap().firmware_version, "0.1.0-rc.1+g1234");
+    }
+
+    #[test]
+    fn newer_header_fields_are_skipped() {
+        let sections = [Section::new(RecordType::MacStats, 40, vec![7; 40])];
+        let mut data = write_scan_file(&header(), &sections);
+        // Four extra header bytes a later revision might add before the sections
+        let header_len = u16::from_le_bytes([data[6], data[7]]) as usize;
+        data.splice(header_len..header_len, [0xaa; 4]);
+        data[6..8].copy_from_slice(&(header_len as u16 + 4).to_le_bytes());
+        assert_eq!(read_scan_file(&data).unwrap().sections, sections);
+    }
+
+    #[test]
+    fn rejects_bad_magic() {
+        let mut data = write_scan_file(&header(), &[Section::new(RecordType::MacStats, 40, vec![7; 40])]);
+        data[3] = b'G';
+        // Without the magic the file can only be a headerless MAC list, which this is not
+        assert_ne!(data.len() % 6, 0);
+        assert_eq!(read_scan_file(&data), Err(ScanFileError::NotLegacyLength(data.len())));
+    }
+
+    #[test]
+    fn rejects_unknown_version() {
+        let mut data = write_scan_file(&header(), &[]);
+        data[4..6].copy_from_slice(&(VERSION + 1).to_le_bytes());
+        assert_eq!(read_scan_file(&data), Err(ScanFileError::UnsupportedVersion(VERSION + 1)));
+        data[4..6].copy_from_slice(&0u16.to_le_bytes());
+        assert_eq!(read_scan_file(&data), Err(ScanFileError::UnsupportedVersion(0)));
+    }
+
+    #[test]
+    fn rejects_truncated_sections() {
+        let sections = [
+            Section::new(RecordType::MacStats, 40, vec![7; 80]),
+            Section::new(RecordType::Unknown(42), 3, vec![1, 2, 3]),
+        ];
+        let data = write_scan_file(&header(), &sections);
+        let header_len = u16::from_le_bytes([data[6], data[7]]) as usize;
+        let second_section = header_len + SECTION_HEADER_LEN + 80;
+
+        // Cut in the middle of the first section's records, and inside the second section header
+        let cut = header_len + SECTION_HEADER_LEN + 50;
+        assert_eq!(read_scan_file(&data[..cut]), Err(ScanFileError::Truncated { offset: header_len + SECTION_HEADER_LEN }));
+        assert_eq!(read_scan_file(&data[..second_section + 5]), Err(ScanFileError::Truncated { offset: second_section + 4 }));
+
+        // A record count that claims more records than the file holds
+        let mut data = data;
+        data[header_len + 4..header_len + 8].copy_from_slice(&3u32.to_le_bytes());
+        assert!(matches!(read_scan_file(&data), Err(ScanFileError::Truncated { .. })));
+    }
+
+    #[test]
+    fn legacy_files() {
+        let file = read_scan_file(&[1; 12]).unwrap();
+        assert_eq!(file.header, None);
+        assert_eq!(file.mac_addresses(), vec![[1; 6], [1; 6]]);
+        assert!(read_scan_file(&[1; 7]).is_err());
+    }
+
+    #[test]
+    fn truncated_file() {
+        let data = write_scan_file(&header(), &[Section::new(RecordType::MacStats, 40, vec![7; 80])]);
+        assert!(read_scan_file(&data[..data.len() - 1]).is_err());
+    }
+}
@@ -0,0 +1,176 @@
+// Scan profiles: which frames the radio delivers and which ones the callback keeps
+// Hardware filtering (frame classes) happens in the Wi-Fi driver; the subtype filter runs in
+// the promiscuous callback before anything is queued.
+
+use crate::frame::{FrameType, MacHeader};
+use crate::ie::{SUBTYPE_BEACON, SUBTYPE_PROBE_REQUEST};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ScanProfile {
+    // Everything the radio can hand us, including control frames and frames with a bad FCS
+    Full,
+    // Probe requests only: a low-noise count of nearby client devices
+    Presence,
+    // Beacons only: nearby access points
+    Beacons,
+    Management,
+    Data,
+    Control,
+}
+
+// Promiscuous filter bits, the same values as WIFI_PROMIS_FILTER_MASK_* and
+// WIFI_PROMIS_CTRL_FILTER_MASK_ALL in esp_wifi_types.h
+pub const FILTER_MASK_MGMT: u32 = 1;
+pub const FILTER_MASK_CTRL: u32 = 1 << 1;
+pub const FILTER_MASK_DATA: u32 = 1 << 2;
+pub const FILTER_MASK_FCSFAIL: u32 = 1 << 6;
+pub const CTRL_FILTER_MASK_ALL: u32 = 0xFF80_0000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FrameClasses {
+    pub management: bool,
+    pub control: bool,
+    pub data: bool,
+    pub fcs_failed: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SubtypeFilter {
+    All,
+    Only(FrameType, u8),
+}
+
+impl ScanProfile {
+    pub const ALL: [ScanProfile; 6] = [
+        ScanProfile::Full,
+        ScanProfile::Presence,
+        ScanProfile::Beacons,
+        ScanProfile::Management,
+        ScanProfile::Data,
+        ScanProfile::Control,
+    ];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            ScanProfile::Full => "Full",
+            ScanProfile::Presence => "Presence",
+            ScanProfile::Beacons => "Beacons",
+            ScanProfile::Management => "Mgmt only",
+            ScanProfile::Data => "Data only",
+            ScanProfile::Control => "Ctrl only",
+        }
+    }
+
+    pub fn next(&self) -> ScanProfile {
+        let idx = Self::ALL.iter().position(|profile| profile == self).unwrap_or(0);
+        Self::ALL[(idx + 1) % Self::ALL.len()]
+    }
+
+    // Stable id used when the profile is persisted
+    pub fn id(&self) -> u8 {
+        Self::ALL.iter().position(|profile| profile == self).unwrap_or(0) as u8
+    }
+
+    pub fn from_id(id: u8) -> Option<ScanProfile> {
+        Self::ALL.get(id as usize).copied()
+    }
+
+    pub fn frame_classes(&self) -> FrameClasses {
+        let none = FrameClasses { management: false, control: false, data: false, fcs_failed: false };
+        match self {
+            ScanProfile::Full => FrameClasses { management: true, control: true, data: true, fcs_failed: true },
+            ScanProfile::Presence | ScanProfile::Beacons | ScanProfile::Management => FrameClasses { management: true, ..none },
+            ScanProfile::Data => FrameClasses { data: true, ..none },
+            ScanProfile::Control => FrameClasses { control: true, ..none },
+        }
+    }
+
+    pub fn subtype_filter(&self) -> SubtypeFilter {
+        match self {
+            ScanProfile::Presence => SubtypeFilter::Only(FrameType::Management, SUBTYPE_PROBE_REQUEST),
+            ScanProfile::Beacons => SubtypeFilter::Only(FrameType::Management, SUBTYPE_BEACON),
+            _ => SubtypeFilter::All,
+        }
+    }
+}
+
+impl FrameClasses {
+    // Mask for esp_wifi_set_promiscuous_filter
+    pub fn filter_mask(&self) -> u32 {
+        [
+            (self.management, FILTER_MASK_MGMT),
+            (self.control, FILTER_MASK_CTRL),
+            (self.data, FILTER_MASK_DATA),
+            (self.fcs_failed, FILTER_MASK_FCSFAIL),
+        ]
+        .iter()
+        .filter(|(enabled, _)| *enabled)
+        .fold(0, |mask, (_, bit)| mask | bit)
+    }
+
+    // Mask for esp_wifi_set_promiscuous_ctrl_filter. Control frames additionally need their own
+    // subtype filter, otherwise none are delivered.
+    pub fn ctrl_filter_mask(&self) -> Option<u32> {
+        self.control.then_some(CTRL_FILTER_MASK_ALL)
+    }
+}
+
+impl SubtypeFilter {
+    pub fn accepts(&self, header: &MacHeader) -> bool {
+        match self {
+            SubtypeFilter::All => true,
+            SubtypeFilter::Only(frame_type, subtype) => header.frame_type() == *frame_type && header.subtype() == *subtype,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::frame::parse_header;
+
+    fn header(frame_control: u8) -> MacHeader {
+        let mut frame = [0u8; 24];
+        frame[0] = frame_control;
+        parse_header(&frame).unwrap()
+    }
+
+    #[test]
+    fn subtype_filter_keeps_one_subtype() {
+        let probe_request = header(0x40);
+        let beacon = header(0x80);
+        let data = header(0x08);
+        let ack = header(0xd4);
+        let accepted = |profile: ScanProfile| {
+            [probe_request, beacon, data, ack].map(|header| profile.subtype_filter().accepts(&header))
+        };
+        assert_eq!(accepted(ScanProfile::Presence), [true, false, false, false]);
+        assert_eq!(accepted(ScanProfile::Beacons), [false, true, false, false]);
+        assert_eq!(accepted(ScanProfile::Full), [true; 4]);
+        // The driver already dropped the other classes
+        assert_eq!(accepted(ScanProfile::Data), [true; 4]);
+    }
+
+    #[test]
+    fn profiles_map_to_driver_filters() {
+        let filters = |profile: ScanProfile| {
+            let classes = profile.frame_classes();
+            (classes.filter_mask(), classes.ctrl_filter_mask())
+        };
+        assert_eq!(filters(ScanProfile::Full), (0x47, Some(CTRL_FILTER_MASK_ALL)));
+        for profile in [ScanProfile::Presence, ScanProfile::Beacons, ScanProfile::Management] {
+            assert_eq!(filters(profile), (FILTER_MASK_MGMT, None), "{}", profile.label());
+        }
+        assert_eq!(filters(ScanProfile::Data), (FILTER_MASK_DATA, None));
+        assert_eq!(filters(ScanProfile::Control), (FILTER_MASK_CTRL, Some(CTRL_FILTER_MASK_ALL)));
+    }
+
+    #[test]
+    fn profile_choices() {
+        for profile in ScanProfile::ALL {
+            assert_eq!(ScanProfile::from_id(profile.id()), Some(profile));
+        }
+        assert_eq!(ScanProfile::Control.next(), ScanProfile::Full);
+        assert_eq!(ScanProfile::from_id(ScanProfile::ALL.len() as u8), None);
+    }
+}
@@ -0,0 +1,158 @@
+// Scheduled scans: the device sleeps between scans and wakes on the RTC timer
+// The firmware counts completed cycles in RTC slow memory, see sleep.rs.
+
+use alloc::{format, string::{String, ToString}};
+use core::time::Duration;
+
+use crate::scan_duration::format_duration;
+use crate::scan_profile::ScanProfile;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ScheduleInterval {
+    Off,
+    Seconds(u32),
+}
+
+impl ScheduleInterval {
+    pub const ALL: [ScheduleInterval; 8] = [
+        ScheduleInterval::Off,
+        ScheduleInterval::Seconds(5 * 60),
+        ScheduleInterval::Seconds(15 * 60),
+        ScheduleInterval::Seconds(30 * 60),
+        ScheduleInterval::Seconds(60 * 60),
+        ScheduleInterval::Seconds(2 * 60 * 60),
+        ScheduleInterval::Seconds(6 * 60 * 60),
+        ScheduleInterval::Seconds(24 * 60 * 60),
+    ];
+
+    pub fn label(&self) -> String {
+        match self {
+            ScheduleInterval::Off => "Off".to_string(),
+            ScheduleInterval::Seconds(secs) => format!("Every {}", format_duration(*secs as u64)),
+        }
+    }
+
+    pub fn next(&self) -> ScheduleInterval {
+        let idx = Self::ALL.iter().position(|interval| interval == self).unwrap_or(0);
+        Self::ALL[(idx + 1) % Self::ALL.len()]
+    }
+
+    pub fn id(&self) -> u8 {
+        Self::ALL.iter().position(|interval| interval == self).unwrap_or(0) as u8
+    }
+
+    pub fn from_id(id: u8) -> Option<ScheduleInterval> {
+        Self::ALL.get(id as usize).copied()
+    }
+
+    pub fn as_duration(&self) -> Option<Duration> {
+        match self {
+            ScheduleInterval::Off => None,
+            ScheduleInterval::Seconds(secs) => Some(Duration::from_secs(*secs as u64)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ScheduleCycles {
+    Count(u32),
+    Unlimited,
+}
+
+impl ScheduleCycles {
+    pub const ALL: [ScheduleCycles; 7] = [
+        ScheduleCycles::Count(1),
+        ScheduleCycles::Count(4),
+        ScheduleCycles::Count(12),
+        ScheduleCycles::Count(24),
+        ScheduleCycles::Count(48),
+        ScheduleCycles::Count(96),
+        ScheduleCycles::Unlimited,
+    ];
+
+    pub const DEFAULT: ScheduleCycles = ScheduleCycles::Count(24);
+
+    pub fn label(&self) -> String {
+        match self {
+            ScheduleCycles::Count(count) => format!("{} scans", count),
+            ScheduleCycles::Unlimited => "Unlimited".to_string(),
+        }
+    }
+
+    pub fn next(&self) -> ScheduleCycles {
+        let idx = Self::ALL.iter().position(|cycles| cycles == self).unwrap_or(0);
+        Self::ALL[(idx + 1) % Self::ALL.len()]
+    }
+
+    pub fn id(&self) -> u8 {
+        Self::ALL.iter().position(|cycles| cycles == self).unwrap_or(0) as u8
+    }
+
+    pub fn from_id(id: u8) -> Option<ScheduleCycles> {
+        Self::ALL.get(id as usize).copied()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Schedule {
+    pub interval: ScheduleInterval,
+    pub cycles: ScheduleCycles,
+    pub profile: ScanProfile,
+}
+
+impl Schedule {
+    // Time to sleep before the next scheduled scan, or None once the schedule is done
+    pub fn next_wakeup(&self, completed: u32) -> Option<Duration> {
+        let remaining = match self.cycles {
+            ScheduleCycles::Count(count) => completed < count,
+            ScheduleCycles::Unlimited => true,
+        };
+        if remaining {
+            self.interval.as_duration()
+        } else {
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn schedule(interval: ScheduleInterval, cycles: ScheduleCycles) -> Schedule {
+        Schedule { interval, cycles, profile: ScanProfile::Presence }
+    }
+
+    #[test]
+    fn wakes_until_the_cycles_are_done() {
+        let every_15m = ScheduleInterval::Seconds(15 * 60);
+        let four = schedule(every_15m, ScheduleCycles::Count(4));
+        assert_eq!(four.next_wakeup(0), Some(Duration::from_secs(900)));
+        assert_eq!(four.next_wakeup(3), Some(Duration::from_secs(900)));
+        assert_eq!(four.next_wakeup(4), None);
+        assert_eq!(four.next_wakeup(5), None);
+
+        let unlimited = schedule(every_15m, ScheduleCycles::Unlimited);
+        assert_eq!(unlimited.next_wakeup(u32::MAX), Some(Duration::from_secs(900)));
+    }
+
+    #[test]
+    fn off_never_wakes() {
+        for cycles in ScheduleCycles::ALL {
+            assert_eq!(schedule(ScheduleInterval::Off, cycles).next_wakeup(0), None, "{}", cycles.label());
+        }
+    }
+
+    #[test]
+    fn choices_and_labels() {
+        for interval in ScheduleInterval::ALL {
+            assert_eq!(ScheduleInterval::from_id(interval.id()), Some(interval));
+        }
+        for cycles in ScheduleCycles::ALL {
+            assert_eq!(ScheduleCycles::from_id(cycles.id()), Some(cycles));
+        }
+        assert_eq!(ScheduleInterval::Seconds(24 * 60 * 60).next(), ScheduleInterval::Off);
+        assert_eq!(ScheduleCycles::Unlimited.next(), ScheduleCycles::Count(1));
+        assert_eq!(ScheduleCycles::Count(12).label(), "12 scans");
+    }
+}
@@ -0,0 +1,391 @@
+// Typed device settings and their persisted form
+//
+// Settings are stored as one versioned blob: a schema version byte followed by one field per
+// byte (two for the dwell time). Fields are only ever appended, so an older blob decodes with
+// defaults for the fields it lacks and a newer one with the fields this firmware knows about.
+// Unknown ids and out of range numbers fall back to defaults or are clamped, so a corrupt
+// blob still gives a usable device. `SettingsStore` keeps the blob in NVS.
+
+use alloc::{string::{String, ToString}, vec::Vec};
+
+use anyhow::Result;
+use log::error;
+
+use crate::channel_hop::{ChannelPlan, RegulatoryDomain};
+use crate::dump_protocol::DumpFormat;
+use crate::mac_addr::CountPolicy;
+use crate::menu::MenuValues;
+use crate::observation::MAX_FRAME_LEN;
+use crate::pcap::{CaptureFormat, CaptureOptions};
+use crate::probe_ssid::SsidCollection;
+use crate::scan_duration::{CheckpointWindow, ScanDuration};
+use crate::scan_profile::ScanProfile;
+use crate::schedule::{Schedule, ScheduleCycles, ScheduleInterval};
+
+pub const SCHEMA_VERSION: u8 = 2;
+// Longest blob any schema version writes
+pub const MAX_ENCODED_LEN: usize = 64;
+
+const DWELL_CHOICES_MS: [u32; 4] = [100, 250, 500, 1000];
+const DWELL_RANGE_MS: (u32, u32) = (50, 2000);
+// -100 dBm keeps everything the radio reports
+pub const MIN_RSSI_RANGE_DBM: (i8, i8) = (-100, -30);
+const SNAPLEN_CHOICES: [u32; 4] = [64, 128, 256, MAX_FRAME_LEN as u32];
+// Frames are copied out of the radio buffer at MAX_FRAME_LEN, so a longer snap length keeps nothing more
+const SNAPLEN_RANGE: (u32, u32) = (24, MAX_FRAME_LEN as u32);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Brightness {
+    Dimmest,
+    Dim,
+    Normal,
+    Bright,
+    Brightest,
+}
+
+impl Brightness {
+    pub const ALL: [Brightness; 5] = [
+        Brightness::Dimmest,
+        Brightness::Dim,
+        Brightness::Normal,
+        Brightness::Bright,
+        Brightness::Brightest,
+    ];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            Brightness::Dimmest => "Dimmest",
+            Brightness::Dim => "Dim",
+            Brightness::Normal => "Normal",
+            Brightness::Bright => "Bright",
+            Brightness::Brightest => "Brightest",
+        }
+    }
+
+    pub fn next(&self) -> Brightness {
+        let idx = Self::ALL.iter().position(|brightness| brightness == self).unwrap_or(0);
+        Self::ALL[(idx + 1) % Self::ALL.len()]
+    }
+
+    pub fn id(&self) -> u8 {
+        Self::ALL.iter().position(|brightness| brightness == self).unwrap_or(0) as u8
+    }
+
+    pub fn from_id(id: u8) -> Option<Brightness> {
+        Self::ALL.get(id as usize).copied()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Settings {
+    pub scan_profile: ScanProfile,
+    pub scan_duration: ScanDuration,
+    pub checkpoint: CheckpointWindow,
+    pub domain: RegulatoryDomain,
+    pub dwell_ms: u32,
+    // Frames weaker than this are treated as noise and not counted
+    pub min_rssi_dbm: i8,
+    pub probed_ssids: SsidCollection,
+    pub brightness: Brightness,
+    pub dump_format: DumpFormat,
+    pub schedule: Schedule,
+    // Browse the access points found once an interactive scan ends
+    pub show_ap_list: bool,
+    // Which address classes count as devices
+    pub count_policy: CountPolicy,
+    // File format and truncation of capture mode
+    pub capture: CaptureOptions,
+    pub capture_duration: ScanDuration,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Settings {
+            scan_profile: ScanProfile::Full,
+            scan_duration: ScanDuration::DEFAULT,
+            checkpoint: CheckpointWindow::Off,
+            domain: RegulatoryDomain::Etsi,
+            dwell_ms: 250,
+            min_rssi_dbm: -90,
+            probed_ssids: SsidCollection::Hashed,
+            brightness: Brightness::Normal,
+            dump_format: DumpFormat::Hex,
+            schedule: Schedule {
+                interval: ScheduleInterval::Off,
+                cycles: ScheduleCycles::DEFAULT,
+                // Presence is the cheapest profile that still counts devices
+                profile: ScanProfile::Presence,
+            },
+            show_ap_list: true,
+            count_policy: CountPolicy::DEVICES,
+            capture: CaptureOptions {
+                format: CaptureFormat::PcapNg,
+                snaplen: MAX_FRAME_LEN as u32,
+                headers_only: false,
+            },
+            capture_duration: ScanDuration::Seconds(60),
+        }
+    }
+}
+
+// Names accepted by `Settings::get` and `Settings::set`, in display order
+pub const KEYS: [&str; 18] = [
+    "scan_profile",
+    "scan_duration",
+    "checkpoint",
+    "domain",
+    "dwell_ms",
+    "min_rssi",
+    "probed_ssids",
+    "brightness",
+    "dump_format",
+    "sched_interval",
+    "sched_cycles",
+    "sched_profile",
+    "ap_list",
+    "count",
+    "cap_format",
+    "cap_snaplen",
+    "cap_headers",
+    "cap_time",
+];
+
+impl Settings {
+    pub fn channel_plan(&self) -> ChannelPlan {
+        ChannelPlan::for_domain(self.domain, self.dwell_ms)
+    }
+
+    // Clamp numeric fields into their supported ranges
+    pub fn validate(&mut self) {
+        self.dwell_ms = self.dwell_ms.clamp(DWELL_RANGE_MS.0, DWELL_RANGE_MS.1);
+        self.min_rssi_dbm = self.min_rssi_dbm.clamp(MIN_RSSI_RANGE_DBM.0, MIN_RSSI_RANGE_DBM.1);
+        self.capture.snaplen = self.capture.snaplen.clamp(SNAPLEN_RANGE.0, SNAPLEN_RANGE.1);
+    }
+
+    pub fn encode(&self) -> Vec<u8> {
+        let mut out = Vec::with_capacity(MAX_ENCODED_LEN);
+        out.push(SCHEMA_VERSION);
+        out.push(self.scan_profile.id());
+        out.push(self.scan_duration.id());
+        out.push(self.checkpoint.id());
+        out.push(self.domain.id());
+        out.extend_from_slice(&(self.dwell_ms as u16).to_le_bytes());
+        out.push(self.min_rssi_dbm as u8);
+        out.push(self.probed_ssids.id());
+        out.push(self.brightness.id());
+        out.push(self.dump_format.id());
+        out.push(self.schedule.interval.id());
+        out.push(self.schedule.cycles.id());
+        out.push(self.schedule.profile.id());
+        out.push(self.show_ap_list as u8);
+        out.push(self.count_policy.id());
+        out.push(self.capture.format.id());
+        out.extend_from_slice(&(self.capture.snaplen as u16).to_le_bytes());
+        out.push(self.capture.headers_only as u8);
+        out.push(self.capture_duration.id());
+        out
+    }
+
+    // Returns the settings and the schema version they were written with
+    pub fn decode(data: &[u8]) -> Result<(Settings, u8)> {
+        let Some((&version, fields)) = data.split_first() else {
+            return Err(anyhow::anyhow!("Empty settings blob"));
+        };
+        if version < 2 {
+            return Err(anyhow::anyhow!("Settings schema version {} is not a blob", version));
+        }
+
+        let defaults = Settings::default();
+        let byte = |idx: usize| fields.get(idx).copied();
+        let mut settings = Settings {
+            scan_profile: byte(0).and_then(ScanProfile::from_id).unwrap_or(defaults.scan_profile),
+            scan_duration: byte(1).and_then(ScanDuration::from_id).unwrap_or(defaults.scan_duration),
+            checkpoint: byte(2).and_then(CheckpointWindow::from_id).unwrap_or(defaults.checkpoint),
+            domain: byte(3).and_then(RegulatoryDomain::from_id).unwrap_or(defaults.domain),
+            dwell_ms: match (byte(4), byte(5)) {
+                (Some(low), Some(high)) => u16::from_le_bytes([low, high]) as u32,
+                _ => defaults.dwell_ms,
+            },
+            min_rssi_dbm: byte(6).map(|value| value as i8).unwrap_or(defaults.min_rssi_dbm),
+            probed_ssids: byte(7).and_then(SsidCollection::from_id).unwrap_or(defaults.probed_ssids),
+            brightness: byte(8).and_then(Brightness::from_id).unwrap_or(defaults.brightness),
+            dump_format: byte(9).and_then(DumpFormat::from_id).unwrap_or(defaults.dump_format),
+            schedule: Schedule {
+                interval: byte(10).and_then(ScheduleInterval::from_id).unwrap_or(defaults.schedule.interval),
+                cycles: byte(11).and_then(ScheduleCycles::from_id).unwrap_or(defaults.schedule.cycles),
+                profile: byte(12).and_then(ScanProfile::from_id).unwrap_or(defaults.schedule.profile),
+            },
+            show_ap_list: byte(13).map(|value| value != 0).unwrap_or(defaults.show_ap_list),
+            count_policy: byte(14).and_then(CountPolicy::from_id).unwrap_or(defaults.count_policy),
+            capture: CaptureOptions {
+                format: byte(15).and_then(CaptureFormat::from_id).unwrap_or(defaults.capture.format),
+                snaplen: match (byte(16), byte(17)) {
+                    (Some(low), Some(high)) => u16::from_le_bytes([low, high]) as u32,
+                    _ => defaults.capture.snaplen,
+                },
+                headers_only: byte(18).map(|value| value != 0).unwrap_or(defaults.capture.headers_only),
+            },
+            capture_duration: byte(19).and_then(ScanDuration::from_id).unwrap_or(defaults.capture_duration),
+        };
+        settings.validate();
+        Ok((settings, version))
+    }
+
+    pub fn get(&self, key: &str) -> Option<String> {
+        let value = match key {
+            "scan_profile" => self.scan_profile.label().to_string(),
+            "scan_duration" => self.scan_duration.label(),
+            "checkpoint" => self.checkpoint.label(),
+            "domain" => self.domain.label().to_string(),
+            "dwell_ms" => self.dwell_ms.to_string(),
+            "min_rssi" => self.min_rssi_dbm.to_string(),
+            "probed_ssids" => self.probed_ssids.label().to_string(),
+            "brightness" => self.brightness.label().to_string(),
+            "dump_format" => self.dump_format.label().to_string(),
+            "sched_interval" => self.schedule.interval.label(),
+            "sched_cycles" => self.schedule.cycles.label(),
+            "sched_profile" => self.schedule.profile.label().to_string(),
+            "ap_list" => on_off(self.show_ap_list).to_string(),
+            "count" => self.count_policy.label().to_string(),
+            "cap_format" => self.capture.format.label().to_string(),
+            "cap_snaplen" => self.capture.snaplen.to_string(),
+            "cap_headers" => on_off(self.capture.headers_only).to_string(),
+            "cap_time" => self.capture_duration.label(),
+            _ => return None,
+        };
+        Some(value)
+    }
+
+    // Parses `value` the way `get` prints it; choices match case-insensitively
+    pub fn set(&mut self, key: &str, value: &str) -> Result<()> {
+        let value = value.trim();
+        match key {
+            "scan_profile" => self.scan_profile = parse_choice(&ScanProfile::ALL, |p| p.label().to_string(), value)?,
+            "scan_duration" => self.scan_duration = parse_choice(&ScanDuration::ALL, ScanDuration::label, value)?,
+            "checkpoint" => self.checkpoint = parse_choice(&CheckpointWindow::ALL, CheckpointWindow::label, value)?,
+            "domain" => self.domain = parse_choice(&RegulatoryDomain::ALL, |d| d.label().to_string(), value)?,
+            "dwell_ms" => self.dwell_ms = parse_number(value, DWELL_RANGE_MS)?,
+            "min_rssi" => self.min_rssi_dbm = parse_number(value, MIN_RSSI_RANGE_DBM)?,
+            "probed_ssids" => self.probed_ssids = parse_choice(&SsidCollection::ALL, |m| m.label().to_string(), value)?,
+            "brightness" => self.brightness = parse_choice(&Brightness::ALL, |b| b.label().to_string(), value)?,
+            "dump_format" => self.dump_format = parse_choice(&DumpFormat::ALL, |f| f.label().to_string(), value)?,
+            "sched_interval" => self.schedule.interval = parse_choice(&ScheduleInterval::ALL, ScheduleInterval::label, value)?,
+            "sched_cycles" => self.schedule.cycles = parse_choice(&ScheduleCycles::ALL, ScheduleCycles::label, value)?,
+            "sched_profile" => self.schedule.profile = parse_choice(&ScanProfile::ALL, |p| p.label().to_string(), value)?,
+            "ap_list" => self.show_ap_list = parse_choice(&[true, false], |on| on_off(*on).to_string(), value)?,
+            "count" => self.count_policy = parse_choice(&CountPolicy::ALL, |p| p.label().to_string(), value)?,
+            "cap_format" => self.capture.format = parse_choice(&CaptureFormat::ALL, |f| f.label().to_string(), value)?,
+            "cap_snaplen" => self.capture.snaplen = parse_number(value, SNAPLEN_RANGE)?,
+            "cap_headers" => self.capture.headers_only = parse_choice(&[true, false], |on| on_off(*on).to_string(), value)?,
+            "cap_time" => self.capture_duration = parse_choice(&ScanDuration::ALL, ScanDuration::label, value)?,
+            _ => return Err(anyhow::anyhow!("Unknown setting {}", key)),
+        }
+        Ok(())
+    }
+}
+
+impl MenuValues for Settings {
+    fn value(&self, key: &str) -> String {
+        self.get(key).unwrap_or_default()
+    }
+
+    fn set_value(&mut self, key: &str, value: &str) {
+        if let Err(e) = self.set(key, value) {
+            error!("Menu set {}: {}", key, e);
+        }
+    }
+
+    fn next_choice(&mut self, key: &str) {
+        match key {
+            "scan_profile" => self.scan_profile = self.scan_profile.next(),
+            "scan_duration" => self.scan_duration = self.scan_duration.next(),
+            "checkpoint" => self.checkpoint = self.checkpoint.next(),
+            "domain" => self.domain = self.domain.next(),
+            "dwell_ms" => self.dwell_ms = next_in(&DWELL_CHOICES_MS, self.dwell_ms),
+            "probed_ssids" => self.probed_ssids = self.probed_ssids.next(),
+            "brightness" => self.brightness = self.brightness.next(),
+            "dump_format" => self.dump_format = self.dump_format.next(),
+            "sched_interval" => self.schedule.interval = self.schedule.interval.next(),
+            "sched_cycles" => self.schedule.cycles = self.schedule.cycles.next(),
+            "sched_profile" => self.schedule.profile = self.schedule.profile.next(),
+            "count" => self.count_policy = self.count_policy.next(),
+            "cap_format" => self.capture.format = self.capture.format.next(),
+            "cap_snaplen" => self.capture.snaplen = next_in(&SNAPLEN_CHOICES, self.capture.snaplen),
+            "cap_time" => self.capture_duration = self.capture_duration.next(),
+            _ => error!("Setting {} has no choices", key),
+        }
+    }
+}
+
+fn on_off(on: bool) -> &'static str {
+    if on { "On" } else { "Off" }
+}
+
+// The choice after `current`; a value that is not in the list (set from the console) moves to the first
+fn next_in<T: Copy + PartialEq>(choices: &[T], current: T) -> T {
+    match choices.iter().position(|choice| *choice == current) {
+        Some(idx) => choices[(idx + 1) % choices.len()],
+        None => choices[0],
+    }
+}
+
+fn parse_choice<T: Copy>(all: &[T], label: impl Fn(&T) -> String, value: &str) -> Result<T> {
+    all.iter()
+        .find(|choice| label(choice).eq_ignore_ascii_case(value))
+        .copied()
+        .ok_or_else(|| {
+            let labels: Vec<String> = all.iter().map(label).collect();
+            anyhow::anyhow!("Expected one of: {}", labels.join(", "))
+        })
+}
+
+fn parse_number<T: core::str::FromStr + PartialOrd + core::fmt::Display + Copy>(value: &str, range: (T, T)) -> Result<T> {
+    let number: T = value.parse().map_err(|_| anyhow::anyhow!("Not a number: {}", value))?;
+    if number < range.0 || number > range.1 {
+        return Err(anyhow::anyhow!("Expected {} to {}", range.0, range.1));
+    }
+    Ok(number)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn encode_round_trip() {
+        let mut settings = Settings::default();
+        settings.set("scan_duration", "until stopped").unwrap();
+        settings.set("dwell_ms", "500").unwrap();
+        settings.set("min_rssi", "-70").unwrap();
+        settings.set("sched_interval", "Every 15m").unwrap();
+        settings.set("dump_format", "base64").unwrap();
+        settings.set("count", "universal").unwrap();
+        settings.set("cap_format", "pcap").unwrap();
+        settings.set("cap_snaplen", "100").unwrap();
+        settings.set("cap_headers", "on").unwrap();
+        settings.set("cap_time", "until stopped").unwrap();
+        assert_eq!(Settings::decode(&settings.encode()).unwrap(), (settings, SCHEMA_VERSION));
+    }
+
+    #[test]
+    fn older_blobs_keep_defaults() {
+        let (settings, _) = Settings::decode(&[2, 1]).unwrap();
+        assert_eq!(settings.scan_duration, Settings::default().scan_duration);
+    }
+
+    #[test]
+    fn get_and_set() {
+        let mut settings = Settings::default();
+        assert!(settings.set("dwell_ms", "5").is_err());
+        assert!(settings.set("nope", "5").is_err());
+        assert!(settings.set("cap_snaplen", "1500").is_err());
+        settings.set("ap_list", "off").unwrap();
+        // Every value reads back in a form `set` accepts
+        for key in KEYS {
+            let value = settings.get(key).unwrap();
+            let mut other = Settings::default();
+            other.set(key, &value).unwrap();
+            assert_eq!(other.get(key), Some(value), "{}", key);
+        }
+    }
+}
@@ -0,0 +1,305 @@
+// In-memory implementations of the hal.rs traits, for running the modes on the host
+// Time only moves when something waits on the SimClock, and scripted frames and button presses
+// are handed out once their time has come, so a scan of several minutes runs in milliseconds
+// and always the same way. Only built for tests and with the `sim` feature.
+
+use alloc::{boxed::Box, collections::{BTreeMap, VecDeque}, rc::Rc, string::{String, ToString}, vec::Vec};
+use core::{cell::Cell, time::Duration};
+
+use anyhow::Result;
+
+use crate::dump_protocol::{DumpReceiver, Link};
+use crate::frame;
+use crate::gesture::ButtonEvent;
+use crate::hal::{Clock, InputDevice, PacketSource, Screen, Storage};
+use crate::observation::{FrameBytes, Observation, RxMeta, SignalMode};
+use crate::settings::Brightness;
+
+// Clones share the same time
+#[derive(Debug, Clone)]
+pub struct SimClock {
+    now_ms: Rc<Cell<u64>>,
+    unix_start_us: u64,
+}
+
+impl SimClock {
+    pub fn new(unix_start_s: u64) -> Self {
+        SimClock { now_ms: Rc::new(Cell::new(0)), unix_start_us: unix_start_s * 1_000_000 }
+    }
+
+    pub fn advance(&self, ms: u64) {
+        self.now_ms.set(self.now_ms.get() + ms);
+    }
+}
+
+impl Clock for SimClock {
+    fn now_ms(&self) -> u64 {
+        self.now_ms.get()
+    }
+
+    fn unix_time_us(&self) -> u64 {
+        self.unix_start_us + self.now_ms.get() * 1000
+    }
+
+    fn delay_ms(&self, ms: u32) {
+        self.advance(ms as u64);
+    }
+}
+
+// Frames that arrive at fixed times, on whatever channel the source is tuned to by then
+pub struct ScriptedPackets {
+    clock: SimClock,
+    frames: VecDeque<(u64, Observation)>,
+    // Every channel the source was tuned to, in order
+    pub channels: Vec<u8>,
+}
+
+impl ScriptedPackets {
+    pub fn new(clock: SimClock) -> Self {
+        ScriptedPackets { clock, frames: VecDeque::new(), channels: Vec::new() }
+    }
+
+    // Frames have to be pushed in the order they arrive
+    pub fn push(&mut self, at_ms: u64, frame: &[u8], rssi: i8) -> Result<()> {
+        let header = frame::parse_header(frame).map_err(|e| anyhow::anyhow!("{}", e))?;
+        let meta = RxMeta {
+            rssi,
+            noise_floor: -95,
+            channel: 0,
+            secondary_channel: 0,
+            rate: 0,
+            sig_mode: SignalMode::NonHt,
+            mcs: 0,
+            wide_bandwidth: false,
+            sig_len: (frame.len() + 4) as u16,
+            timestamp_us: (at_ms * 1000) as u32,
+            fcs_failed: false,
+        };
+        self.frames.push_back((at_ms, Observation { meta, header, frame: FrameBytes::new(frame) }));
+        Ok(())
+    }
+
+    // Frames that have not arrived yet
+    pub fn pending(&self) -> usize {
+        self.frames.len()
+    }
+}
+
+impl PacketSource for ScriptedPackets {
+    fn next_observation(&mut self) -> Option<Observation> {
+        if !self.frames.front().is_some_and(|(at_ms, _)| *at_ms <= self.clock.now_ms()) {
+            return None;
+        }
+        let (_, mut observation) = self.frames.pop_front()?;
+        observation.meta.channel = self.channels.last().copied().unwrap_or(1);
+        Some(observation)
+    }
+
+    fn set_channel(&mut self, channel: u8) -> Result<()> {
+        self.channels.push(channel);
+        Ok(())
+    }
+
+    fn dropped(&self) -> u32 {
+        0
+    }
+}
+
+// Button presses that happen at fixed times
+pub struct ScriptedInput {
+    clock: SimClock,
+    events: VecDeque<(u64, ButtonEvent)>,
+}
+
+impl ScriptedInput {
+    pub fn new(clock: SimClock) -> Self {
+        ScriptedInput { clock, events: VecDeque::new() }
+    }
+
+    // Presses have to be added in the order they happen
+    pub fn press(&mut self, at_ms: u64, event: ButtonEvent) {
+        self.events.push_back((at_ms, event));
+    }
+
+    // Presses that have not been seen yet
+    pub fn pending(&self) -> usize {
+        self.events.len()
+    }
+}
+
+impl InputDevice for ScriptedInput {
+    fn poll(&mut self) -> ButtonEvent {
+        match self.events.front() {
+            Some(&(at_ms, event)) if at_ms <= self.clock.now_ms() => {
+                self.events.pop_front();
+                event
+            },
+            _ => ButtonEvent::None,
+        }
+    }
+}
+
+pub struct MemoryStorage {
+    pub files: BTreeMap<String, Vec<u8>>,
+    capacity: usize,
+}
+
+impl MemoryStorage {
+    pub fn new(capacity: usize) -> Self {
+        MemoryStorage { files: BTreeMap::new(), capacity }
+    }
+
+    fn file(&self, name: &str) -> Result<&Vec<u8>> {
+        self.files.get(name).ok_or_else(|| anyhow::anyhow!("No such file {}", name))
+    }
+
+    fn ensure_room(&self, growth: usize) -> Result<()> {
+        let (total, used) = self.space()?;
+        if used + growth > total {
+            return Err(anyhow::anyhow!("Storage full"));
+        }
+        Ok(())
+    }
+}
+
+impl Storage for MemoryStorage {
+    fn list(&self) -> Result<Vec<String>> {
+        Ok(self.files.keys().cloned().collect())
+    }
+
+    fn size(&self, name: &str) -> Result<usize> {
+        self.file(name).map(Vec::len)
+    }
+
+    fn read(&self, name: &str) -> Result<Vec<u8>> {
+        self.file(name).cloned()
+    }
+
+    fn write(&mut self, name: &str, data: &[u8]) -> Result<()> {
+        let replaced = self.files.get(name).map_or(0, Vec::len);
+        self.ensure_room(data.len().saturating_sub(replaced))?;
+        self.files.insert(name.to_string(), data.to_vec());
+        Ok(())
+    }
+
+    fn append(&mut self, name: &str, data: &[u8]) -> Result<()> {
+        self.file(name)?;
+        self.ensure_room(data.len())?;
+        self.files.entry(name.to_string()).or_default().extend_from_slice(data);
+        Ok(())
+    }
+
+    fn delete(&mut self, name: &str) -> Result<()> {
+        self.files.remove(name).map(|_| ()).ok_or_else(|| anyhow::anyhow!("No such file {}", name))
+    }
+
+    fn space(&self) -> Result<(usize, usize)> {
+        Ok((self.capacity, self.files.values().map(Vec::len).sum()))
+    }
+}
+
+// Keeps the text of every flushed screen; rectangles are not recorded
+#[derive(Debug, Default)]
+pub struct MemoryScreen {
+    drawing: Vec<String>,
+    // Lines of text per flush, oldest first
+    pub frames: Vec<Vec<String>>,
+    pub brightness: Option<Brightness>,
+}
+
+impl MemoryScreen {
+    pub fn new() -> Self {
+        MemoryScreen::default()
+    }
+
+    // What is on the screen right now
+    pub fn shown(&self) -> &[String] {
+        self.frames.last().map(Vec::as_slice).unwrap_or_default()
+    }
+
+    // Whether any flushed screen had a line containing `text`
+    pub fn has_shown(&self, text: &str) -> bool {
+        self.frames.iter().flatten().any(|line| line.contains(text))
+    }
+}
+
+impl Screen for MemoryScreen {
+    fn clear(&mut self) -> Result<()> {
+        self.drawing.clear();
+        Ok(())
+    }
+
+    fn draw_text(&mut self, _x: i32, _y: i32, text: &str, _color: bool) -> Result<()> {
+        self.drawing.push(text.to_string());
+        Ok(())
+    }
+
+    fn draw_rect(&mut self, _x: i32, _y: i32, _width: i32, _height: i32, _color: bool) -> Result<()> {
+        Ok(())
+    }
+
+    fn fill_rect(&mut self, _x: i32, _y: i32, _width: i32, _height: i32, _color: bool) -> Result<()> {
+        Ok(())
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        self.frames.push(self.drawing.clone());
+        Ok(())
+    }
+
+    fn set_brightness(&mut self, brightness: Brightness) -> Result<()> {
+        self.brightness = Some(brightness);
+        Ok(())
+    }
+}
+
+// Connects a dump sender straight to a receiver, for checking the protocol without a serial port
+pub struct Loopback {
+    pub receiver: DumpReceiver,
+    replies: VecDeque<String>,
+    // Damages the sent line with this index (counting from 0) when it returns true
+    pub corrupt: Box<dyn FnMut(usize) -> bool>,
+    // Loses the sent line with this index when it returns true, as an unplugged cable would
+    pub lost: Box<dyn FnMut(usize) -> bool>,
+    // Every reply of the receiver, in order
+    pub history: Vec<String>,
+    sent: usize,
+}
+
+impl Loopback {
+    pub fn new(receiver: DumpReceiver) -> Self {
+        Loopback {
+            receiver,
+            replies: VecDeque::new(),
+            corrupt: Box::new(|_| false),
+            lost: Box::new(|_| false),
+            history: Vec::new(),
+            sent: 0,
+        }
+    }
+}
+
+impl Link for Loopback {
+    fn send(&mut self, line: &str) -> Result<()> {
+        let mut line = line.to_string();
+        let idx = self.sent;
+        self.sent += 1;
+        if (self.lost)(idx) {
+            return Ok(());
+        }
+        if (self.corrupt)(idx) && line.len() > 1 {
+            // Flip a bit in the last character, as a noisy line would
+            let last = line.pop().unwrap_or(' ');
+            line.push((last as u8 ^ 0x01) as char);
+        }
+        if let Some(reply) = self.receiver.handle_line(&line) {
+            self.history.push(reply.clone());
+            self.replies.push_back(reply);
+        }
+        Ok(())
+    }
+
+    fn receive(&mut self, _timeout: Duration) -> Result<Option<String>> {
+        Ok(self.replies.pop_front())
+    }
+}
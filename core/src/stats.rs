@@ -0,0 +1,255 @@
+// Per-MAC statistics collected during a scan
+
+use alloc::{collections::BTreeMap, vec::Vec};
+
+use crate::frame::MacAddress;
+use crate::mac_addr;
+use crate::observation::Observation;
+
+// Number of (type, subtype) histogram entries kept in a saved record
+pub const SAVED_SUBTYPES: usize = 4;
+// Serialized size of one record: MAC + the fields written by `MacStats::write_record`
+pub const RECORD_LEN: usize = 6 + 4 * 4 + 3 + 1 + 2 + SAVED_SUBTYPES * 3;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Role {
+    Transmitter,
+    Receiver,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MacStats {
+    // Milliseconds since the start of the scan
+    pub first_seen_ms: u32,
+    pub last_seen_ms: u32,
+    pub frame_count: u32,
+    pub tx_count: u32,
+    // RSSI is only meaningful for frames this address transmitted
+    pub rssi_min: i8,
+    pub rssi_max: i8,
+    rssi_sum: i64,
+    // Bit n set when the address was seen on channel n
+    pub channels: u16,
+    // Sorted (type << 4 | subtype, count) pairs
+    subtypes: Vec<(u8, u32)>,
+}
+
+impl MacStats {
+    pub fn new(now_ms: u32) -> Self {
+        MacStats {
+            first_seen_ms: now_ms,
+            last_seen_ms: now_ms,
+            frame_count: 0,
+            tx_count: 0,
+            rssi_min: 0,
+            rssi_max: 0,
+            rssi_sum: 0,
+            channels: 0,
+            subtypes: Vec::new(),
+        }
+    }
+
+    pub fn record(&mut self, role: Role, observation: &Observation, now_ms: u32) {
+        self.last_seen_ms = now_ms;
+        self.frame_count = self.frame_count.saturating_add(1);
+
+        if observation.meta.channel < 16 {
+            self.channels |= 1 << observation.meta.channel;
+        }
+
+        let frame_type = ((observation.header.frame_control.0 & 0x000C) >> 2) as u8;
+        let key = frame_type << 4 | observation.header.subtype();
+        match self.subtypes.binary_search_by_key(&key, |(k, _)| *k) {
+            Ok(idx) => self.subtypes[idx].1 = self.subtypes[idx].1.saturating_add(1),
+            Err(idx) => self.subtypes.insert(idx, (key, 1)),
+        }
+
+        if role == Role::Transmitter {
+            let rssi = observation.meta.rssi;
+            if self.tx_count == 0 {
+                self.rssi_min = rssi;
+                self.rssi_max = rssi;
+            } else {
+                self.rssi_min = self.rssi_min.min(rssi);
+                self.rssi_max = self.rssi_max.max(rssi);
+            }
+            self.rssi_sum += rssi as i64;
+            self.tx_count = self.tx_count.saturating_add(1);
+        }
+    }
+
+    pub fn seen_as_transmitter(&self) -> bool {
+        self.tx_count > 0
+    }
+
+    pub fn rssi_mean(&self) -> Option<i8> {
+        if self.tx_count == 0 {
+            return None;
+        }
+        Some((self.rssi_sum / self.tx_count as i64) as i8)
+    }
+
+    // The most frequent (type << 4 | subtype, count) entries, most frequent first
+    pub fn top_subtypes(&self, n: usize) -> Vec<(u8, u32)> {
+        let mut sorted = self.subtypes.clone();
+        sorted.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+        sorted.truncate(n);
+        sorted
+    }
+
+    // Little endian fixed-size record; RSSI fields are 0 when the address never transmitted
+    // and unused histogram slots are 0xFF with a count of 0. The flags byte has bit 0 set for
+    // transmitters and bit 1 for locally administered (usually randomized) addresses.
+    pub fn write_record(&self, mac: &MacAddress, out: &mut Vec<u8>) {
+        out.extend_from_slice(mac);
+        out.extend_from_slice(&self.first_seen_ms.to_le_bytes());
+        out.extend_from_slice(&self.last_seen_ms.to_le_bytes());
+        out.extend_from_slice(&self.frame_count.to_le_bytes());
+        out.extend_from_slice(&self.tx_count.to_le_bytes());
+        out.push(self.rssi_min as u8);
+        out.push(self.rssi_max as u8);
+        out.push(self.rssi_mean().unwrap_or(0) as u8);
+        let locally_administered = mac_addr::classify(mac).locally_administered;
+        out.push(self.seen_as_transmitter() as u8 | (locally_administered as u8) << 1);
+        out.extend_from_slice(&self.channels.to_le_bytes());
+
+        let top = self.top_subtypes(SAVED_SUBTYPES);
+        for slot in 0..SAVED_SUBTYPES {
+            let (key, count) = top.get(slot).copied().unwrap_or((0xFF, 0));
+            out.push(key);
+            out.extend_from_slice(&(count.min(u16::MAX as u32) as u16).to_le_bytes());
+        }
+    }
+
+    // Inverse of `write_record`. Only the saved top subtypes come back, and the RSSI sum is
+    // rebuilt from the saved mean.
+    pub fn read_record(record: &[u8]) -> Option<(MacAddress, MacStats)> {
+        if record.len() < RECORD_LEN {
+            return None;
+        }
+        let u32_at = |offset: usize| u32::from_le_bytes([record[offset], record[offset + 1], record[offset + 2], record[offset + 3]]);
+        let mut mac = [0u8; 6];
+        mac.copy_from_slice(&record[..6]);
+        let tx_count = u32_at(18);
+        let subtypes = record[28..28 + SAVED_SUBTYPES * 3]
+            .chunks_exact(3)
+            .filter(|slot| slot[0] != 0xFF)
+            .map(|slot| (slot[0], u16::from_le_bytes([slot[1], slot[2]]) as u32))
+            .collect::<Vec<_>>();
+        let mut stats = MacStats {
+            first_seen_ms: u32_at(6),
+            last_seen_ms: u32_at(10),
+            frame_count: u32_at(14),
+            tx_count,
+            rssi_min: record[22] as i8,
+            rssi_max: record[23] as i8,
+            rssi_sum: record[24] as i8 as i64 * tx_count as i64,
+            channels: u16::from_le_bytes([record[26], record[27]]),
+            subtypes,
+        };
+        stats.subtypes.sort_unstable();
+        Some((mac, stats))
+    }
+
+    // Folds in the statistics of the same address from another scan, with its times already
+    // moved onto this scan's clock
+    pub fn merge(&mut self, other: &MacStats) {
+        self.first_seen_ms = self.first_seen_ms.min(other.first_seen_ms);
+        self.last_seen_ms = self.last_seen_ms.max(other.last_seen_ms);
+        self.frame_count = self.frame_count.saturating_add(other.frame_count);
+        if other.tx_count > 0 {
+            if self.tx_count == 0 {
+                self.rssi_min = other.rssi_min;
+                self.rssi_max = other.rssi_max;
+            } else {
+                self.rssi_min = self.rssi_min.min(other.rssi_min);
+                self.rssi_max = self.rssi_max.max(other.rssi_max);
+            }
+        }
+        self.tx_count = self.tx_count.saturating_add(other.tx_count);
+        self.rssi_sum += other.rssi_sum;
+        self.channels |= other.channels;
+        for &(key, count) in &other.subtypes {
+            match self.subtypes.binary_search_by_key(&key, |(k, _)| *k) {
+                Ok(idx) => self.subtypes[idx].1 = self.subtypes[idx].1.saturating_add(count),
+                Err(idx) => self.subtypes.insert(idx, (key, count)),
+            }
+        }
+    }
+}
+
+// Fold one observation into the per-MAC table: the receiver and, when present, the transmitter
+pub fn record_observation(map: &mut BTreeMap<MacAddress, MacStats>, observation: &Observation, now_ms: u32) {
+    let header = &observation.header;
+    map.entry(header.receiver())
+        .or_insert_with(|| MacStats::new(now_ms))
+        .record(Role::Receiver, observation, now_ms);
+    if let Some(transmitter) = header.transmitter() {
+        map.entry(transmitter)
+            .or_insert_with(|| MacStats::new(now_ms))
+            .record(Role::Transmitter, observation, now_ms);
+    }
+}
+
+pub fn serialize_records<'a>(records: impl Iterator<Item = (&'a MacAddress, &'a MacStats)>) -> Vec<u8> {
+    let mut out = Vec::with_capacity(records.size_hint().0 * RECORD_LEN);
+    for (mac, stats) in records {
+        stats.write_record(mac, &mut out);
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::observation::tests::observation;
+    use alloc::vec;
+
+    #[test]
+    fn records_round_trip() {
+        // Probe request from 00:1a:11:01:02:03
+        let mut frame = vec![0x40, 0x00, 0, 0];
+        frame.extend([0xff; 6]);
+        frame.extend([0x00, 0x1a, 0x11, 1, 2, 3]);
+        frame.extend([0xff; 6]);
+        frame.extend([0x10, 0]);
+        let mut map = BTreeMap::new();
+        record_observation(&mut map, &observation(&frame, -50), 10);
+        record_observation(&mut map, &observation(&frame, -70), 20);
+
+        let stats = &map[&[0x00, 0x1a, 0x11, 1, 2, 3]];
+        assert_eq!((stats.first_seen_ms, stats.last_seen_ms), (10, 20));
+        assert!(stats.seen_as_transmitter());
+        assert_eq!(stats.rssi_mean(), Some(-60));
+
+        let data = serialize_records(map.iter());
+        // The broadcast receiver is counted too
+        assert_eq!(data.len(), map.len() * RECORD_LEN);
+        for record in data.chunks(RECORD_LEN) {
+            let (mac, back) = MacStats::read_record(record).unwrap();
+            let mut again = Vec::new();
+            back.write_record(&mac, &mut again);
+            assert_eq!(again, record);
+        }
+    }
+
+    #[test]
+    fn merge_adds_counts() {
+        let mut frame = vec![0x40, 0x00, 0, 0];
+        frame.extend([0xff; 6]);
+        frame.extend([2; 6]);
+        frame.extend([0xff; 6]);
+        frame.extend([0x10, 0]);
+        let mut first = MacStats::new(5);
+        first.record(Role::Transmitter, &observation(&frame, -40), 5);
+        let mut second = MacStats::new(50);
+        second.record(Role::Transmitter, &observation(&frame, -80), 50);
+
+        first.merge(&second);
+        assert_eq!(first.frame_count, 2);
+        assert_eq!((first.first_seen_ms, first.last_seen_ms), (5, 50));
+        assert_eq!((first.rssi_min, first.rssi_max), (-80, -40));
+        assert_eq!(first.rssi_mean(), Some(-60));
+        assert_eq!(first.top_subtypes(1), vec![(0x04, 2)]);
+    }
+}
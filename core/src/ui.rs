@@ -0,0 +1,62 @@
+// Screens shared by the modes, drawn on any `Screen` in the 6x10 font
+
+use alloc::{format, string::String};
+
+use anyhow::Result;
+
+use crate::hal::Screen;
+use crate::mac_addr::AddressSummary;
+use crate::menu::MenuView;
+
+// Rows that fit under the title line of a list screen
+pub const LIST_ROWS: usize = 5;
+
+pub fn draw_status_update(screen: &mut impl Screen, time: &str, total_count: usize, estimated: usize, channel: u8, dropped: u32) -> Result<()> {
+    screen.clear()?;
+    screen.draw_text(10, 4, time, true)?;
+    screen.draw_text(10, 16, &format!("MACs found: {}", total_count), true)?;
+    screen.draw_text(10, 28, &format!("Est. devices: {}", estimated), true)?;
+    screen.draw_text(10, 40, &format!("Channel: {}", channel), true)?;
+    screen.draw_text(10, 52, &format!("Dropped: {}", dropped), true)?;
+    screen.flush()
+}
+
+// Leaves the bottom line free for the save result
+pub fn draw_final_count(screen: &mut impl Screen, summary: &AddressSummary, estimated: usize, vendors: &str) -> Result<()> {
+    screen.clear()?;
+    screen.draw_text(10, 2, &format!("Found {} MACs", summary.devices), true)?;
+    screen.draw_text(10, 12, &format!("Est. {} devices", estimated), true)?;
+    screen.draw_text(10, 22, &format!("Uni {} Local {}", summary.unicast_universal, summary.unicast_local), true)?;
+    screen.draw_text(10, 32, &format!("Multi {} Bcast {}", summary.multicast, summary.broadcast + summary.null), true)?;
+    // Vendors of the universally administered addresses, e.g. "Apple 12, Samsung 7"
+    screen.draw_text(0, 42, vendors, true)?;
+    screen.flush()
+}
+
+// A title line followed by up to LIST_ROWS rows starting at `first`
+pub fn draw_list(screen: &mut impl Screen, title: &str, rows: &[String], first: usize) -> Result<()> {
+    screen.clear()?;
+    screen.draw_text(0, 0, title, true)?;
+    for (idx, row) in rows.iter().skip(first).take(LIST_ROWS).enumerate() {
+        screen.draw_text(0, 12 + 10 * idx as i32, row, true)?;
+    }
+    screen.flush()
+}
+
+// Title on top, the selected row inverted; while editing the row is outlined instead
+pub fn draw_menu(screen: &mut impl Screen, view: &MenuView) -> Result<()> {
+    screen.clear()?;
+    screen.draw_text(0, 0, &view.title, true)?;
+    for (idx, row) in view.rows.iter().enumerate() {
+        let y = 12 + 10 * idx as i32;
+        let highlighted = idx == view.highlighted;
+        if highlighted && view.editing {
+            screen.draw_rect(0, y - 1, 128, 11, true)?;
+        } else if highlighted {
+            screen.fill_rect(0, y - 1, 128, 11, true)?;
+        }
+        screen.draw_text(2, y, row, !highlighted || view.editing)?;
+    }
+    screen.flush()
+}
+
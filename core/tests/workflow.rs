@@ -0,0 +1,380 @@
+// The modes as main.rs runs them, on the in-memory board from sim.rs: frames and button
+// presses are scripted against the simulated clock.
+
+use std::collections::{BTreeMap, VecDeque};
+
+use mac_sniff_core::app::{self, CommandOutcome, MenuAction, MenuHost};
+use mac_sniff_core::capture;
+use mac_sniff_core::dump;
+use mac_sniff_core::dump_protocol::DumpReceiver;
+use mac_sniff_core::frame::MacAddress;
+use mac_sniff_core::gesture::ButtonEvent;
+use mac_sniff_core::hal::{Board, Clock, Storage};
+use mac_sniff_core::pcap::{self, CaptureFormat, CaptureOptions};
+use mac_sniff_core::scan::{self, ScanOptions};
+use mac_sniff_core::scan_duration::{CheckpointWindow, ScanDuration};
+use mac_sniff_core::scan_file;
+use mac_sniff_core::settings::Settings;
+use mac_sniff_core::sim::{Loopback, MemoryScreen, MemoryStorage, ScriptedInput, ScriptedPackets, SimClock};
+
+type SimBoard = Board<MemoryScreen, ScriptedInput, SimClock>;
+
+const START_UNIX_S: u64 = 1_700_000_000;
+const DEVICE_ID: MacAddress = [0x24, 0x0a, 0xc4, 0x12, 0x34, 0x56];
+const STATIONS: [MacAddress; 3] = [
+    [0x00, 0x1b, 0x63, 0x00, 0x00, 0x01],
+    [0x00, 0x1b, 0x63, 0x00, 0x00, 0x02],
+    [0x3c, 0x5a, 0xb4, 0x00, 0x00, 0x03],
+];
+const BSSID: MacAddress = [0x00, 0x14, 0x6c, 0x00, 0x01, 0x01];
+
+// Stands in for the serial console and the settings store
+#[derive(Default)]
+struct Host {
+    commands: VecDeque<CommandOutcome>,
+    saves: usize,
+}
+
+impl MenuHost<MemoryScreen> for Host {
+    fn run_commands(&mut self, _screen: &mut MemoryScreen, _settings: &mut Settings) -> anyhow::Result<CommandOutcome> {
+        Ok(self.commands.pop_front().unwrap_or(CommandOutcome::Idle))
+    }
+
+    fn save_settings(&mut self, _settings: &Settings) -> anyhow::Result<()> {
+        self.saves += 1;
+        Ok(())
+    }
+}
+
+fn board(clock: &SimClock) -> SimBoard {
+    Board { screen: MemoryScreen::new(), input: ScriptedInput::new(clock.clone()), clock: clock.clone() }
+}
+
+fn scan_options(settings: &Settings) -> ScanOptions {
+    ScanOptions {
+        duration: settings.scan_duration,
+        checkpoint: settings.checkpoint,
+        min_rssi_dbm: settings.min_rssi_dbm,
+        probed_ssids: settings.probed_ssids,
+        count_policy: settings.count_policy,
+        boot_count: 1,
+        device_id: DEVICE_ID,
+        firmware_version: "0.1.0",
+    }
+}
+
+fn probe_request(mac: MacAddress, seq: u16) -> Vec<u8> {
+    let mut frame = vec![0x40, 0, 0, 0];
+    frame.extend([0xff; 6]);
+    frame.extend(mac);
+    frame.extend([0xff; 6]);
+    frame.extend((seq << 4).to_le_bytes());
+    // Wildcard SSID, supported rates
+    frame.extend([0, 0, 1, 1, 2]);
+    frame
+}
+
+fn beacon(bssid: MacAddress, ssid: &[u8]) -> Vec<u8> {
+    let mut frame = vec![0x80, 0, 0, 0];
+    frame.extend([0xff; 6]);
+    frame.extend(bssid);
+    frame.extend(bssid);
+    frame.extend([0x10, 0]);
+    // Timestamp, beacon interval, capability
+    frame.extend([0; 8]);
+    frame.extend(100u16.to_le_bytes());
+    frame.extend(0x0411u16.to_le_bytes());
+    frame.extend([0, ssid.len() as u8]);
+    frame.extend(ssid);
+    frame.extend([3, 1, 6]);
+    frame
+}
+
+// Every station probes and the access point beacons once per `period_ms`
+fn traffic(clock: &SimClock, from_ms: u64, until_ms: u64, period_ms: u64) -> ScriptedPackets {
+    let mut packets = ScriptedPackets::new(clock.clone());
+    let mut at_ms = from_ms;
+    let mut seq = 0;
+    while at_ms < until_ms {
+        packets.push(at_ms, &beacon(BSSID, b"office"), -55).unwrap();
+        for (idx, station) in STATIONS.iter().enumerate() {
+            packets.push(at_ms + 10 * (idx as u64 + 1), &probe_request(*station, seq), -60 - 5 * idx as i8).unwrap();
+        }
+        seq += 1;
+        at_ms += period_ms;
+    }
+    packets
+}
+
+#[test]
+fn scan_save_and_dump() {
+    let clock = SimClock::new(START_UNIX_S);
+    let mut board = board(&clock);
+    let mut settings = Settings { scan_duration: ScanDuration::UntilStopped, ..Settings::default() };
+    let mut host = Host::default();
+    let mut storage = MemoryStorage::new(64 * 1024);
+
+    // Pick Scan, stop it by holding the button, scroll the access point list and leave it
+    board.input.press(500, ButtonEvent::LongPress);
+    board.input.press(20_000, ButtonEvent::LongPress);
+    board.input.press(30_000, ButtonEvent::ShortPress);
+    board.input.press(31_000, ButtonEvent::LongPress);
+    // Back in the menu: down to Files, open it, pick Dump
+    for at_ms in [40_000, 40_100, 40_200] {
+        board.input.press(at_ms, ButtonEvent::ShortPress);
+    }
+    board.input.press(40_300, ButtonEvent::LongPress);
+    board.input.press(40_400, ButtonEvent::LongPress);
+
+    assert_eq!(app::run_menu(&mut board, &mut settings, &mut host).unwrap(), MenuAction::Scan);
+    let packets = traffic(&clock, 1000, 15_000, 1000);
+    let report = scan::run_scan(&mut board, packets, &mut storage, &scan_options(&settings), &settings.channel_plan(), || false).unwrap();
+    assert_eq!(report.devices, 4);
+    assert_eq!(report.files, ["scan_1_0000.bin"]);
+    assert!(board.screen.has_shown("Found 4 MACs"));
+    assert_eq!(board.screen.shown().last().map(String::as_str), Some("MAC data saved"));
+
+    assert!(settings.show_ap_list && !report.access_points.is_empty());
+    board.clock.delay_ms(2000);
+    app::run_ap_list(&mut board, &report.access_points).unwrap();
+    assert!(board.screen.has_shown("APs 1-1 of 1"));
+
+    assert_eq!(app::run_menu(&mut board, &mut settings, &mut host).unwrap(), MenuAction::Dump);
+    assert_eq!(board.input.pending(), 0);
+    let mut link = Loopback::new(DumpReceiver::new(BTreeMap::new()));
+    let summary = dump::run_dump(&mut board.screen, &storage, &mut link, settings.dump_format, None).unwrap();
+    assert_eq!((summary.files, summary.retransmits), (1, 0));
+    assert!(link.receiver.finished);
+
+    let received = &link.receiver.files[0];
+    assert_eq!(received.path, "scan_1_0000.bin");
+    assert_eq!(received.data, storage.read("scan_1_0000.bin").unwrap());
+    let scan = scan_file::read_scan_file(&received.data).unwrap();
+    let header = scan.header.as_ref().unwrap();
+    assert_eq!((header.start_time_s, header.device_id), (START_UNIX_S, DEVICE_ID));
+    let macs = scan.mac_addresses();
+    assert!(STATIONS.iter().chain([&BSSID]).all(|mac| macs.contains(mac)));
+    assert_eq!(host.saves, 0);
+}
+
+#[test]
+fn console_stops_scan_and_menu_edits_are_saved() {
+    let clock = SimClock::new(START_UNIX_S);
+    let mut board = board(&clock);
+    let mut settings = Settings::default();
+    let mut host = Host::default();
+    let mut storage = MemoryStorage::new(64 * 1024);
+
+    // Settings > Scan > Min RSSI: step it once and confirm, then the console starts a scan
+    board.input.press(100, ButtonEvent::ShortPress);
+    board.input.press(200, ButtonEvent::ShortPress);
+    for at_ms in [300, 400] {
+        board.input.press(at_ms, ButtonEvent::LongPress);
+    }
+    for _ in 0..3 {
+        board.input.press(500, ButtonEvent::ShortPress);
+    }
+    board.input.press(600, ButtonEvent::LongPress);
+    board.input.press(700, ButtonEvent::ShortPress);
+    board.input.press(800, ButtonEvent::LongPress);
+    host.commands.extend(vec![CommandOutcome::Idle; 30]);
+    host.commands.push_back(CommandOutcome::Action(MenuAction::Scan));
+
+    assert_eq!(app::run_menu(&mut board, &mut settings, &mut host).unwrap(), MenuAction::Scan);
+    assert_eq!((settings.min_rssi_dbm, host.saves), (-85, 1));
+    assert_eq!(board.screen.brightness, Some(settings.brightness));
+
+    let packets = traffic(&clock, 1000, 10_000, 500);
+    let mut polls = 0;
+    let report = scan::run_scan(&mut board, packets, &mut storage, &scan_options(&settings), &settings.channel_plan(), || {
+        polls += 1;
+        polls > 300
+    }).unwrap();
+    assert_eq!(report.devices, 4);
+    // Stopped after 3 s, long before the 30 s scan was over
+    let header = scan_file::read_scan_file(&storage.read(&report.files[0]).unwrap()).unwrap().header.unwrap();
+    assert!((3000..4000).contains(&header.duration_ms), "{}", header.duration_ms);
+}
+
+#[test]
+fn unconfirmed_edit_is_undone() {
+    let clock = SimClock::new(START_UNIX_S);
+    let mut board = board(&clock);
+    let mut settings = Settings::default();
+    let mut host = Host::default();
+
+    // Settings > Scan > Profile: step it once, then leave the button alone
+    board.input.press(100, ButtonEvent::ShortPress);
+    board.input.press(200, ButtonEvent::ShortPress);
+    for at_ms in [300, 400, 500] {
+        board.input.press(at_ms, ButtonEvent::LongPress);
+    }
+    board.input.press(600, ButtonEvent::ShortPress);
+    host.commands.extend(vec![CommandOutcome::Idle; 150]);
+    host.commands.push_back(CommandOutcome::Action(MenuAction::Scan));
+
+    assert_eq!(app::run_menu(&mut board, &mut settings, &mut host).unwrap(), MenuAction::Scan);
+    assert_eq!(settings, Settings::default());
+    assert_eq!(host.saves, 0);
+    // The stepped value was on screen before it was put back
+    assert!(board.screen.has_shown(&format!("Profile: {}", settings.scan_profile.next().label())));
+}
+
+#[test]
+fn continuous_scan_rotates_checkpoints() {
+    let scan_for = |capacity: usize| {
+        let clock = SimClock::new(START_UNIX_S);
+        let mut board = board(&clock);
+        let settings = Settings {
+            scan_duration: ScanDuration::Seconds(5 * 60),
+            checkpoint: CheckpointWindow::Seconds(60),
+            ..Settings::default()
+        };
+        let mut storage = MemoryStorage::new(capacity);
+        let packets = traffic(&clock, 0, 5 * 60 * 1000, 10_000);
+        let report = scan::run_scan(&mut board, packets, &mut storage, &scan_options(&settings), &settings.channel_plan(), || false).unwrap();
+        (report, storage)
+    };
+
+    // Four full windows, then the final results
+    let (report, storage) = scan_for(64 * 1024);
+    assert_eq!(report.files.len(), 5);
+    assert_eq!(storage.list().unwrap(), report.files);
+    let largest = report.files.iter().map(|name| storage.size(name).unwrap()).max().unwrap();
+
+    // Only room for two files: the oldest windows are deleted
+    let (report, storage) = scan_for(largest * 5 / 2);
+    assert_eq!(report.files, ["scan_1_0003.bin", "scan_1_0004.bin"]);
+    assert_eq!(storage.list().unwrap(), report.files);
+}
+
+#[test]
+fn scans_after_a_power_cycle_keep_older_files() {
+    let clock = SimClock::new(START_UNIX_S);
+    let mut board = board(&clock);
+    let settings = Settings { scan_duration: ScanDuration::Seconds(30), ..Settings::default() };
+    let mut storage = MemoryStorage::new(64 * 1024);
+    // Left by an earlier power-on with the same boot count and the same clock
+    storage.write("scan_1_0000.bin", b"older").unwrap();
+    storage.write("scan_2_0004.bin", b"other boot").unwrap();
+
+    let packets = traffic(&clock, 0, 30_000, 1000);
+    let report = scan::run_scan(&mut board, packets, &mut storage, &scan_options(&settings), &settings.channel_plan(), || false).unwrap();
+    assert_eq!(report.files, ["scan_1_0001.bin"]);
+    assert_eq!(storage.read("scan_1_0000.bin").unwrap(), b"older");
+}
+
+#[test]
+fn report_counts_devices_of_every_window() {
+    let clock = SimClock::new(START_UNIX_S);
+    let mut board = board(&clock);
+    let settings = Settings {
+        scan_duration: ScanDuration::Seconds(3 * 60),
+        checkpoint: CheckpointWindow::Seconds(60),
+        ..Settings::default()
+    };
+    let mut storage = MemoryStorage::new(64 * 1024);
+    // A different station in each window
+    let mut packets = ScriptedPackets::new(clock.clone());
+    for (window, station) in STATIONS.iter().enumerate() {
+        for seq in 0..5 {
+            packets.push(window as u64 * 60_000 + seq * 10_000 + 1000, &probe_request(*station, seq as u16), -60).unwrap();
+        }
+    }
+
+    let report = scan::run_scan(&mut board, packets, &mut storage, &scan_options(&settings), &settings.channel_plan(), || false).unwrap();
+    assert_eq!(report.files.len(), 3);
+    assert_eq!(report.devices, 3);
+    for (name, station) in report.files.iter().zip(STATIONS) {
+        let macs = scan_file::read_scan_file(&storage.read(name).unwrap()).unwrap().mac_addresses();
+        assert_eq!(macs, [station]);
+    }
+}
+
+#[test]
+fn busy_window_is_saved_early() {
+    let clock = SimClock::new(START_UNIX_S);
+    let mut board = board(&clock);
+    let settings = Settings { scan_duration: ScanDuration::Seconds(60), ..Settings::default() };
+    assert_eq!(settings.checkpoint, CheckpointWindow::Off);
+    let mut storage = MemoryStorage::new(512 * 1024);
+    // 1500 stations, one probe each
+    let mut packets = ScriptedPackets::new(clock.clone());
+    for idx in 0..1500u16 {
+        let [high, low] = idx.to_be_bytes();
+        packets.push(1000 + idx as u64 * 20, &probe_request([0x00, 0x1b, 0x63, 0x01, high, low], 0), -60).unwrap();
+    }
+
+    let report = scan::run_scan(&mut board, packets, &mut storage, &scan_options(&settings), &settings.channel_plan(), || false).unwrap();
+    assert_eq!(report.files.len(), 2);
+    assert_eq!(report.devices, 1500);
+    let saved: usize = report.files.iter()
+        .map(|name| scan_file::read_scan_file(&storage.read(name).unwrap()).unwrap().mac_addresses().len())
+        .sum();
+    assert_eq!(saved, 1500);
+}
+
+#[test]
+fn capture_rotates_files() {
+    let clock = SimClock::new(START_UNIX_S);
+    let mut board = board(&clock);
+    let mut storage = MemoryStorage::new(40 * 1024);
+    let mut packets = traffic(&clock, 0, 50_000, 200);
+    let frames = packets.pending() as u32;
+    let settings = Settings::default();
+    assert_eq!(settings.capture, CaptureOptions { format: CaptureFormat::PcapNg, snaplen: 320, headers_only: false });
+
+    let summary = capture::run_capture(&mut board, &mut packets, &mut storage, settings.capture, &settings.channel_plan(), settings.capture_duration).unwrap();
+    assert_eq!(summary.frames, frames);
+    assert!(summary.files_deleted > 0);
+    let files = storage.list().unwrap();
+    assert_eq!(files.len() as u32, summary.files_written - summary.files_deleted);
+    for name in &files {
+        // Every file starts with its own section header block
+        assert_eq!(storage.read(name).unwrap()[..4], [0x0a, 0x0d, 0x0d, 0x0a]);
+    }
+    assert!(board.screen.has_shown("Capture 57s left"));
+}
+
+#[test]
+fn capture_stops_when_storage_is_full() {
+    let clock = SimClock::new(START_UNIX_S);
+    let mut board = board(&clock);
+    // Room for a scan file but not for a capture file
+    let mut storage = MemoryStorage::new(8 * 1024);
+    storage.write("scan_1_0000.bin", &[0; 1024]).unwrap();
+    let mut packets = traffic(&clock, 0, 5000, 1000);
+    let settings = Settings::default();
+
+    let summary = capture::run_capture(&mut board, &mut packets, &mut storage, settings.capture, &settings.channel_plan(), settings.capture_duration).unwrap();
+    assert_eq!((summary.frames, summary.files_written, summary.files_deleted), (0, 0, 0));
+    assert_eq!(storage.list().unwrap(), ["scan_1_0000.bin"]);
+}
+
+#[test]
+fn capture_follows_settings() {
+    let clock = SimClock::new(START_UNIX_S);
+    let mut board = board(&clock);
+    let mut storage = MemoryStorage::new(40 * 1024);
+    let mut packets = traffic(&clock, 0, 5000, 1000);
+    let mut settings = Settings::default();
+    settings.set("cap_format", "pcap").unwrap();
+    settings.set("cap_headers", "on").unwrap();
+    settings.capture_duration = ScanDuration::UntilStopped;
+    board.input.press(8000, ButtonEvent::LongPress);
+
+    let summary = capture::run_capture(&mut board, &mut packets, &mut storage, settings.capture, &settings.channel_plan(), settings.capture_duration).unwrap();
+    assert_eq!((summary.frames, summary.files_written), (20, 1));
+    assert!(board.screen.has_shown("Capture 6s"));
+    let files = storage.list().unwrap();
+    assert_eq!(files, [format!("cap_{}_0.pcap", START_UNIX_S)]);
+
+    // Only the 24 byte management headers were kept, after the radiotap header
+    let (format, captured) = pcap::read_capture(&storage.read(&files[0]).unwrap()).unwrap();
+    assert_eq!((format, captured.len()), (CaptureFormat::Pcap, 20));
+    for packet in captured {
+        let radiotap_len = u16::from_le_bytes([packet.data[2], packet.data[3]]) as usize;
+        assert_eq!(packet.data.len() - radiotap_len, 24);
+        assert!(packet.original_len > packet.data.len());
+    }
+}
@@ -0,0 +1,276 @@
+// Export of the scan tables to CSV, JSON, NDJSON and SQLite, and of capture files to pcap
+
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+use clap::ValueEnum;
+use serde_json::{Map, Value as JsonValue};
+
+use mac_sniff_core::pcap::{self, CaptureFormat, CaptureOptions};
+
+use crate::scan;
+use crate::table::{self, ColumnType, Table, Value};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum ExportFormat {
+    /// One file per table in the output directory
+    Csv,
+    /// One object with an array of rows per table
+    Json,
+    /// One object per row, with the table name under "table"
+    Ndjson,
+    Sqlite,
+    /// Capture files merged into one classic pcap in timestamp order
+    Pcap,
+}
+
+pub fn run(format: ExportFormat, output: Option<&Path>, inputs: &[PathBuf]) -> Result<()> {
+    let required = |what: &str| output.ok_or_else(|| anyhow::anyhow!("{:?} export needs --output {}", format, what));
+    let tables = || -> Result<Vec<Table>> {
+        Ok(table::tables(&scan::load_all(&scan::expand_inputs(inputs, &["bin"])?)?))
+    };
+    match format {
+        ExportFormat::Csv => write_csv(&tables()?, required("<directory>")?),
+        ExportFormat::Json => write_json(&tables()?, output),
+        ExportFormat::Ndjson => write_ndjson(&tables()?, output),
+        ExportFormat::Sqlite => write_sqlite(&tables()?, required("<file>")?),
+        ExportFormat::Pcap => write_pcap(&scan::expand_inputs(inputs, &["pcap", "pcapng"])?, required("<file>")?),
+    }
+}
+
+// Writes to the file, or stdout without one
+fn write_output(output: Option<&Path>, write: impl FnOnce(&mut dyn Write) -> Result<()>) -> Result<()> {
+    match output {
+        Some(path) => {
+            let file = File::create(path).with_context(|| format!("Failed to create {}", path.display()))?;
+            let mut out = BufWriter::new(file);
+            write(&mut out)?;
+            out.flush()?;
+        },
+        None => write(&mut io::stdout().lock())?,
+    }
+    Ok(())
+}
+
+fn write_csv(tables: &[Table], dir: &Path) -> Result<()> {
+    fs::create_dir_all(dir).with_context(|| format!("Failed to create {}", dir.display()))?;
+    for table in tables {
+        let path = dir.join(format!("{}.csv", table.name));
+        write_output(Some(&path), |out| {
+            let header = table.columns.iter().map(|(name, _)| *name).collect::<Vec<_>>().join(",");
+            writeln!(out, "{}", header)?;
+            for row in &table.rows {
+                let fields = row.iter().map(csv_field).collect::<Vec<_>>();
+                writeln!(out, "{}", fields.join(","))?;
+            }
+            Ok(())
+        })?;
+        println!("Wrote {} rows to {}", table.rows.len(), path.display());
+    }
+    Ok(())
+}
+
+fn csv_field(value: &Value) -> String {
+    match value {
+        Value::Null => String::new(),
+        Value::Bool(value) => (*value as u8).to_string(),
+        Value::Int(value) => value.to_string(),
+        Value::Text(text) if text.contains([',', '"', '\n', '\r']) => format!("\"{}\"", text.replace('"', "\"\"")),
+        Value::Text(text) => text.clone(),
+    }
+}
+
+fn write_json(tables: &[Table], output: Option<&Path>) -> Result<()> {
+    let mut document = Map::new();
+    for table in tables {
+        document.insert(table.name.to_string(), JsonValue::Array(json_rows(table).collect()));
+    }
+    write_output(output, |out| {
+        serde_json::to_writer_pretty(&mut *out, &document)?;
+        writeln!(out)?;
+        Ok(())
+    })
+}
+
+fn write_ndjson(tables: &[Table], output: Option<&Path>) -> Result<()> {
+    write_output(output, |out| {
+        for table in tables {
+            for mut row in json_rows(table) {
+                if let JsonValue::Object(fields) = &mut row {
+                    fields.insert("table".to_string(), table.name.into());
+                }
+                writeln!(out, "{}", row)?;
+            }
+        }
+        Ok(())
+    })
+}
+
+fn json_rows(table: &Table) -> impl Iterator<Item = JsonValue> + '_ {
+    table.rows.iter().map(|row| {
+        let fields = table.columns.iter().zip(row)
+            .map(|((name, _), value)| {
+                let value = match value {
+                    Value::Null => JsonValue::Null,
+                    Value::Bool(value) => JsonValue::Bool(*value),
+                    Value::Int(value) => JsonValue::from(*value),
+                    Value::Text(text) => JsonValue::from(text.as_str()),
+                };
+                (name.to_string(), value)
+            })
+            .collect();
+        JsonValue::Object(fields)
+    })
+}
+
+fn write_sqlite(tables: &[Table], path: &Path) -> Result<()> {
+    // Exporting twice into the same database would duplicate every row
+    if path.exists() {
+        return Err(anyhow::anyhow!("{} already exists", path.display()));
+    }
+    let mut connection = rusqlite::Connection::open(path)?;
+    let transaction = connection.transaction()?;
+    for table in tables {
+        let columns = table.columns.iter()
+            .map(|(name, column_type)| match column_type {
+                ColumnType::Integer => format!("{} INTEGER", name),
+                ColumnType::Text => format!("{} TEXT", name),
+            })
+            .collect::<Vec<_>>()
+            .join(", ");
+        transaction.execute(&format!("CREATE TABLE {} ({})", table.name, columns), [])?;
+
+        let placeholders = vec!["?"; table.columns.len()].join(", ");
+        let mut insert = transaction.prepare(&format!("INSERT INTO {} VALUES ({})", table.name, placeholders))?;
+        for row in &table.rows {
+            let values = row.iter().map(|value| match value {
+                Value::Null => rusqlite::types::Value::Null,
+                Value::Bool(value) => rusqlite::types::Value::Integer(*value as i64),
+                Value::Int(value) => rusqlite::types::Value::Integer(*value),
+                Value::Text(text) => rusqlite::types::Value::Text(text.clone()),
+            });
+            insert.execute(rusqlite::params_from_iter(values))?;
+        }
+    }
+    transaction.commit()?;
+    println!("Wrote {}", path.display());
+    Ok(())
+}
+
+fn write_pcap(inputs: &[PathBuf], output: &Path) -> Result<()> {
+    let mut packets = Vec::new();
+    for input in inputs {
+        let data = fs::read(input).with_context(|| format!("Failed to read {}", input.display()))?;
+        let (_, file_packets) = pcap::read_capture(&data)
+            .ok_or_else(|| anyhow::anyhow!("{} is not a pcap or pcapng capture", input.display()))?;
+        packets.extend(file_packets);
+    }
+    packets.sort_by_key(|packet| packet.timestamp_us);
+
+    // file_header adds room for the radiotap header, which the packets already include
+    let snaplen = packets.iter().map(|packet| packet.data.len()).max().unwrap_or(0) as u32;
+    let options = CaptureOptions { format: CaptureFormat::Pcap, snaplen, headers_only: false };
+    let mut out = pcap::file_header(&options);
+    for packet in &packets {
+        out.extend(pcap::raw_packet_record(CaptureFormat::Pcap, packet.timestamp_us, &packet.data, packet.original_len));
+    }
+    fs::write(output, out).with_context(|| format!("Failed to write {}", output.display()))?;
+    println!("Wrote {} packets from {} captures to {}", packets.len(), inputs.len(), output.display());
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::slice;
+    use crate::scan::tests::scan_file;
+
+    const BSSID: [u8; 6] = [0x00, 0x14, 0x6c, 0x00, 0x01, 0x01];
+    const PHONE: [u8; 6] = [0x00, 0x1b, 0x63, 0x00, 0x00, 0x01];
+    const WATCH: [u8; 6] = [0x3c, 0x5a, 0xb4, 0x00, 0x00, 0x03];
+
+    // A fresh directory holding two scans, as a dump leaves them
+    fn dump_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("mac_sniff_host_{}_{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("scan_1_0000.bin"), scan_file(1_700_000_000, BSSID, "office, 2nd floor", &[(PHONE, 100, 10)])).unwrap();
+        fs::write(dir.join("scan_1_0001.bin"), scan_file(1_700_000_060, BSSID, "office, 2nd floor", &[(PHONE, 0, 3), (WATCH, 500, 7)])).unwrap();
+        fs::write(dir.join("notes.txt"), "not a scan").unwrap();
+        dir
+    }
+
+    #[test]
+    fn csv_export() {
+        let dir = dump_dir("csv");
+        let output = dir.join("csv");
+        run(ExportFormat::Csv, Some(&output), slice::from_ref(&dir)).unwrap();
+
+        let scans = fs::read_to_string(output.join("scans.csv")).unwrap();
+        assert_eq!(scans.lines().collect::<Vec<_>>(), [
+            "scan,version,start_time_s,duration_ms,device_id,boot_count,firmware,channel_plan",
+            "scan_1_0000.bin,1,1700000000,60000,24:0a:c4:12:34:56,1,0.1.0,1:500ms 6:500ms 11:500ms",
+            "scan_1_0001.bin,1,1700000060,60000,24:0a:c4:12:34:56,1,0.1.0,1:500ms 6:500ms 11:500ms",
+        ]);
+        let devices = fs::read_to_string(output.join("devices.csv")).unwrap();
+        let macs: Vec<(&str, &str, &str)> = devices.lines().skip(1)
+            .map(|line| {
+                let fields: Vec<&str> = line.split(',').collect();
+                (fields[0], fields[1], fields[7])
+            })
+            .collect();
+        assert_eq!(macs, [
+            ("scan_1_0000.bin", "00:1b:63:00:00:01", "10"),
+            ("scan_1_0001.bin", "00:1b:63:00:00:01", "3"),
+            ("scan_1_0001.bin", "3c:5a:b4:00:00:03", "7"),
+        ]);
+        // SSIDs with commas are quoted
+        let access_points = fs::read_to_string(output.join("access_points.csv")).unwrap();
+        assert!(access_points.lines().nth(1).unwrap().starts_with("scan_1_0000.bin,00:14:6c:00:01:01,\"office, 2nd floor\",0,6,"));
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn json_and_ndjson_export() {
+        let dir = dump_dir("json");
+        let output = dir.join("export.json");
+        run(ExportFormat::Json, Some(&output), slice::from_ref(&dir)).unwrap();
+        let document: JsonValue = serde_json::from_slice(&fs::read(&output).unwrap()).unwrap();
+        assert_eq!(document["scans"].as_array().unwrap().len(), 2);
+        let devices = document["devices"].as_array().unwrap();
+        assert_eq!(devices.len(), 3);
+        assert_eq!(devices[2]["mac"], "3c:5a:b4:00:00:03");
+        assert_eq!(devices[2]["first_seen_ms"], 500);
+        assert_eq!(devices[2]["randomized"], false);
+        assert_eq!(document["associations"][0]["ssid"], "office, 2nd floor");
+        assert_eq!(document["probed_ssids"][1]["ssid"], "home");
+
+        let output = dir.join("export.ndjson");
+        run(ExportFormat::Ndjson, Some(&output), &[dir.join("scan_1_0001.bin")]).unwrap();
+        let rows: Vec<JsonValue> = fs::read_to_string(&output).unwrap().lines().map(|line| serde_json::from_str(line).unwrap()).collect();
+        let tables: Vec<&str> = rows.iter().map(|row| row["table"].as_str().unwrap()).collect();
+        assert_eq!(tables, ["scans", "devices", "devices", "access_points", "associations", "associations", "probed_ssids", "probed_ssids"]);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn sqlite_export() {
+        let dir = dump_dir("sqlite");
+        let output = dir.join("scans.db");
+        run(ExportFormat::Sqlite, Some(&output), slice::from_ref(&dir)).unwrap();
+        let connection = rusqlite::Connection::open(&output).unwrap();
+        let frames: i64 = connection
+            .query_row("SELECT SUM(frames) FROM devices WHERE mac = '00:1b:63:00:00:01'", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(frames, 13);
+        let beacons: i64 = connection.query_row("SELECT SUM(beacons) FROM access_points", [], |row| row.get(0)).unwrap();
+        assert_eq!(beacons, 200);
+        drop(connection);
+
+        // A second export would duplicate every row
+        assert!(run(ExportFormat::Sqlite, Some(&output), slice::from_ref(&dir)).is_err());
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
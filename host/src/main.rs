@@ -0,0 +1,96 @@
+// Host side of mac_sniff: receives dumps from the device and decodes, exports, merges and
+// compares the scan files in them. The scan file, record and dump protocol code comes from the
+// core crate the firmware is built on.
+
+mod scan;
+mod table;
+mod export;
+mod merge;
+mod receive;
+mod report;
+
+use std::path::PathBuf;
+
+use anyhow::Result;
+use clap::{Parser, Subcommand};
+
+use export::ExportFormat;
+
+#[derive(Parser)]
+#[command(name = "mac_sniff_host", version, about = "Receive, decode and export mac_sniff scans")]
+struct Cli {
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Receive a dump from a serial port or pty, or replay one logged to a file
+    Receive {
+        /// Serial port or pty (e.g. /dev/ttyUSB0, COM3), or a regular file to replay
+        source: PathBuf,
+        #[arg(long, default_value_t = 115200)]
+        baud: u32,
+        /// Directory the received files are written to
+        #[arg(short, long, default_value = "dump")]
+        output: PathBuf,
+        /// Send the console `dump` command first instead of waiting for one started from the menu
+        #[arg(long)]
+        start: bool,
+    },
+    /// Export scan files, or capture files for pcap
+    Export {
+        #[arg(short, long, value_enum)]
+        format: ExportFormat,
+        /// Output file, or directory for csv. json and ndjson go to stdout without it.
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+        /// Files, or directories whose files are all read
+        #[arg(required = true)]
+        inputs: Vec<PathBuf>,
+    },
+    /// Merge scan files into one, with the devices seen in any of them
+    Merge {
+        #[arg(short, long)]
+        output: PathBuf,
+        #[arg(required = true)]
+        inputs: Vec<PathBuf>,
+    },
+    /// Devices and access points only in the first scan, only in the second, or in both
+    Diff {
+        first: PathBuf,
+        second: PathBuf,
+        /// List the addresses, not just the counts
+        #[arg(short, long)]
+        list: bool,
+    },
+    /// Summary statistics per scan file and over all of them
+    Summary {
+        #[arg(required = true)]
+        inputs: Vec<PathBuf>,
+    },
+}
+
+fn main() -> Result<()> {
+    match Cli::parse().command {
+        Command::Receive { source, baud, output, start } => receive::run(&source, baud, &output, start),
+        Command::Export { format, output, inputs } => export::run(format, output.as_deref(), &inputs),
+        Command::Merge { output, inputs } => {
+            let scans = scan::load_all(&scan::expand_inputs(&inputs, &["bin"])?)?;
+            std::fs::write(&output, merge::merge(&scans))?;
+            println!("Merged {} scans into {}", scans.len(), output.display());
+            Ok(())
+        },
+        Command::Diff { first, second, list } => {
+            let first = scan::Scan::load(&first)?;
+            let second = scan::Scan::load(&second)?;
+            report::print_diff(&first, &second, list);
+            Ok(())
+        },
+        Command::Summary { inputs } => {
+            let scans = scan::load_all(&scan::expand_inputs(&inputs, &["bin"])?)?;
+            report::print_summary(&scans);
+            Ok(())
+        },
+    }
+}
@@ -0,0 +1,175 @@
+// Merges scans into one scan file. Times are moved onto the clock of the earliest scan, and
+// records of the same device, access point, association or probed SSID are combined.
+
+use std::collections::{BTreeMap, BTreeSet};
+
+use mac_sniff_core::ap::{self, AccessPoint};
+use mac_sniff_core::association::{self, Association};
+use mac_sniff_core::frame::MacAddress;
+use mac_sniff_core::probe_ssid::{self, ProbedSsid};
+use mac_sniff_core::scan_file::{self, RecordType, ScanHeader, Section};
+use mac_sniff_core::stats::{self, MacStats};
+
+use crate::scan::Scan;
+
+pub fn merge(scans: &[Scan]) -> Vec<u8> {
+    let Some(base) = scans.iter().filter_map(|scan| scan.header.as_ref()).min_by_key(|header| header.start_time_s) else {
+        // Only legacy files: the result is a legacy list of every address
+        let macs: BTreeSet<MacAddress> = scans.iter().flat_map(|scan| &scan.devices).map(|device| device.mac).collect();
+        return macs.iter().flatten().copied().collect();
+    };
+    let offset_ms = |scan: &Scan| {
+        let start = scan.header.as_ref().map_or(base.start_time_s, |header| header.start_time_s);
+        (start.saturating_sub(base.start_time_s) * 1000).min(u32::MAX as u64) as u32
+    };
+
+    let mut devices: BTreeMap<MacAddress, Option<MacStats>> = BTreeMap::new();
+    let mut access_points: BTreeMap<MacAddress, AccessPoint> = BTreeMap::new();
+    let mut associations: BTreeMap<(MacAddress, MacAddress), Association> = BTreeMap::new();
+    let mut probed_ssids: BTreeMap<(MacAddress, bool, Vec<u8>), u32> = BTreeMap::new();
+    let mut duration_ms = 0u32;
+
+    for scan in scans {
+        let offset = offset_ms(scan);
+        let shift = |ms: u32| ms.saturating_add(offset);
+        if let Some(header) = &scan.header {
+            duration_ms = duration_ms.max(shift(header.duration_ms));
+        }
+
+        for device in &scan.devices {
+            let merged = devices.entry(device.mac).or_insert(None);
+            let Some(stats) = &device.stats else {
+                continue;
+            };
+            let mut stats = stats.clone();
+            stats.first_seen_ms = shift(stats.first_seen_ms);
+            stats.last_seen_ms = shift(stats.last_seen_ms);
+            match merged {
+                Some(merged) => merged.merge(&stats),
+                None => *merged = Some(stats),
+            }
+        }
+
+        for (bssid, access_point) in &scan.access_points {
+            let mut access_point = access_point.clone();
+            access_point.first_seen_ms = shift(access_point.first_seen_ms);
+            access_point.last_seen_ms = shift(access_point.last_seen_ms);
+            let Some(merged) = access_points.get_mut(bssid) else {
+                access_points.insert(*bssid, access_point);
+                continue;
+            };
+            // The most recent beacon describes the network as it is now
+            if access_point.last_seen_ms >= merged.last_seen_ms {
+                std::mem::swap(&mut merged.info, &mut access_point.info);
+                merged.rx_channel = access_point.rx_channel;
+            }
+            merged.first_seen_ms = merged.first_seen_ms.min(access_point.first_seen_ms);
+            merged.last_seen_ms = merged.last_seen_ms.max(access_point.last_seen_ms);
+            merged.beacons = merged.beacons.saturating_add(access_point.beacons);
+            merged.probe_responses = merged.probe_responses.saturating_add(access_point.probe_responses);
+            merged.rssi_max = merged.rssi_max.max(access_point.rssi_max);
+        }
+
+        for (station, bssid, association) in &scan.associations {
+            let first_seen_ms = shift(association.first_seen_ms);
+            let last_seen_ms = shift(association.last_seen_ms);
+            let merged = associations.entry((*station, *bssid)).or_insert_with(|| Association {
+                first_seen_ms,
+                last_seen_ms,
+                uplink_frames: 0,
+                downlink_frames: 0,
+                station_rssi_max: None,
+            });
+            merged.first_seen_ms = merged.first_seen_ms.min(first_seen_ms);
+            merged.last_seen_ms = merged.last_seen_ms.max(last_seen_ms);
+            merged.uplink_frames = merged.uplink_frames.saturating_add(association.uplink_frames);
+            merged.downlink_frames = merged.downlink_frames.saturating_add(association.downlink_frames);
+            merged.station_rssi_max = merged.station_rssi_max.max(association.station_rssi_max);
+        }
+
+        for probed in &scan.probed_ssids {
+            let count = probed_ssids.entry((probed.mac, probed.hashed, probed.entry.ssid.clone())).or_insert(0);
+            *count = count.saturating_add(probed.entry.count);
+        }
+    }
+
+    let header = ScanHeader { duration_ms, ..base.clone() };
+    let (with_stats, legacy): (Vec<_>, Vec<_>) = devices.iter().partition(|(_, stats)| stats.is_some());
+    let mut sections = vec![Section::new(
+        RecordType::MacStats,
+        stats::RECORD_LEN,
+        stats::serialize_records(with_stats.iter().filter_map(|(mac, stats)| Some((*mac, stats.as_ref()?)))),
+    )];
+    // Addresses only seen in legacy files have no statistics to go with them
+    if !legacy.is_empty() {
+        sections.push(Section::new(RecordType::LegacyMac, 6, legacy.iter().flat_map(|(mac, _)| mac.iter().copied()).collect()));
+    }
+
+    let mut records = Vec::new();
+    for (bssid, access_point) in &access_points {
+        access_point.write_record(bssid, &mut records);
+    }
+    sections.push(Section::new(RecordType::AccessPoint, ap::RECORD_LEN, std::mem::take(&mut records)));
+    for ((station, bssid), association) in &associations {
+        association.write_record(station, bssid, &mut records);
+    }
+    sections.push(Section::new(RecordType::Association, association::RECORD_LEN, std::mem::take(&mut records)));
+    for ((mac, hashed, ssid), count) in probed_ssids {
+        ProbedSsid { ssid, count }.write_record(&mac, hashed, &mut records);
+    }
+    sections.push(Section::new(RecordType::ProbedSsid, probe_ssid::RECORD_LEN, records));
+
+    scan_file::write_scan_file(&header, &sections)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scan::tests::scan_file;
+
+    const BSSID: MacAddress = [0x00, 0x14, 0x6c, 0x00, 0x01, 0x01];
+    const PHONE: MacAddress = [0x00, 0x1b, 0x63, 0x00, 0x00, 0x01];
+    const LAPTOP: MacAddress = [0x00, 0x1b, 0x63, 0x00, 0x00, 0x02];
+    const WATCH: MacAddress = [0x3c, 0x5a, 0xb4, 0x00, 0x00, 0x03];
+
+    fn decode(name: &str, data: &[u8]) -> Scan {
+        Scan::decode(name.to_string(), data).unwrap()
+    }
+
+    #[test]
+    fn merges_onto_the_earliest_clock() {
+        let scans = [
+            decode("scan_1_0001.bin", &scan_file(1_700_000_060, BSSID, "office", &[(LAPTOP, 500, 4), (WATCH, 2000, 7)])),
+            decode("scan_1_0000.bin", &scan_file(1_700_000_000, BSSID, "office-old", &[(PHONE, 100, 10), (LAPTOP, 300, 5)])),
+        ];
+        let merged = decode("merged.bin", &merge(&scans));
+
+        let header = merged.header.as_ref().unwrap();
+        assert_eq!((header.start_time_s, header.duration_ms), (1_700_000_000, 120_000));
+        let devices: Vec<(MacAddress, u32, u32)> = merged.devices.iter()
+            .map(|device| {
+                let stats = device.stats.as_ref().unwrap();
+                (device.mac, stats.first_seen_ms, stats.frame_count)
+            })
+            .collect();
+        assert_eq!(devices, [(PHONE, 100, 10), (LAPTOP, 300, 9), (WATCH, 62_000, 7)]);
+
+        // The later scan has the newer beacon
+        assert_eq!(merged.access_points.len(), 1);
+        let (_, access_point) = &merged.access_points[0];
+        assert_eq!((access_point.info.ssid.as_slice(), access_point.beacons), (&b"office"[..], 200));
+        assert_eq!((access_point.first_seen_ms, access_point.last_seen_ms), (0, 119_000));
+
+        let uplink: Vec<(MacAddress, u32)> = merged.associations.iter().map(|(station, _, association)| (*station, association.uplink_frames)).collect();
+        assert_eq!(uplink, [(PHONE, 10), (LAPTOP, 9), (WATCH, 7)]);
+        let probes: Vec<(MacAddress, u32)> = merged.probed_ssids.iter().map(|probed| (probed.mac, probed.entry.count)).collect();
+        assert_eq!(probes, [(PHONE, 1), (LAPTOP, 2), (WATCH, 1)]);
+    }
+
+    #[test]
+    fn legacy_files_merge_into_an_address_list() {
+        let legacy = |macs: &[MacAddress]| decode("legacy.bin", &macs.concat());
+        let merged = merge(&[legacy(&[LAPTOP, PHONE]), legacy(&[PHONE, WATCH])]);
+        assert_eq!(merged, [PHONE, LAPTOP, WATCH].concat());
+    }
+}
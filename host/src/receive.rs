@@ -0,0 +1,159 @@
+// Receives a dump from the device (see core/src/dump_protocol.rs) over a serial port or pty, or
+// replays one logged to a file. Data of a file still in transfer is kept in <name>.part so an
+// interrupted dump resumes where it stopped.
+
+use std::collections::BTreeMap;
+use std::ffi::OsStr;
+use std::fs::{self, File, OpenOptions};
+use std::io::{BufRead, BufReader, ErrorKind, Write};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use anyhow::{Context, Result};
+
+use mac_sniff_core::dump_protocol::{file_name, DumpReceiver};
+
+const READ_TIMEOUT: Duration = Duration::from_secs(1);
+const PART_EXTENSION: &str = "part";
+
+struct Session {
+    receiver: DumpReceiver,
+    output: PathBuf,
+    // Path of the file in transfer and how much of it is in its .part file
+    part: Option<(String, usize)>,
+    saved: usize,
+}
+
+impl Session {
+    fn new(output: &Path) -> Result<Self> {
+        fs::create_dir_all(output).with_context(|| format!("Failed to create {}", output.display()))?;
+        let mut partial = BTreeMap::new();
+        for entry in fs::read_dir(output)? {
+            let path = entry?.path();
+            if path.extension().is_some_and(|extension| extension == PART_EXTENSION) {
+                if let Some(name) = path.file_stem() {
+                    partial.insert(name.to_string_lossy().to_string(), fs::read(&path)?);
+                }
+            }
+        }
+        if !partial.is_empty() {
+            println!("Resuming {} partially received files", partial.len());
+        }
+        Ok(Session { receiver: DumpReceiver::new(partial), output: output.to_path_buf(), part: None, saved: 0 })
+    }
+
+    // Feeds one line to the receiver and returns the reply for the device
+    fn handle_line(&mut self, line: &str) -> Result<Option<String>> {
+        let reply = self.receiver.handle_line(line);
+
+        match self.receiver.in_progress() {
+            Some((path, data)) => match &mut self.part {
+                Some((current, written)) if current == path => {
+                    if data.len() > *written {
+                        let mut file = OpenOptions::new().append(true).open(part_path(&self.output, path)?)?;
+                        file.write_all(&data[*written..])?;
+                        *written = data.len();
+                    }
+                },
+                // A new file, or the same one started over: the .part file holds what the
+                // receiver kept
+                _ => {
+                    println!("Receiving {}", path);
+                    fs::write(part_path(&self.output, path)?, data)?;
+                    self.part = Some((path.to_string(), data.len()));
+                },
+            },
+            None => self.part = None,
+        }
+
+        while self.saved < self.receiver.files.len() {
+            let file = &self.receiver.files[self.saved];
+            let target = self.output.join(local_name(&file.path)?);
+            fs::write(&target, &file.data).with_context(|| format!("Failed to write {}", target.display()))?;
+            let part = part_path(&self.output, &file.path)?;
+            if part.exists() {
+                fs::remove_file(part)?;
+            }
+            println!("Saved {} ({} bytes)", target.display(), file.data.len());
+            self.saved += 1;
+        }
+        Ok(reply)
+    }
+}
+
+// The name a file from the device is written under, which has to be a plain name on this
+// host as well; the receiver already refuses anything else
+fn local_name(path: &str) -> Result<&str> {
+    file_name(path)
+        .filter(|name| Path::new(name).file_name() == Some(OsStr::new(name)))
+        .ok_or_else(|| anyhow::anyhow!("Refusing file name {:?}", path))
+}
+
+fn part_path(output: &Path, path: &str) -> Result<PathBuf> {
+    Ok(output.join(format!("{}.{}", local_name(path)?, PART_EXTENSION)))
+}
+
+pub fn run(source: &Path, baud: u32, output: &Path, start: bool) -> Result<()> {
+    let mut session = Session::new(output)?;
+
+    if source.is_file() {
+        // Nobody answers a log, so only what arrived intact the first time is kept
+        println!("Replaying {}", source.display());
+        let reader = BufReader::new(File::open(source)?);
+        for line in reader.lines() {
+            session.handle_line(&line?)?;
+            if session.receiver.finished {
+                break;
+            }
+        }
+    } else {
+        let port = serialport::new(source.to_string_lossy(), baud)
+            .timeout(READ_TIMEOUT)
+            .open()
+            .with_context(|| format!("Failed to open {}", source.display()))?;
+        let mut writer = port.try_clone()?;
+        let mut reader = BufReader::new(port);
+        if start {
+            writer.write_all(b"dump\n")?;
+        }
+        println!("Waiting for a dump on {} (Ctrl+C to abort)", source.display());
+
+        let mut line = Vec::new();
+        while !session.receiver.finished {
+            match reader.read_until(b'\n', &mut line) {
+                Ok(0) => return Err(anyhow::anyhow!("{} closed", source.display())),
+                Ok(_) if line.ends_with(b"\n") => {
+                    let text = String::from_utf8_lossy(&line).to_string();
+                    line.clear();
+                    if let Some(reply) = session.handle_line(&text)? {
+                        writer.write_all(format!("{}\n", reply).as_bytes())?;
+                    }
+                },
+                // A partial line stays in the buffer until the rest arrives
+                Ok(_) => {},
+                Err(e) if e.kind() == ErrorKind::TimedOut => {},
+                Err(e) => return Err(e.into()),
+            }
+        }
+    }
+
+    if !session.receiver.finished {
+        println!("Dump did not finish; run again to resume");
+    }
+    println!("{} files saved to {}", session.saved, output.display());
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn names_stay_in_the_output_directory() {
+        assert_eq!(local_name("/spiffs/scan_1_0000.bin").unwrap(), "scan_1_0000.bin");
+        for path in ["..", "/spiffs/..", "..\\evil.bin", "C:evil", ""] {
+            assert!(local_name(path).is_err(), "{}", path);
+        }
+        assert_eq!(part_path(Path::new("dump"), "/spiffs/cap_0.pcap").unwrap(), Path::new("dump").join("cap_0.pcap.part"));
+    }
+}
@@ -0,0 +1,151 @@
+// Text reports: summary statistics and the difference between two scans
+
+use std::collections::BTreeSet;
+
+use mac_sniff_core::frame::MacAddress;
+use mac_sniff_core::mac_addr::{self, CountPolicy};
+use mac_sniff_core::oui;
+use mac_sniff_core::scan_file::ScanHeader;
+
+use crate::scan::{format_mac, Scan};
+
+// Same policy the firmware counts devices with
+const COUNT_POLICY: CountPolicy = CountPolicy::DEVICES;
+const TOP_VENDORS: usize = 10;
+
+struct Totals {
+    devices: BTreeSet<MacAddress>,
+    access_points: BTreeSet<MacAddress>,
+    associations: usize,
+    probing: BTreeSet<MacAddress>,
+}
+
+impl Totals {
+    fn new() -> Self {
+        Totals { devices: BTreeSet::new(), access_points: BTreeSet::new(), associations: 0, probing: BTreeSet::new() }
+    }
+
+    fn add(&mut self, scan: &Scan) {
+        self.devices.extend(scan.devices.iter().map(|device| device.mac).filter(|mac| COUNT_POLICY.counts_mac(mac)));
+        self.access_points.extend(scan.access_points.iter().map(|(bssid, _)| *bssid));
+        self.associations += scan.associations.len();
+        self.probing.extend(scan.probed_ssids.iter().map(|probed| probed.mac));
+    }
+
+    fn print(&self) {
+        let randomized = self.devices.iter().filter(|mac| mac_addr::classify(mac).locally_administered).count();
+        println!("  Devices:       {} ({} randomized)", self.devices.len(), randomized);
+        let vendors = oui::vendor_counts(&self.devices);
+        println!("  Vendors:       {}", oui::format_top_vendors(&vendors[..vendors.len().min(TOP_VENDORS)], usize::MAX));
+        println!("  Access points: {}", self.access_points.len());
+        println!("  Associations:  {}", self.associations);
+        println!("  Probing:       {} devices", self.probing.len());
+    }
+}
+
+fn print_header(header: Option<&ScanHeader>) {
+    match header {
+        Some(header) => {
+            println!("  Device:        {} (boot {}, firmware {})", format_mac(&header.device_id), header.boot_count, header.firmware_version);
+            println!("  Start:         {} (unix time), duration {} s", header.start_time_s, header.duration_ms / 1000);
+        },
+        None => println!("  Legacy headerless file"),
+    }
+}
+
+pub fn print_summary(scans: &[Scan]) {
+    let mut all = Totals::new();
+    for scan in scans {
+        let mut totals = Totals::new();
+        totals.add(scan);
+        all.add(scan);
+        println!("{}", scan.name);
+        print_header(scan.header.as_ref());
+        totals.print();
+    }
+    if scans.len() > 1 {
+        let start = scans.iter().filter_map(|scan| scan.header.as_ref()).map(|header| header.start_time_s).min();
+        let end = scans.iter()
+            .filter_map(|scan| scan.header.as_ref())
+            .map(|header| header.start_time_s + header.duration_ms as u64 / 1000)
+            .max();
+        println!("All {} scans", scans.len());
+        if let (Some(start), Some(end)) = (start, end) {
+            println!("  Span:          {} to {} (unix time)", start, end);
+        }
+        all.print();
+    }
+}
+
+// Addresses only in the first set, only in the second, and how many are in both
+struct SetDiff {
+    only_first: Vec<MacAddress>,
+    only_second: Vec<MacAddress>,
+    both: usize,
+}
+
+impl SetDiff {
+    fn new(first: &BTreeSet<MacAddress>, second: &BTreeSet<MacAddress>) -> Self {
+        SetDiff {
+            only_first: first.difference(second).copied().collect(),
+            only_second: second.difference(first).copied().collect(),
+            both: first.intersection(second).count(),
+        }
+    }
+
+    fn print(&self, label: &str, list: bool) {
+        println!("{}: {} only in first, {} only in second, {} in both", label, self.only_first.len(), self.only_second.len(), self.both);
+        if list {
+            for (sign, macs) in [("-", &self.only_first), ("+", &self.only_second)] {
+                for mac in macs {
+                    let line = format!("{} {} {}", sign, format_mac(mac), oui::lookup(mac).unwrap_or_default());
+                    println!("{}", line.trim_end());
+                }
+            }
+        }
+    }
+}
+
+fn devices(scan: &Scan) -> BTreeSet<MacAddress> {
+    scan.devices.iter().map(|device| device.mac).filter(|mac| COUNT_POLICY.counts_mac(mac)).collect()
+}
+
+fn access_points(scan: &Scan) -> BTreeSet<MacAddress> {
+    scan.access_points.iter().map(|(bssid, _)| *bssid).collect()
+}
+
+pub fn print_diff(first: &Scan, second: &Scan, list: bool) {
+    println!("--- {}", first.name);
+    println!("+++ {}", second.name);
+    SetDiff::new(&devices(first), &devices(second)).print("Devices", list);
+    SetDiff::new(&access_points(first), &access_points(second)).print("Access points", list);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scan::tests::scan_file;
+
+    #[test]
+    fn diff_of_two_scans() {
+        let office = [0x00, 0x14, 0x6c, 0x00, 0x01, 0x01];
+        let cafe = [0x00, 0x14, 0x6c, 0x00, 0x02, 0x02];
+        let phone = [0x00, 0x1b, 0x63, 0x00, 0x00, 0x01];
+        let laptop = [0x00, 0x1b, 0x63, 0x00, 0x00, 0x02];
+        let watch = [0x3c, 0x5a, 0xb4, 0x00, 0x00, 0x03];
+        let randomized = [0xda, 0xa1, 0x19, 0x2c, 0x31, 0x5e];
+        let multicast = [0x01, 0x00, 0x5e, 0x00, 0x00, 0xfb];
+        let decode = |data: Vec<u8>| Scan::decode("scan.bin".to_string(), &data).unwrap();
+        let first = decode(scan_file(1_700_000_000, office, "office", &[(phone, 0, 1), (laptop, 0, 1), (multicast, 0, 1)]));
+        let second = decode(scan_file(1_700_000_060, cafe, "cafe", &[(laptop, 0, 1), (watch, 0, 1), (randomized, 0, 1)]));
+
+        // Multicast addresses are not devices
+        let diff = SetDiff::new(&devices(&first), &devices(&second));
+        assert_eq!(diff.only_first, [phone]);
+        assert_eq!(diff.only_second, [watch, randomized]);
+        assert_eq!(diff.both, 1);
+
+        let diff = SetDiff::new(&access_points(&first), &access_points(&second));
+        assert_eq!((diff.only_first, diff.only_second, diff.both), (vec![office], vec![cafe], 0));
+    }
+}
@@ -0,0 +1,213 @@
+// Scan files decoded into their records
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+
+use mac_sniff_core::ap::AccessPoint;
+use mac_sniff_core::association::Association;
+use mac_sniff_core::frame::MacAddress;
+use mac_sniff_core::probe_ssid::ProbedSsid;
+use mac_sniff_core::scan_file::{self, RecordType, ScanHeader};
+use mac_sniff_core::stats::MacStats;
+
+pub struct Device {
+    pub mac: MacAddress,
+    // None for the bare addresses of legacy headerless files
+    pub stats: Option<MacStats>,
+}
+
+pub struct ProbedSsidEntry {
+    pub mac: MacAddress,
+    pub entry: ProbedSsid,
+    pub hashed: bool,
+}
+
+pub struct Scan {
+    // File name the scan was read from
+    pub name: String,
+    pub version: u16,
+    // None for legacy headerless files
+    pub header: Option<ScanHeader>,
+    pub devices: Vec<Device>,
+    pub access_points: Vec<(MacAddress, AccessPoint)>,
+    // (station, bssid, association)
+    pub associations: Vec<(MacAddress, MacAddress, Association)>,
+    pub probed_ssids: Vec<ProbedSsidEntry>,
+}
+
+impl Scan {
+    pub fn load(path: &Path) -> Result<Scan> {
+        let data = fs::read(path).with_context(|| format!("Failed to read {}", path.display()))?;
+        let name = path.file_name().map(|name| name.to_string_lossy().to_string()).unwrap_or_default();
+        Scan::decode(name, &data).with_context(|| format!("Failed to decode {}", path.display()))
+    }
+
+    pub fn decode(name: String, data: &[u8]) -> Result<Scan> {
+        let file = scan_file::read_scan_file(data)?;
+        let mut scan = Scan {
+            name,
+            version: file.version,
+            header: file.header,
+            devices: Vec::new(),
+            access_points: Vec::new(),
+            associations: Vec::new(),
+            probed_ssids: Vec::new(),
+        };
+        // read_scan_file has checked the record lengths; fields appended by newer firmware are
+        // ignored
+        for section in &file.sections {
+            let records = section.records();
+            match section.record_type {
+                RecordType::LegacyMac => scan.devices.extend(records.filter(|record| record.len() >= 6).map(|record| {
+                    let mut mac = [0u8; 6];
+                    mac.copy_from_slice(&record[..6]);
+                    Device { mac, stats: None }
+                })),
+                RecordType::MacStats => scan.devices.extend(records
+                    .filter_map(MacStats::read_record)
+                    .map(|(mac, stats)| Device { mac, stats: Some(stats) })),
+                RecordType::AccessPoint => scan.access_points.extend(records.filter_map(AccessPoint::read_record)),
+                RecordType::Association => scan.associations.extend(records.filter_map(Association::read_record)),
+                RecordType::ProbedSsid => scan.probed_ssids.extend(records
+                    .filter_map(ProbedSsid::read_record)
+                    .map(|(mac, entry, hashed)| ProbedSsidEntry { mac, entry, hashed })),
+                RecordType::Unknown(_) => {},
+            }
+        }
+        Ok(scan)
+    }
+
+    pub fn ssid_of(&self, bssid: &MacAddress) -> Option<String> {
+        self.access_points.iter()
+            .find(|(candidate, _)| candidate == bssid)
+            .map(|(_, access_point)| String::from_utf8_lossy(&access_point.info.ssid).to_string())
+    }
+}
+
+pub fn format_mac(mac: &MacAddress) -> String {
+    mac.iter().map(|byte| format!("{:02x}", byte)).collect::<Vec<_>>().join(":")
+}
+
+// Files as given, and the files with one of `extensions` directly inside the directories
+// given, in name order
+pub fn expand_inputs(inputs: &[PathBuf], extensions: &[&str]) -> Result<Vec<PathBuf>> {
+    let mut paths = Vec::new();
+    for input in inputs {
+        if input.is_dir() {
+            let mut entries = fs::read_dir(input)
+                .with_context(|| format!("Failed to read {}", input.display()))?
+                .map(|entry| entry.map(|entry| entry.path()))
+                .collect::<std::io::Result<Vec<_>>>()?;
+            entries.retain(|path| {
+                path.is_file() && path.extension().is_some_and(|extension| extensions.iter().any(|wanted| extension == *wanted))
+            });
+            entries.sort();
+            paths.extend(entries);
+        } else {
+            paths.push(input.clone());
+        }
+    }
+    if paths.is_empty() {
+        return Err(anyhow::anyhow!("No input files"));
+    }
+    Ok(paths)
+}
+
+pub fn load_all(paths: &[PathBuf]) -> Result<Vec<Scan>> {
+    paths.iter().map(|path| Scan::load(path)).collect()
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    use mac_sniff_core::ap::{self, BeaconInfo, Security};
+    use mac_sniff_core::association;
+    use mac_sniff_core::probe_ssid;
+    use mac_sniff_core::scan_file::Section;
+    use mac_sniff_core::stats;
+
+    pub const DEVICE_ID: MacAddress = [0x24, 0x0a, 0xc4, 0x12, 0x34, 0x56];
+
+    // A scan file as the firmware writes it: `devices` as (mac, first_seen_ms, frames), all of
+    // them associated to one access point and probing for "home"
+    pub fn scan_file(start_time_s: u64, bssid: MacAddress, ssid: &str, devices: &[(MacAddress, u32, u32)]) -> Vec<u8> {
+        let header = ScanHeader {
+            start_time_s,
+            duration_ms: 60_000,
+            device_id: DEVICE_ID,
+            boot_count: 1,
+            firmware_version: "0.1.0".to_string(),
+            channel_plan: vec![(1, 500), (6, 500), (11, 500)],
+        };
+        let stats: Vec<(MacAddress, MacStats)> = devices.iter()
+            .map(|&(mac, first_seen_ms, frames)| {
+                let mut stats = MacStats::new(first_seen_ms);
+                stats.last_seen_ms = first_seen_ms + 1000;
+                stats.frame_count = frames;
+                stats.tx_count = frames;
+                stats.rssi_min = -70;
+                stats.rssi_max = -50;
+                stats.channels = 1 << 6;
+                (mac, stats)
+            })
+            .collect();
+        let access_point = AccessPoint {
+            info: BeaconInfo {
+                ssid: ssid.as_bytes().to_vec(),
+                hidden: false,
+                channel: Some(6),
+                beacon_interval: 100,
+                capability: 0x0411,
+                security: Security::Wpa2,
+                ht: true,
+                vht: false,
+                he: false,
+                country: Some(*b"DE"),
+                manufacturer: None,
+            },
+            first_seen_ms: 0,
+            last_seen_ms: 59_000,
+            beacons: 100,
+            probe_responses: 2,
+            rssi_max: -40,
+            rx_channel: 6,
+        };
+        let mut access_points = Vec::new();
+        access_point.write_record(&bssid, &mut access_points);
+        let mut associations = Vec::new();
+        let mut probed_ssids = Vec::new();
+        for &(mac, first_seen_ms, frames) in devices {
+            let association = Association {
+                first_seen_ms,
+                last_seen_ms: first_seen_ms + 1000,
+                uplink_frames: frames,
+                downlink_frames: 1,
+                station_rssi_max: Some(-50),
+            };
+            association.write_record(&mac, &bssid, &mut associations);
+            ProbedSsid { ssid: b"home".to_vec(), count: 1 }.write_record(&mac, false, &mut probed_ssids);
+        }
+        scan_file::write_scan_file(&header, &[
+            Section::new(RecordType::MacStats, stats::RECORD_LEN, stats::serialize_records(stats.iter().map(|(mac, stats)| (mac, stats)))),
+            Section::new(RecordType::AccessPoint, ap::RECORD_LEN, access_points),
+            Section::new(RecordType::Association, association::RECORD_LEN, associations),
+            Section::new(RecordType::ProbedSsid, probe_ssid::RECORD_LEN, probed_ssids),
+        ])
+    }
+
+    #[test]
+    fn decodes_every_record_type() {
+        let device = [0x00, 0x1b, 0x63, 0x00, 0x00, 0x01];
+        let bssid = [0x00, 0x14, 0x6c, 0x00, 0x01, 0x01];
+        let scan = Scan::decode("scan_1_0000.bin".to_string(), &scan_file(1_700_000_000, bssid, "office", &[(device, 500, 12)])).unwrap();
+        assert_eq!(scan.header.as_ref().map(|header| header.start_time_s), Some(1_700_000_000));
+        assert_eq!(scan.devices.len(), 1);
+        assert_eq!(scan.devices[0].stats.as_ref().map(|stats| stats.frame_count), Some(12));
+        assert_eq!(scan.ssid_of(&bssid).as_deref(), Some("office"));
+        assert_eq!(scan.associations.len(), 1);
+        assert_eq!((scan.associations[0].0, scan.associations[0].1), (device, bssid));
+        assert_eq!(scan.probed_ssids[0].entry.name(false), "home");
+    }
+}
@@ -0,0 +1,243 @@
+// Scans flattened into tables, one row per record, shared by every export format. Each row
+// starts with the name of the scan file it came from.
+
+use mac_sniff_core::ap::AccessPoint;
+use mac_sniff_core::frame::MacAddress;
+use mac_sniff_core::mac_addr;
+use mac_sniff_core::oui;
+use mac_sniff_core::stats::SAVED_SUBTYPES;
+
+use crate::scan::{format_mac, Device, ProbedSsidEntry, Scan};
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    Null,
+    Bool(bool),
+    Int(i64),
+    Text(String),
+}
+
+impl From<bool> for Value {
+    fn from(value: bool) -> Self {
+        Value::Bool(value)
+    }
+}
+
+impl From<u32> for Value {
+    fn from(value: u32) -> Self {
+        Value::Int(value as i64)
+    }
+}
+
+impl From<i8> for Value {
+    fn from(value: i8) -> Self {
+        Value::Int(value as i64)
+    }
+}
+
+impl From<u8> for Value {
+    fn from(value: u8) -> Self {
+        Value::Int(value as i64)
+    }
+}
+
+impl From<u16> for Value {
+    fn from(value: u16) -> Self {
+        Value::Int(value as i64)
+    }
+}
+
+impl From<u64> for Value {
+    fn from(value: u64) -> Self {
+        Value::Int(value as i64)
+    }
+}
+
+impl From<String> for Value {
+    fn from(value: String) -> Self {
+        Value::Text(value)
+    }
+}
+
+impl From<&str> for Value {
+    fn from(value: &str) -> Self {
+        Value::Text(value.to_string())
+    }
+}
+
+impl<T: Into<Value>> From<Option<T>> for Value {
+    fn from(value: Option<T>) -> Self {
+        value.map(Into::into).unwrap_or(Value::Null)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ColumnType {
+    Integer,
+    Text,
+}
+
+pub struct Table {
+    pub name: &'static str,
+    pub columns: &'static [(&'static str, ColumnType)],
+    pub rows: Vec<Vec<Value>>,
+}
+
+use ColumnType::{Integer, Text};
+
+const SCAN_COLUMNS: &[(&str, ColumnType)] = &[
+    ("scan", Text), ("version", Integer), ("start_time_s", Integer), ("duration_ms", Integer),
+    ("device_id", Text), ("boot_count", Integer), ("firmware", Text), ("channel_plan", Text),
+];
+const DEVICE_COLUMNS: &[(&str, ColumnType)] = &[
+    ("scan", Text), ("mac", Text), ("vendor", Text), ("randomized", Integer), ("transmitter", Integer),
+    ("first_seen_ms", Integer), ("last_seen_ms", Integer), ("frames", Integer), ("tx_frames", Integer),
+    ("rssi_min", Integer), ("rssi_max", Integer), ("rssi_mean", Integer), ("channels", Text), ("subtypes", Text),
+];
+const ACCESS_POINT_COLUMNS: &[(&str, ColumnType)] = &[
+    ("scan", Text), ("bssid", Text), ("ssid", Text), ("hidden", Integer), ("channel", Integer), ("security", Text),
+    ("standards", Text), ("country", Text), ("beacon_interval", Integer), ("rssi_max", Integer), ("beacons", Integer),
+    ("probe_responses", Integer), ("first_seen_ms", Integer), ("last_seen_ms", Integer), ("manufacturer", Text),
+    ("vendor", Text),
+];
+const ASSOCIATION_COLUMNS: &[(&str, ColumnType)] = &[
+    ("scan", Text), ("bssid", Text), ("ssid", Text), ("station", Text), ("uplink_frames", Integer),
+    ("downlink_frames", Integer), ("station_rssi_max", Integer), ("first_seen_ms", Integer), ("last_seen_ms", Integer),
+];
+const PROBED_SSID_COLUMNS: &[(&str, ColumnType)] = &[
+    ("scan", Text), ("mac", Text), ("ssid", Text), ("hashed", Integer), ("count", Integer),
+];
+
+const FRAME_TYPES: [&str; 4] = ["mgmt", "ctrl", "data", "ext"];
+
+pub fn tables(scans: &[Scan]) -> Vec<Table> {
+    let mut tables = [
+        Table { name: "scans", columns: SCAN_COLUMNS, rows: Vec::new() },
+        Table { name: "devices", columns: DEVICE_COLUMNS, rows: Vec::new() },
+        Table { name: "access_points", columns: ACCESS_POINT_COLUMNS, rows: Vec::new() },
+        Table { name: "associations", columns: ASSOCIATION_COLUMNS, rows: Vec::new() },
+        Table { name: "probed_ssids", columns: PROBED_SSID_COLUMNS, rows: Vec::new() },
+    ];
+    for scan in scans {
+        tables[0].rows.push(scan_row(scan));
+        tables[1].rows.extend(scan.devices.iter().map(|device| device_row(scan, device)));
+        tables[2].rows.extend(scan.access_points.iter().map(|(bssid, access_point)| access_point_row(scan, bssid, access_point)));
+        tables[3].rows.extend(scan.associations.iter().map(|(station, bssid, association)| vec![
+            scan.name.as_str().into(),
+            format_mac(bssid).into(),
+            scan.ssid_of(bssid).into(),
+            format_mac(station).into(),
+            association.uplink_frames.into(),
+            association.downlink_frames.into(),
+            association.station_rssi_max.into(),
+            association.first_seen_ms.into(),
+            association.last_seen_ms.into(),
+        ]));
+        tables[4].rows.extend(scan.probed_ssids.iter().map(|probed| probed_ssid_row(scan, probed)));
+    }
+    tables.into()
+}
+
+fn scan_row(scan: &Scan) -> Vec<Value> {
+    let Some(header) = &scan.header else {
+        let mut row = vec![scan.name.as_str().into(), scan.version.into()];
+        row.resize(SCAN_COLUMNS.len(), Value::Null);
+        return row;
+    };
+    let plan = header.channel_plan.iter()
+        .map(|(channel, dwell_ms)| format!("{}:{}ms", channel, dwell_ms))
+        .collect::<Vec<_>>()
+        .join(" ");
+    vec![
+        scan.name.as_str().into(),
+        scan.version.into(),
+        header.start_time_s.into(),
+        header.duration_ms.into(),
+        format_mac(&header.device_id).into(),
+        header.boot_count.into(),
+        header.firmware_version.as_str().into(),
+        plan.into(),
+    ]
+}
+
+fn device_row(scan: &Scan, device: &Device) -> Vec<Value> {
+    let mut row = vec![
+        scan.name.as_str().into(),
+        format_mac(&device.mac).into(),
+        oui::lookup(&device.mac).into(),
+        mac_addr::classify(&device.mac).locally_administered.into(),
+    ];
+    let Some(stats) = &device.stats else {
+        row.resize(DEVICE_COLUMNS.len(), Value::Null);
+        return row;
+    };
+    let transmitter = stats.seen_as_transmitter();
+    row.extend([
+        transmitter.into(),
+        stats.first_seen_ms.into(),
+        stats.last_seen_ms.into(),
+        stats.frame_count.into(),
+        stats.tx_count.into(),
+        transmitter.then_some(stats.rssi_min).into(),
+        transmitter.then_some(stats.rssi_max).into(),
+        stats.rssi_mean().into(),
+        channels(stats.channels).into(),
+        subtypes(&stats.top_subtypes(SAVED_SUBTYPES)).into(),
+    ]);
+    row
+}
+
+fn access_point_row(scan: &Scan, bssid: &MacAddress, access_point: &AccessPoint) -> Vec<Value> {
+    let info = &access_point.info;
+    let standards = [(info.ht, "HT"), (info.vht, "VHT"), (info.he, "HE")]
+        .iter()
+        .filter(|(supported, _)| *supported)
+        .map(|(_, name)| *name)
+        .collect::<Vec<_>>()
+        .join("/");
+    vec![
+        scan.name.as_str().into(),
+        format_mac(bssid).into(),
+        String::from_utf8_lossy(&info.ssid).to_string().into(),
+        info.hidden.into(),
+        access_point.channel().into(),
+        info.security.label().into(),
+        standards.into(),
+        info.country.map(|country| String::from_utf8_lossy(&country).to_string()).into(),
+        info.beacon_interval.into(),
+        access_point.rssi_max.into(),
+        access_point.beacons.into(),
+        access_point.probe_responses.into(),
+        access_point.first_seen_ms.into(),
+        access_point.last_seen_ms.into(),
+        info.manufacturer.clone().into(),
+        oui::lookup(bssid).into(),
+    ]
+}
+
+fn probed_ssid_row(scan: &Scan, probed: &ProbedSsidEntry) -> Vec<Value> {
+    vec![
+        scan.name.as_str().into(),
+        format_mac(&probed.mac).into(),
+        probed.entry.name(probed.hashed).into(),
+        probed.hashed.into(),
+        probed.entry.count.into(),
+    ]
+}
+
+// Channel bitmask as 1/6/11
+fn channels(mask: u16) -> String {
+    (0..16)
+        .filter(|channel| mask & (1 << channel) != 0)
+        .map(|channel| channel.to_string())
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+// (type << 4 | subtype, count) pairs as mgmt.4=12 data.8=3
+fn subtypes(entries: &[(u8, u32)]) -> String {
+    entries.iter()
+        .map(|(key, count)| format!("{}.{}={}", FRAME_TYPES[(key >> 4) as usize & 3], key & 0x0F, count))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
@@ -3,81 +3,60 @@ use esp_idf_hal::{
     gpio::{AnyInputPin, Gpio0, Input, InterruptType, Level, PinDriver, Pull},
     prelude::*,
 };
-use std::{
-    sync::{
-        atomic::{AtomicBool, AtomicU32, Ordering},
-        Arc, Mutex,
-    }
-};
-use esp_idf_svc::hal::delay::FreeRtos;
+use std::sync::Mutex;
+
+use mac_sniff_core::gesture::{ButtonEvent, GestureDecoder};
+use mac_sniff_core::hal::{Clock, InputDevice};
+
+use crate::clock::EspClock;
 
 // We'll use GPIO0 as that's typically where the PRG button is connected
 // on Heltec boards, but you might need to adjust this based on your board
 const PRG_BUTTON_PIN: i32 = 0;
 
-// Long press duration in milliseconds
-const LONG_PRESS_DURATION_MS: u32 = 2000; // 1 second for long press
+// Press tracking shared by the interrupt and polling; the decoding itself is in the core crate
+static DECODER: Mutex<GestureDecoder> = Mutex::new(GestureDecoder::new());
+static BUTTON_EVENT: Mutex<ButtonEvent> = Mutex::new(ButtonEvent::None);
+
+type ButtonType = PinDriver<'static, Gpio0, Input>;
 
-// Button states
-#[derive(Debug, Clone, Copy, PartialEq)]
-pub enum ButtonEvent {
-    None,
-    ShortPress,
-    LongPress,
+pub struct Button {
+    pin: ButtonType,
 }
 
-// Button state tracking
-static BUTTON_PRESSED: AtomicBool = AtomicBool::new(false);
-static BUTTON_RELEASED: AtomicBool = AtomicBool::new(false);
-static PRESS_START_TIME: AtomicU32 = AtomicU32::new(0);
-static PRESS_DURATION: AtomicU32 = AtomicU32::new(0);
-static BUTTON_EVENT: Mutex<ButtonEvent> = Mutex::new(ButtonEvent::None);
+impl Button {
+    pub fn is_pressed(&self) -> bool {
+        is_button_pressed(&self.pin)
+    }
+}
 
-type ButtonType = PinDriver<'static, Gpio0, Input>;
+impl InputDevice for Button {
+    fn poll(&mut self) -> ButtonEvent {
+        update_button_state(&self.pin);
+        check_button_event()
+    }
+}
 
-// Get current time in milliseconds
-fn current_time_ms() -> u32 {
-    let now = unsafe { esp_idf_svc::sys::esp_timer_get_time() } as u32;
-    now / 1000 // Convert microseconds to milliseconds
+fn update_decoder(pressed: Option<bool>) {
+    let mut decoder = DECODER.lock().unwrap();
+    // The interrupt fires on every edge without reading the level, so an edge flips the state
+    let pressed = pressed.unwrap_or(!decoder.is_pressed());
+    let event = decoder.update(pressed, EspClock.now_ms() as u32);
+    drop(decoder);
+
+    if event != ButtonEvent::None {
+        log::debug!("Button event {:?}", event);
+        *BUTTON_EVENT.lock().unwrap() = event;
+    }
 }
 
-// Button interrupt handler - simpler version that just sets flags
+// Button interrupt handler
 fn button_isr() {
-    // When the interrupt fires, check the current time
-    let current_time = current_time_ms();
-    
-    // If the button wasn't previously pressed, this is a new press
-    if !BUTTON_PRESSED.load(Ordering::SeqCst) {
-        BUTTON_PRESSED.store(true, Ordering::SeqCst);
-        BUTTON_RELEASED.store(false, Ordering::SeqCst);
-        PRESS_START_TIME.store(current_time, Ordering::SeqCst);
-        log::debug!("Button pressed at {} ms", current_time);
-    } else {
-        // Button was already pressed, so this must be a release
-        BUTTON_RELEASED.store(true, Ordering::SeqCst);
-        
-        // Calculate press duration
-        let start_time = PRESS_START_TIME.load(Ordering::SeqCst);
-        let duration = current_time.saturating_sub(start_time);
-        PRESS_DURATION.store(duration, Ordering::SeqCst);
-        
-        log::debug!("Button released, duration: {} ms", duration);
-        
-        // Reset pressed flag
-        BUTTON_PRESSED.store(false, Ordering::SeqCst);
-        
-        // Determine if it was a short or long press
-        let mut event = BUTTON_EVENT.lock().unwrap();
-        if duration >= LONG_PRESS_DURATION_MS {
-            *event = ButtonEvent::LongPress;
-        } else {
-            *event = ButtonEvent::ShortPress;
-        }
-    }
+    update_decoder(None);
 }
 
 // Function to check what type of button event occurred and reset the flag
-pub fn check_button_event() -> ButtonEvent {
+fn check_button_event() -> ButtonEvent {
     let mut event = BUTTON_EVENT.lock().unwrap();
     let result = *event;
     if result != ButtonEvent::None {
@@ -87,77 +66,18 @@ pub fn check_button_event() -> ButtonEvent {
 }
 
 // Check if button is currently pressed (manual polling)
-pub fn is_button_pressed(button: &ButtonType) -> bool {
+fn is_button_pressed(button: &ButtonType) -> bool {
     button.get_level() == Level::Low
 }
 
 // Update button state - call this in your main loop
-pub fn update_button_state(button: &ButtonType) {
-    // Check for button state changes manually (works with interrupt or polling)
-    let is_pressed = is_button_pressed(button);
-    let was_pressed = BUTTON_PRESSED.load(Ordering::SeqCst);
-    let was_released = BUTTON_RELEASED.load(Ordering::SeqCst);
-    
-    // If state changed from not pressed to pressed
-    if is_pressed && !was_pressed && !was_released {
-        BUTTON_PRESSED.store(true, Ordering::SeqCst);
-        BUTTON_RELEASED.store(false, Ordering::SeqCst);
-        PRESS_START_TIME.store(current_time_ms(), Ordering::SeqCst);
-        log::debug!("Button press detected (polling)");
-    }
-    
-    // If state changed from pressed to not pressed
-    if !is_pressed && was_pressed && !was_released {
-        BUTTON_RELEASED.store(true, Ordering::SeqCst);
-        
-        // Calculate press duration
-        let start_time = PRESS_START_TIME.load(Ordering::SeqCst);
-        let current_time = current_time_ms();
-        let duration = current_time.saturating_sub(start_time);
-        PRESS_DURATION.store(duration, Ordering::SeqCst);
-        
-        log::debug!("Button release detected (polling), duration: {} ms", duration);
-        
-        // Reset pressed flag
-        BUTTON_PRESSED.store(false, Ordering::SeqCst);
-        
-        // Determine if it was a short or long press
-        let mut event = BUTTON_EVENT.lock().unwrap();
-        if duration >= LONG_PRESS_DURATION_MS {
-            *event = ButtonEvent::LongPress;
-        } else {
-            *event = ButtonEvent::ShortPress;
-        }
-    }
-    
-    // Long press detection while button is still held
-    if is_pressed && was_pressed && !was_released {
-        let start_time = PRESS_START_TIME.load(Ordering::SeqCst);
-        let current_time = current_time_ms();
-        let duration = current_time.saturating_sub(start_time);
-        
-        // If we've reached long press duration and haven't triggered it yet
-        if duration >= LONG_PRESS_DURATION_MS {
-            let mut event = BUTTON_EVENT.lock().unwrap();
-            if *event == ButtonEvent::None {
-                *event = ButtonEvent::LongPress;
-                // Log the long press
-                log::info!("Long press detected ({} ms)", duration);
-                // Reset the pressed state so we don't trigger again
-                BUTTON_RELEASED.store(true, Ordering::SeqCst);
-                BUTTON_PRESSED.store(false, Ordering::SeqCst);
-            }
-        }
-    }
-    
-    // Reset released flag after processing
-    if was_released {
-        BUTTON_RELEASED.store(false, Ordering::SeqCst);
-    }
+// Polling catches edges the interrupt missed and reports a long press while the button is held
+fn update_button_state(button: &ButtonType) {
+    update_decoder(Some(is_button_pressed(button)));
 }
 
 // Initialize the button with interrupt
-pub fn init_button(gpio0: esp_idf_hal::gpio::Gpio0) -> anyhow::Result<ButtonType> {
+pub fn init_button(gpio0: esp_idf_hal::gpio::Gpio0) -> anyhow::Result<Button> {
     // Configure the pin as input with pull-up
     let mut button = PinDriver::input(gpio0)?;
     button.set_pull(Pull::Up)?;
@@ -175,5 +95,5 @@ pub fn init_button(gpio0: esp_idf_hal::gpio::Gpio0) -> anyhow::Result<ButtonType
     
     log::info!("PRG button initialized on GPIO{} with press/hold detection", PRG_BUTTON_PIN);
     
-    Ok(button)
+    Ok(Button { pin: button })
 }
\ No newline at end of file
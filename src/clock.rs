@@ -0,0 +1,23 @@
+// Time on the device: the esp_timer for intervals, FreeRTOS for waiting
+
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use esp_idf_hal::delay::FreeRtos;
+
+use mac_sniff_core::hal::Clock;
+
+pub struct EspClock;
+
+impl Clock for EspClock {
+    fn now_ms(&self) -> u64 {
+        (unsafe { esp_idf_svc::sys::esp_timer_get_time() } / 1000) as u64
+    }
+
+    fn unix_time_us(&self) -> u64 {
+        SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_micros() as u64
+    }
+
+    fn delay_ms(&self, ms: u32) {
+        FreeRtos::delay_ms(ms);
+    }
+}
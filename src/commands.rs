@@ -0,0 +1,177 @@
+// Runs console commands on the device. Replies are printed as they are produced; see console.rs.
+
+use anyhow::Result;
+use esp_idf_hal::delay::FreeRtos;
+use log::info;
+
+use mac_sniff_core::app::{CommandOutcome, MenuAction, MenuHost};
+use mac_sniff_core::dump;
+use mac_sniff_core::dump_protocol::crc32;
+use mac_sniff_core::hal::{Screen, Storage};
+use mac_sniff_core::settings::Settings;
+
+use crate::console::{self, Command, Console, Json};
+use crate::display::Oled;
+use crate::settings_store::SettingsStore;
+use crate::spiffs::{self, Spiffs};
+
+// Bytes of a file per `cat` reply line
+const CAT_CHUNK_SIZE: usize = 192;
+
+// The console and the settings store, as the menu sees them
+pub struct ConsoleHost<'a> {
+    pub console: &'a mut Console,
+    pub store: &'a mut SettingsStore,
+}
+
+impl MenuHost<Oled> for ConsoleHost<'_> {
+    fn run_commands(&mut self, screen: &mut Oled, settings: &mut Settings) -> Result<CommandOutcome> {
+        let mut outcome = CommandOutcome::Idle;
+        while let Some(line) = self.console.poll() {
+            if let Some(action) = run_command(&line, screen, self.console, settings, self.store)? {
+                return Ok(CommandOutcome::Action(action));
+            }
+            outcome = CommandOutcome::Ran;
+        }
+        Ok(outcome)
+    }
+
+    fn save_settings(&mut self, settings: &Settings) -> Result<()> {
+        self.store.save(settings)
+    }
+}
+
+// Returns the action to leave the menu with, for commands that start a mode or go to sleep
+pub fn run_command(
+    line: &str,
+    screen: &mut Oled,
+    console: &mut Console,
+    settings: &mut Settings,
+    store: &mut SettingsStore,
+) -> Result<Option<MenuAction>> {
+    let command = match Command::parse(line) {
+        Ok(command) => command,
+        Err((name, e)) => {
+            console::reply(&console::err(&name, &e));
+            return Ok(None);
+        },
+    };
+    info!("Console command {:?}", command);
+
+    let before = settings.clone();
+    if let Some(reply) = console::settings_command(&command, settings) {
+        if *settings != before {
+            store.save(settings)?;
+            screen.set_brightness(settings.brightness)?;
+        }
+        console::reply(&reply);
+        return Ok(None);
+    }
+
+    let (name, result) = match command {
+        Command::Help => ("help", Ok(console::help())),
+        Command::Ls => ("ls", with_spiffs(|storage| list_files(storage))),
+        Command::Cat(file) => ("cat", file_name(&file).and_then(|name| with_spiffs(|storage| cat_file(storage, &name, settings)))),
+        Command::Rm(file) => ("rm", file_name(&file).and_then(|name| with_spiffs(|storage| {
+            storage.delete(&name)?;
+            Ok(console::ok("rm", vec![("path", Json::from(storage.path(&name)))]))
+        }))),
+        Command::Df => ("df", with_spiffs(|storage| {
+            let (total, used) = storage.space()?;
+            Ok(console::ok("df", vec![
+                ("total", Json::from(total)),
+                ("used", Json::from(used)),
+                ("free", Json::from(total.saturating_sub(used))),
+            ]))
+        })),
+        Command::ScanStart => {
+            console::reply(&console::ok("scan", vec![("state", Json::from("starting"))]));
+            return Ok(Some(MenuAction::Scan));
+        },
+        Command::ScanStop => ("scan", Err(anyhow::anyhow!("No scan is running"))),
+        Command::Reboot => {
+            console::reply(&console::ok("reboot", Vec::new()));
+            // Let the reply drain from the UART first
+            FreeRtos::delay_ms(100);
+            unsafe { esp_idf_svc::sys::esp_restart() }
+        },
+        Command::Sleep => {
+            console::reply(&console::ok("sleep", Vec::new()));
+            return Ok(Some(MenuAction::Exit));
+        },
+        Command::Dump(file) => {
+            let only = file.as_deref().map(file_name).transpose();
+            let result = only.and_then(|only| with_spiffs(|storage| {
+                dump::run_dump(screen, storage, console, settings.dump_format, only.as_deref())
+            }));
+            ("dump", result.map(|summary| console::ok("dump", vec![
+                ("files", Json::from(summary.files)),
+                ("bytes", Json::from(summary.bytes)),
+                ("retransmits", Json::from(summary.retransmits as usize)),
+            ])))
+        },
+        Command::Format => ("format", spiffs::format().map(|_| console::ok("format", Vec::new()))),
+        Command::Get(_) | Command::Set(..) => unreachable!("handled as a settings command"),
+    };
+    console::reply(&result.unwrap_or_else(|e| console::err(name, &e)));
+    Ok(None)
+}
+
+// Lets a script stop a running scan; anything else is refused until the scan is over
+pub fn scan_stop_requested(console: &Console) -> bool {
+    let mut stop = false;
+    while let Some(line) = console.poll() {
+        match Command::parse(&line) {
+            Ok(Command::ScanStop) => {
+                console::reply(&console::ok("scan", vec![("state", Json::from("stopping"))]));
+                stop = true;
+            },
+            Ok(_) => {
+                let name = line.split_whitespace().next().unwrap_or_default();
+                console::reply(&console::err(name, &anyhow::anyhow!("Busy scanning, only scan stop works")));
+            },
+            Err((name, e)) => console::reply(&console::err(&name, &e)),
+        }
+    }
+    stop
+}
+
+fn with_spiffs<T>(f: impl FnOnce(&mut Spiffs) -> Result<T>) -> Result<T> {
+    let mut storage = Spiffs::mount(spiffs::BASE_PATH)?;
+    f(&mut storage)
+}
+
+// Accepts a bare file name or a full path on SPIFFS, which has no directories
+fn file_name(name: &str) -> Result<String> {
+    let name = name.strip_prefix(spiffs::BASE_PATH).map(|name| name.trim_start_matches('/')).unwrap_or(name);
+    if name.is_empty() || name.contains('/') {
+        return Err(anyhow::anyhow!("Invalid file name {}", name));
+    }
+    Ok(name.to_string())
+}
+
+fn list_files(storage: &Spiffs) -> Result<Json> {
+    let files = storage.list()?.into_iter()
+        .map(|name| {
+            let size = storage.size(&name).unwrap_or(0);
+            Json::Object(vec![("path", Json::from(storage.path(&name))), ("size", Json::from(size))])
+        })
+        .collect();
+    Ok(console::ok("ls", vec![("files", Json::Array(files))]))
+}
+
+fn cat_file(storage: &Spiffs, name: &str, settings: &Settings) -> Result<Json> {
+    let content = storage.read(name)?;
+    for (idx, chunk) in content.chunks(CAT_CHUNK_SIZE).enumerate() {
+        console::reply(&console::partial("cat", vec![
+            ("offset", Json::from(idx * CAT_CHUNK_SIZE)),
+            ("data", Json::from(settings.dump_format.encode(chunk))),
+        ]));
+    }
+    Ok(console::ok("cat", vec![
+        ("path", Json::from(storage.path(name))),
+        ("size", Json::from(content.len())),
+        ("format", Json::from(settings.dump_format.protocol_name())),
+        ("crc32", Json::from(format!("{:08x}", crc32(&content)))),
+    ]))
+}
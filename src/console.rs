@@ -0,0 +1,305 @@
+// Line based command console on the USB serial, polled from the menu and scan loops
+//
+// Every command in `COMMANDS` is answered with one JSON object per line, so a host script can
+// tell replies from log output by the leading '{'. The last reply of a command carries
+// "ok": true or "ok": false plus an "error" message.
+
+use std::fmt;
+use std::io::BufRead;
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError};
+use std::thread;
+use std::time::Duration;
+
+use anyhow::Result;
+use log::error;
+
+use mac_sniff_core::dump_protocol::Link;
+use mac_sniff_core::settings::{self, Settings};
+
+const READER_STACK_SIZE: usize = 4096;
+const READ_RETRY_DELAY: Duration = Duration::from_millis(50);
+
+pub struct Console {
+    lines: Receiver<String>,
+}
+
+impl Console {
+    // Reads stdin on a background thread; the VFS console is non-blocking, so empty reads are retried
+    pub fn start() -> Result<Self> {
+        let (sender, lines) = mpsc::channel();
+        thread::Builder::new()
+            .name("console".to_string())
+            .stack_size(READER_STACK_SIZE)
+            .spawn(move || {
+                let stdin = std::io::stdin();
+                let mut line = String::new();
+                loop {
+                    match stdin.lock().read_line(&mut line) {
+                        Ok(_) if line.ends_with('\n') => {
+                            // A bare Enter gets no reply
+                            let trimmed = line.trim();
+                            if !trimmed.is_empty() && sender.send(trimmed.to_string()).is_err() {
+                                return;
+                            }
+                            line.clear();
+                        },
+                        // Partial line or nothing available yet
+                        Ok(_) | Err(_) => thread::sleep(READ_RETRY_DELAY),
+                    }
+                }
+            })
+            .map_err(|e| anyhow::anyhow!("Error starting console: {:?}", e))?;
+        Ok(Console { lines })
+    }
+
+    pub fn poll(&self) -> Option<String> {
+        self.lines.try_recv().ok()
+    }
+}
+
+// The dump protocol shares the console: replies from the host arrive as console lines
+impl Link for Console {
+    fn send(&mut self, line: &str) -> Result<()> {
+        println!("{}", line);
+        Ok(())
+    }
+
+    fn receive(&mut self, timeout: Duration) -> Result<Option<String>> {
+        match self.lines.recv_timeout(timeout) {
+            Ok(line) => Ok(Some(line)),
+            Err(RecvTimeoutError::Timeout) => Ok(None),
+            Err(RecvTimeoutError::Disconnected) => Err(anyhow::anyhow!("Console reader stopped")),
+        }
+    }
+}
+
+pub struct CommandSpec {
+    pub name: &'static str,
+    pub usage: &'static str,
+    pub help: &'static str,
+    min_args: usize,
+    // The last argument takes the rest of the line, so values may contain spaces
+    max_args: usize,
+}
+
+pub const COMMANDS: [CommandSpec; 12] = [
+    CommandSpec { name: "help", usage: "help", help: "list commands", min_args: 0, max_args: 0 },
+    CommandSpec { name: "ls", usage: "ls", help: "list files with their sizes", min_args: 0, max_args: 0 },
+    CommandSpec { name: "cat", usage: "cat <file>", help: "print a file in the dump format", min_args: 1, max_args: 1 },
+    CommandSpec { name: "rm", usage: "rm <file>", help: "delete a file", min_args: 1, max_args: 1 },
+    CommandSpec { name: "df", usage: "df", help: "show used and free space", min_args: 0, max_args: 0 },
+    CommandSpec { name: "scan", usage: "scan start|stop", help: "start a scan, or stop the running one", min_args: 1, max_args: 1 },
+    CommandSpec { name: "get", usage: "get [key]", help: "print every setting, or one", min_args: 0, max_args: 1 },
+    CommandSpec { name: "set", usage: "set <key> <value>", help: "change and save a setting", min_args: 2, max_args: 2 },
+    CommandSpec { name: "reboot", usage: "reboot", help: "restart the device", min_args: 0, max_args: 0 },
+    CommandSpec { name: "sleep", usage: "sleep", help: "go to deep sleep until the button or schedule wakes it", min_args: 0, max_args: 0 },
+    CommandSpec { name: "dump", usage: "dump [file]", help: "send every file, or one, to mac_sniff_host receive", min_args: 0, max_args: 1 },
+    CommandSpec { name: "format", usage: "format confirm", help: "erase every file", min_args: 1, max_args: 1 },
+];
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Command {
+    Help,
+    Ls,
+    Cat(String),
+    Rm(String),
+    Df,
+    ScanStart,
+    ScanStop,
+    Get(Option<String>),
+    Set(String, String),
+    Reboot,
+    Sleep,
+    Dump(Option<String>),
+    Format,
+}
+
+impl Command {
+    // Returns the command name along with the error so the reply can still say what failed
+    pub fn parse(line: &str) -> std::result::Result<Command, (String, anyhow::Error)> {
+        let (name, rest) = split_word(line);
+        let name = name.to_string();
+        let Some(spec) = COMMANDS.iter().find(|spec| spec.name == name) else {
+            return Err((name, anyhow::anyhow!("Unknown command, try help")));
+        };
+
+        let mut args = Vec::new();
+        let mut rest = rest;
+        while !rest.is_empty() {
+            if args.len() + 1 == spec.max_args {
+                args.push(rest);
+                break;
+            }
+            let (arg, tail) = split_word(rest);
+            args.push(arg);
+            rest = tail;
+        }
+        if args.len() < spec.min_args || args.len() > spec.max_args {
+            return Err((name, anyhow::anyhow!("Usage: {}", spec.usage)));
+        }
+
+        let arg = |idx: usize| args.get(idx).map(|arg| arg.to_string());
+        let command = match (spec.name, args.as_slice()) {
+            ("help", _) => Command::Help,
+            ("ls", _) => Command::Ls,
+            ("cat", _) => Command::Cat(arg(0).unwrap_or_default()),
+            ("rm", _) => Command::Rm(arg(0).unwrap_or_default()),
+            ("df", _) => Command::Df,
+            ("scan", ["start"]) => Command::ScanStart,
+            ("scan", ["stop"]) => Command::ScanStop,
+            ("get", _) => Command::Get(arg(0)),
+            ("set", _) => Command::Set(arg(0).unwrap_or_default(), arg(1).unwrap_or_default()),
+            ("reboot", _) => Command::Reboot,
+            ("sleep", _) => Command::Sleep,
+            ("dump", _) => Command::Dump(arg(0)),
+            // Erasing everything should not be one typo away
+            ("format", ["confirm"]) => Command::Format,
+            _ => return Err((name, anyhow::anyhow!("Usage: {}", spec.usage))),
+        };
+        Ok(command)
+    }
+}
+
+fn split_word(line: &str) -> (&str, &str) {
+    let line = line.trim();
+    match line.split_once(char::is_whitespace) {
+        Some((word, rest)) => (word, rest.trim_start()),
+        None => (line, ""),
+    }
+}
+
+// Just enough JSON for console replies
+#[derive(Debug, Clone, PartialEq)]
+pub enum Json {
+    Bool(bool),
+    Number(i64),
+    String(String),
+    Array(Vec<Json>),
+    Object(Vec<(&'static str, Json)>),
+}
+
+impl From<bool> for Json {
+    fn from(value: bool) -> Self {
+        Json::Bool(value)
+    }
+}
+
+impl From<i64> for Json {
+    fn from(value: i64) -> Self {
+        Json::Number(value)
+    }
+}
+
+impl From<usize> for Json {
+    fn from(value: usize) -> Self {
+        Json::Number(value as i64)
+    }
+}
+
+impl From<&str> for Json {
+    fn from(value: &str) -> Self {
+        Json::String(value.to_string())
+    }
+}
+
+impl From<String> for Json {
+    fn from(value: String) -> Self {
+        Json::String(value)
+    }
+}
+
+impl fmt::Display for Json {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Json::Bool(value) => write!(f, "{}", value),
+            Json::Number(value) => write!(f, "{}", value),
+            Json::String(value) => write_json_string(f, value),
+            Json::Array(items) => {
+                write!(f, "[")?;
+                for (idx, item) in items.iter().enumerate() {
+                    if idx > 0 {
+                        write!(f, ",")?;
+                    }
+                    write!(f, "{}", item)?;
+                }
+                write!(f, "]")
+            },
+            Json::Object(fields) => {
+                write!(f, "{{")?;
+                for (idx, (key, value)) in fields.iter().enumerate() {
+                    if idx > 0 {
+                        write!(f, ",")?;
+                    }
+                    write_json_string(f, key)?;
+                    write!(f, ":{}", value)?;
+                }
+                write!(f, "}}")
+            },
+        }
+    }
+}
+
+fn write_json_string(f: &mut fmt::Formatter<'_>, value: &str) -> fmt::Result {
+    write!(f, "\"")?;
+    for c in value.chars() {
+        match c {
+            '"' => write!(f, "\\\"")?,
+            '\\' => write!(f, "\\\\")?,
+            '\n' => write!(f, "\\n")?,
+            '\r' => write!(f, "\\r")?,
+            '\t' => write!(f, "\\t")?,
+            c if (c as u32) < 0x20 => write!(f, "\\u{:04x}", c as u32)?,
+            c => write!(f, "{}", c)?,
+        }
+    }
+    write!(f, "\"")
+}
+
+pub fn reply(json: &Json) {
+    println!("{}", json);
+}
+
+// Final reply of a command that worked, with whatever it has to report
+pub fn ok(cmd: &str, mut fields: Vec<(&'static str, Json)>) -> Json {
+    fields.splice(0..0, [("ok", Json::Bool(true)), ("cmd", Json::from(cmd))]);
+    Json::Object(fields)
+}
+
+pub fn err(cmd: &str, error: &anyhow::Error) -> Json {
+    error!("Console {}: {}", cmd, error);
+    Json::Object(vec![("ok", Json::Bool(false)), ("cmd", Json::from(cmd)), ("error", Json::from(error.to_string()))])
+}
+
+// Intermediate output of a long reply, e.g. one chunk of `cat`
+pub fn partial(cmd: &str, mut fields: Vec<(&'static str, Json)>) -> Json {
+    fields.insert(0, ("cmd", Json::from(cmd)));
+    Json::Object(fields)
+}
+
+pub fn help() -> Json {
+    let commands = COMMANDS.iter()
+        .map(|spec| Json::Object(vec![("usage", Json::from(spec.usage)), ("help", Json::from(spec.help))]))
+        .collect();
+    ok("help", vec![("commands", Json::Array(commands))])
+}
+
+// `get` and `set`, which only touch the settings
+pub fn settings_command(command: &Command, settings: &mut Settings) -> Option<Json> {
+    let reply = match command {
+        Command::Get(None) => Ok(ok("get", vec![("settings", settings_object(settings))])),
+        Command::Get(Some(key)) => settings.get(key)
+            .map(|value| ok("get", vec![("key", Json::from(key.as_str())), ("value", Json::from(value))]))
+            .ok_or_else(|| anyhow::anyhow!("Unknown setting {}", key)),
+        Command::Set(key, value) => settings.set(key, value)
+            .map(|_| ok("set", vec![("key", Json::from(key.as_str())), ("value", Json::from(settings.get(key).unwrap_or_default()))])),
+        _ => return None,
+    };
+    let name = if matches!(command, Command::Set(..)) { "set" } else { "get" };
+    Some(reply.unwrap_or_else(|e| err(name, &e)))
+}
+
+fn settings_object(settings: &Settings) -> Json {
+    Json::Object(settings::KEYS.iter()
+        .map(|key| (*key, Json::from(settings.get(key).unwrap_or_default())))
+        .collect())
+}
@@ -11,7 +11,8 @@ use embedded_graphics::{
 use ssd1306::{mode::BufferedGraphicsMode, prelude::*, Ssd1306};
 use anyhow::Result;
 
-use crate::button::ButtonEvent;
+use mac_sniff_core::hal::Screen;
+use mac_sniff_core::settings;
 
 // Constants to match Arduino code
 pub const DISPLAY_ADDRESS: u8 = 0x3C;
@@ -19,87 +20,84 @@ pub const DISPLAY_I2C_FREQ: u32 = 10_000; // 10 kHz
 
 pub type AppDisplay = Ssd1306<I2CInterface<I2cDriver<'static>>, DisplaySize128x64, BufferedGraphicsMode<DisplaySize128x64>>;
 
-// Rest of the display implementation remains the same
-pub fn clear_display(display: &mut AppDisplay) -> Result<()> {
-    display.clear(BinaryColor::Off).map_err(|e| anyhow::anyhow!("There was an error clearing the display: {:?}", e))?;
-    Ok(())
+// The SSD1306 on the board; the screens themselves are drawn by the core crate
+pub struct Oled {
+    display: AppDisplay,
 }
 
-pub fn draw_rect(display: &mut AppDisplay, x: i32, y: i32, width: i32, height: i32, color: bool) -> Result<()> {
-    let top_left = Point::new(x, y);
-    let rect_color = if color { BinaryColor::On } else { BinaryColor::Off };
-    let rect_style = PrimitiveStyle::with_stroke(rect_color, 1);
-    
-    Rectangle::new(top_left, Size::new(width as u32, height as u32))
-        .into_styled(rect_style)
-        .draw(display)
-        .map_err(|e| anyhow::anyhow!("{:?}", e))?;
-        
-    Ok(())
+impl Oled {
+    // Expects an initialized display
+    pub fn new(display: AppDisplay) -> Self {
+        Oled { display }
+    }
 }
 
-pub fn fill_rect(display: &mut AppDisplay, x: i32, y: i32, width: i32, height: i32, color: bool) -> Result<()> {
-    let top_left = Point::new(x, y);
-    let rect_color = if color { BinaryColor::On } else { BinaryColor::Off };
-    let rect_style = PrimitiveStyle::with_fill(rect_color);
-    
-    Rectangle::new(top_left, Size::new(width as u32, height as u32))
-        .into_styled(rect_style)
-        .draw(display)
-        .map_err(|e| anyhow::anyhow!("Failed to draw rectangle: {:?}", e))?;
-        
-    Ok(())
-}
+impl Screen for Oled {
+    fn clear(&mut self) -> Result<()> {
+        self.display.clear(BinaryColor::Off).map_err(|e| anyhow::anyhow!("There was an error clearing the display: {:?}", e))?;
+        Ok(())
+    }
 
-pub fn draw_text(display: &mut AppDisplay, x: i32, y: i32, text: &str, color: bool) -> Result<()> {
-    let text_color = if color { BinaryColor::On } else { BinaryColor::Off };
-    
-    let text_style = MonoTextStyleBuilder::new()
-        .font(&FONT_6X10)
-        .text_color(text_color)
-        .build();
-        
-    Text::with_baseline(
-        text,
-        Point::new(x, y),
-        text_style,
-        Baseline::Top,
-    )
-    .draw(display)
-    .map_err(|e| anyhow::anyhow!("Failed to draw text: {:?}", e))?;
-    
-    Ok(())
-}
+    fn draw_rect(&mut self, x: i32, y: i32, width: i32, height: i32, color: bool) -> Result<()> {
+        let top_left = Point::new(x, y);
+        let rect_color = if color { BinaryColor::On } else { BinaryColor::Off };
+        let rect_style = PrimitiveStyle::with_stroke(rect_color, 1);
 
-pub fn flush_display(display: &mut AppDisplay) -> Result<()> {
-    display.flush().map_err(|e| anyhow::anyhow!("Failed to flush display: {:?}", e))?;
-    FreeRtos::delay_ms(10);
-    Ok(())
-}
+        Rectangle::new(top_left, Size::new(width as u32, height as u32))
+            .into_styled(rect_style)
+            .draw(&mut self.display)
+            .map_err(|e| anyhow::anyhow!("{:?}", e))?;
 
-pub fn draw_start_up(display: &mut AppDisplay) -> Result<()> {
-    clear_display(display)?;
-    flush_display(display)?;
-    FreeRtos::delay_ms(1000);
-    Ok(())
-}
+        Ok(())
+    }
 
-pub fn draw_status_update(display: &mut AppDisplay, durration: &u64, total_count: &usize, button_event: &ButtonEvent) -> Result<()> {
-    // Update display with current status
-    clear_display(display)?;
-    draw_text(display, 10, 10, &format!("Time left: {}s", durration), true)?;
-    draw_text(display, 10, 30, &format!("MACs found: {}", total_count), true)?;
-    flush_display(display)?;
+    fn fill_rect(&mut self, x: i32, y: i32, width: i32, height: i32, color: bool) -> Result<()> {
+        let top_left = Point::new(x, y);
+        let rect_color = if color { BinaryColor::On } else { BinaryColor::Off };
+        let rect_style = PrimitiveStyle::with_fill(rect_color);
 
-    Ok(())
-}
+        Rectangle::new(top_left, Size::new(width as u32, height as u32))
+            .into_styled(rect_style)
+            .draw(&mut self.display)
+            .map_err(|e| anyhow::anyhow!("Failed to draw rectangle: {:?}", e))?;
+
+        Ok(())
+    }
+
+    fn draw_text(&mut self, x: i32, y: i32, text: &str, color: bool) -> Result<()> {
+        let text_color = if color { BinaryColor::On } else { BinaryColor::Off };
+
+        let text_style = MonoTextStyleBuilder::new()
+            .font(&FONT_6X10)
+            .text_color(text_color)
+            .build();
+
+        Text::with_baseline(
+            text,
+            Point::new(x, y),
+            text_style,
+            Baseline::Top,
+        )
+        .draw(&mut self.display)
+        .map_err(|e| anyhow::anyhow!("Failed to draw text: {:?}", e))?;
+
+        Ok(())
+    }
 
-pub fn draw_final_count(display: &mut AppDisplay, count: &usize) -> Result<()> {
-    // Show final count on display
-    clear_display(display)?;
-    draw_text(display, 10, 10, &format!("Found {} MACs", count), true)?;
-    flush_display(display)?;
-    FreeRtos::delay_ms(5000); // Show the result for 5 seconds
+    fn flush(&mut self) -> Result<()> {
+        self.display.flush().map_err(|e| anyhow::anyhow!("Failed to flush display: {:?}", e))?;
+        FreeRtos::delay_ms(10);
+        Ok(())
+    }
 
-    Ok(())
+    fn set_brightness(&mut self, brightness: settings::Brightness) -> Result<()> {
+        let level = match brightness {
+            settings::Brightness::Dimmest => Brightness::DIMMEST,
+            settings::Brightness::Dim => Brightness::DIM,
+            settings::Brightness::Normal => Brightness::NORMAL,
+            settings::Brightness::Bright => Brightness::BRIGHT,
+            settings::Brightness::Brightest => Brightness::BRIGHTEST,
+        };
+        self.display.set_brightness(level).map_err(|e| anyhow::anyhow!("Failed to set brightness: {:?}", e))
+    }
 }
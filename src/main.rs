@@ -1,15 +1,31 @@
 mod display;
 mod wifi;
 mod button;
-mod app;
+mod clock;
 mod spiffs;
+mod sniffer;
+mod settings_store;
+mod sleep;
+mod console;
+mod commands;
 
-use std::{collections::HashMap, sync::mpsc::{self, SyncSender}, time::Duration};
+use std::{sync::atomic::{AtomicU32, Ordering}, time::Duration};
 
-use app::{render_initial_menu, update_initial_menu_state, InitMenuDisplayOptions, INIT_MENU_DISPLAY_STATE};
-use button::{check_button_event, ButtonEvent};
-use display::{clear_display, draw_final_count, draw_start_up, draw_status_update, draw_text, flush_display, DISPLAY_ADDRESS, DISPLAY_I2C_FREQ};
-use esp_idf_hal::{gpio::PinDriver, i2c::APBTickType, sys::{esp_deep_sleep_start, esp_wifi_set_promiscuous_rx_cb}};
+use mac_sniff_core::app::{self, MenuAction};
+use mac_sniff_core::capture;
+use mac_sniff_core::dump;
+use mac_sniff_core::hal::{Board, PacketSource, Screen, Storage};
+use mac_sniff_core::scan::{self, ScanOptions};
+use mac_sniff_core::scan_duration::ScanDuration;
+use clock::EspClock;
+use commands::ConsoleHost;
+use sniffer::Sniffer;
+use console::{Console, Json};
+use settings_store::SettingsStore;
+use sleep::WakeCause;
+use spiffs::Spiffs;
+use display::{Oled, DISPLAY_ADDRESS, DISPLAY_I2C_FREQ};
+use esp_idf_hal::{gpio::PinDriver, i2c::APBTickType};
 use esp_idf_svc::{
     eventloop::EspSystemEventLoop, 
     hal::{delay::FreeRtos, prelude::{Peripherals, FromValueType}}, 
@@ -19,7 +35,9 @@ use log::{debug, info, error};
 use ssd1306::{mode::DisplayConfig, prelude::DisplayRotation, size::DisplaySize128x64, I2CDisplayInterface, Ssd1306};
 use wifi::create_wifi_driver;
 
-const DURRATION_U64: u64 = 30;
+// RTC slow memory survives deep sleep, so this counts wakeups since the last power-on
+#[link_section = ".rtc.data"]
+static BOOT_COUNT: AtomicU32 = AtomicU32::new(0);
 
 fn main() -> anyhow::Result<()> {
     // It is necessary to call this function once. Otherwise some patches to the runtime
@@ -29,11 +47,24 @@ fn main() -> anyhow::Result<()> {
     // Bind the log crate to the ESP Logging facilities
     esp_idf_svc::log::EspLogger::initialize_default();
 
-    debug!("Hello, world!");
+    let boot_count = BOOT_COUNT.fetch_add(1, Ordering::SeqCst) + 1;
+    debug!("Hello, world! Boot #{}", boot_count);
 
     let peripherals = Peripherals::take()?;
     let sys_loop = EspSystemEventLoop::take()?;
     let nvs = EspDefaultNvsPartition::take()?;
+    let mut settings_store = SettingsStore::open(nvs.clone())?;
+    let mut settings = settings_store.load();
+    // A timer wakeup means a scheduled scan is due and skips the menu. A button wakeup goes to
+    // the menu but keeps the schedule going; only a reset starts it over.
+    let wake_cause = sleep::wake_cause();
+    let scheduled_wake = wake_cause == WakeCause::Timer;
+    match wake_cause {
+        WakeCause::Timer => info!("Scheduled scan {} woke up", sleep::completed_cycles() + 1),
+        WakeCause::Button => info!("Woken by button press"),
+        WakeCause::Reset => sleep::reset_cycles(),
+        WakeCause::Other(source) => info!("Woken by wakeup source {}", source),
+    }
 
     debug!("Setting up button");
     let button = button::init_button(peripherals.pins.gpio0)?;
@@ -71,261 +102,156 @@ fn main() -> anyhow::Result<()> {
         DisplayRotation::Rotate0,
     ).into_buffered_graphics_mode();
     display.init().map_err(|e| anyhow::anyhow!("Failed to init display: {:?}", e))?;
+    let mut board = Board { screen: Oled::new(display), input: button, clock: EspClock };
+    board.screen.set_brightness(settings.brightness)?;
     FreeRtos::delay_ms(1000);
-    draw_start_up(&mut display)?;
-    render_initial_menu(&mut display)?;
-    loop {
-        clear_display(&mut display)?;
-        button::update_button_state(&button);
-        match check_button_event() {
-            ButtonEvent::LongPress => {
-                break;
-            },
-            ButtonEvent::ShortPress => {
-                update_initial_menu_state(&ButtonEvent::ShortPress)?;
-                render_initial_menu(&mut display)?;
-            },
-            ButtonEvent::None => {}
-        }
-        FreeRtos::delay_ms(100);
-    }
-
-    let init_menu_state = INIT_MENU_DISPLAY_STATE.lock().map_err(|e| { anyhow::anyhow!("Mutex poisoned: {:?}", e)})?;
-
-    match *init_menu_state {
-        InitMenuDisplayOptions::Scan => {
-            type MacAddress = [u8; 6];
-            let mut mac_map: HashMap<MacAddress, bool> = HashMap::with_capacity(200);
-            let (tx_mac_map, rx_mac_map) = mpsc::sync_channel(100);
-            static mut TX: Option<SyncSender<MacAddress>> = None;
-
-            unsafe {
-                TX = Some(tx_mac_map.clone());
-            }
-
-            unsafe extern "C" fn rx_callback(buf: *mut core::ffi::c_void, _type: u32) {
-                if !buf.is_null() {
-                    let frame_data = std::slice::from_raw_parts(buf as *const u8, 24);
-                    let frame_type = (frame_data[0] & 0x0C) >> 2;
-                    let frame_subtype = (frame_data[0] & 0xF0) >> 4;
-                    
-                    let type_str = match frame_type {
-                        0 => "Management",
-                        1 => "Control",
-                        2 => "Data",
-                        3 => "Extension",
-                        _ => "Unknown"
-                    };
-            
-                    // Extract MAC addresses
-                    let destination_mac = frame_data[4..10].try_into().unwrap();
-                    let source_mac = frame_data[10..16].try_into().unwrap();
-                    
-                    if let Some(tx) = &TX {
-                        let _ = tx.send(destination_mac);
-                        let _ = tx.send(source_mac);
-                    }
-                    
-                    debug!("Frame: {} (type: {}, subtype: {})", type_str, frame_type, frame_subtype);
-                    debug!("  Destination: {:?}", destination_mac);
-                    debug!("  Source: {:?}", source_mac);
-                }
-            }
+    let mut console = None;
+    let action = if scheduled_wake {
+        MenuAction::Scan
+    } else {
+        board.screen.clear()?;
+        board.screen.flush()?;
+        FreeRtos::delay_ms(1000);
+        // Scripts can drive the device over serial while the menu is up
+        let console = console.insert(Console::start()?);
+        app::run_menu(&mut board, &mut settings, &mut ConsoleHost { console, store: &mut settings_store })?
+    };
+    let scan_profile = if scheduled_wake {
+        settings.schedule.profile
+    } else {
+        settings.scan_profile
+    };
+    info!("Scan profile: {}", scan_profile.label());
 
+    match action {
+        MenuAction::Scan => {
             let _wifi_driver = create_wifi_driver(peripherals.modem, sys_loop, nvs)?;
-            unsafe {
-                esp_wifi_set_promiscuous_rx_cb(Some(rx_callback));
-            }
+            let sniffer = Sniffer::start(scan_profile)?;
 
-            // Run for 30 seconds then exit
-            let start = std::time::Instant::now();
-            let duration = std::time::Duration::from_secs(DURRATION_U64);
-            let mut last_check_in_time = start;
-
-            while start.elapsed() < duration {
-                // Update button state
-                button::update_button_state(&button);
-                // Check for new MAC addresses
-                match rx_mac_map.try_recv() {
-                    Ok(mac) => {
-                        mac_map.entry(mac).or_insert(true);
-                    },
-                    Err(mpsc::TryRecvError::Empty) => {},
-                    Err(mpsc::TryRecvError::Disconnected) => {
-                        info!("Channel disconnected");
-                        break;
-                    }
+            let mut storage = Spiffs::mount(spiffs::BASE_PATH)?;
+            let channel_plan = settings.channel_plan();
+            let options = ScanOptions {
+                // Nobody is around to stop an unattended scan
+                duration: match settings.scan_duration {
+                    ScanDuration::UntilStopped if scheduled_wake => ScanDuration::DEFAULT,
+                    duration => duration,
+                },
+                checkpoint: settings.checkpoint,
+                min_rssi_dbm: settings.min_rssi_dbm,
+                probed_ssids: settings.probed_ssids,
+                count_policy: settings.count_policy,
+                boot_count,
+                device_id: wifi::device_id(),
+                firmware_version: env!("CARGO_PKG_VERSION"),
+            };
+            let result = scan::run_scan(&mut board, sniffer, &mut storage, &options, &channel_plan, || {
+                console.as_ref().is_some_and(commands::scan_stop_requested)
+            });
+            drop(storage);
+            let result = result.and_then(|report| {
+                if console.is_some() {
+                    console::reply(&console::ok("scan", vec![
+                        ("state", Json::from("finished")),
+                        ("devices", Json::from(report.devices)),
+                        ("files", Json::from(report.files.len())),
+                    ]));
                 }
-
-                let button_event = button::check_button_event();
-                if last_check_in_time.elapsed() >= std::time::Duration::from_secs(3) {
-                    info!("Time remaining: {} seconds, Unique MACs: {}", 
-                        DURRATION_U64 - start.elapsed().as_secs(),
-                        mac_map.len()
-                    );
-                    // Update display with current status
-                    draw_status_update(&mut display, &(DURRATION_U64 - start.elapsed().as_secs()), &mac_map.len(), &button_event)?;
-                    last_check_in_time = std::time::Instant::now();
+                // Nobody is around to page through it after a scheduled scan
+                if settings.show_ap_list && !scheduled_wake && !report.access_points.is_empty() {
+                    FreeRtos::delay_ms(2000);
+                    app::run_ap_list(&mut board, &report.access_points)?;
                 }
-                FreeRtos::delay_ms(100);
+                Ok(())
+            });
+            if let Err(e) = result {
+                error!("Scan failed: {}", e);
+                board.screen.clear()?;
+                board.screen.draw_text(5, 5, "Scan failed", true)?;
+                board.screen.flush()?;
+                FreeRtos::delay_ms(5000);
             }
+            if scheduled_wake {
+                info!("Scheduled scan {} finished", sleep::complete_cycle());
+            }
+        },
+        MenuAction::Capture => {
+            let _wifi_driver = create_wifi_driver(peripherals.modem, sys_loop, nvs)?;
+            let mut sniffer = Sniffer::start(scan_profile)?;
 
-            info!("Found {} unique MAC addresses", mac_map.len());
+            let mut storage = Spiffs::mount(spiffs::BASE_PATH)?;
+            let channel_plan = settings.channel_plan();
+            let result = capture::run_capture(
+                &mut board,
+                &mut sniffer,
+                &mut storage,
+                settings.capture,
+                &channel_plan,
+                settings.capture_duration,
+            );
+            drop(storage);
+            let dropped = sniffer.dropped();
+            drop(sniffer);
 
-            draw_final_count(&mut display, &mac_map.len())?;
-            
-            // Save MAC addresses to file if there's enough space
-            info!("Attempting to save MAC addresses to SPIFFS");
-            let mac_data: Vec<u8> = mac_map.keys()
-                .flat_map(|mac| mac.iter().copied())
-                .collect();
-            
-            spiffs::mount("/spffs")?;
-            
-            // Check if we have enough space to save the file
-            let needed_bytes = mac_data.len();
-            if spiffs::has_enough_space(needed_bytes)? {
-                let timestamp = std::time::SystemTime::now()
-                    .duration_since(std::time::UNIX_EPOCH)
-                    .unwrap_or_default()
-                    .as_secs();
-                
-                let filename = format!("/spffs/scan_{}.bin", timestamp);
-                match spiffs::save_to_file(&filename, &mac_data) {
-                    Ok(_) => {
-                        info!("Successfully saved {} MAC addresses to {}", mac_map.len(), filename);
-                        draw_text(&mut display, 5, 40, "MAC data saved", true)?;
-                        flush_display(&mut display)?;
-                    },
-                    Err(e) => {
-                        error!("Failed to save MAC addresses: {}", e);
-                        draw_text(&mut display, 5, 40, "Save failed", true)?;
-                        flush_display(&mut display)?;
-                    }
+            let screen = &mut board.screen;
+            screen.clear()?;
+            match result {
+                Ok(summary) => {
+                    info!("Capture finished: {} frames, {} bytes, {} files written, {} rotated out, {} dropped",
+                        summary.frames, summary.bytes, summary.files_written, summary.files_deleted, dropped);
+                    screen.draw_text(5, 5, "Capture complete", true)?;
+                    screen.draw_text(5, 20, &format!("Frames: {}", summary.frames), true)?;
+                    screen.draw_text(5, 30, &format!("Files: {}", summary.files_written), true)?;
+                },
+                Err(e) => {
+                    error!("Capture failed: {}", e);
+                    screen.draw_text(5, 5, "Capture failed", true)?;
                 }
-            } else {
-                error!("Not enough space to save MAC addresses");
-                draw_text(&mut display, 5, 40, "Not enough space", true)?;
-                flush_display(&mut display)?;
             }
-            
-            spiffs::unmount()?;
-        
-            info!("{} seconds elapsed, exiting...", DURRATION_U64);
-
+            screen.flush()?;
+            FreeRtos::delay_ms(5000);
         },
-        InitMenuDisplayOptions::Size => {
+        MenuAction::Size => {
             info!("Mounting SPIFFS filesystem");
-            spiffs::mount(
-                "/spffs"
-            )?;
-            let (total, used) = spiffs::get_space_info()?;
-            draw_text(&mut display, 5, 5, &format!("Total: {} bytes", total), true)?;
-            draw_text(&mut display, 5, 15, &format!("Used: {} bytes", used), true)?;
-            flush_display(&mut display)?;
-
-            spiffs::unmount()?;
+            let storage = Spiffs::mount(spiffs::BASE_PATH)?;
+            let (total, used) = storage.space()?;
+            drop(storage);
+            board.screen.draw_text(5, 5, &format!("Total: {} bytes", total), true)?;
+            board.screen.draw_text(5, 15, &format!("Used: {} bytes", used), true)?;
+            board.screen.flush()?;
 
             FreeRtos::delay_ms(5000);
         },
-        // ...existing code...
-        InitMenuDisplayOptions::Dump => {
-            info!("Starting SPIFFS dump to USB");
-            draw_text(&mut display, 5, 5, "Dumping files...", true)?;
-            flush_display(&mut display)?;
-            
-            // Mount filesystem
-            spiffs::mount("/spffs")?;
-            
-            // Get list of files
-            let files = match spiffs::list_files("/spffs") {
-                Ok(files) => files,
-                Err(e) => {
-                    error!("Failed to list files: {}", e);
-                    draw_text(&mut display, 5, 20, "Failed to list files", true)?;
-                    flush_display(&mut display)?;
-                    FreeRtos::delay_ms(3000);
-                    spiffs::unmount()?;
-                    return Ok(());
-                }
+        MenuAction::Dump => {
+            let storage = Spiffs::mount(spiffs::BASE_PATH)?;
+            let result = match console.as_mut() {
+                Some(console) => dump::run_dump(&mut board.screen, &storage, console, settings.dump_format, None),
+                None => Err(anyhow::anyhow!("Serial console is not running")),
             };
-            
-            if files.is_empty() {
-                draw_text(&mut display, 5, 20, "No files to dump", true)?;
-                flush_display(&mut display)?;
-                FreeRtos::delay_ms(3000);
-                spiffs::unmount()?;
-                return Ok(());
-            }
-            
-            // Show number of files found
-            draw_text(&mut display, 5, 20, &format!("Found {} files", files.len()), true)?;
-            flush_display(&mut display)?;
-            
-            // Start transfer protocol
-            println!("MAC_SNIFF_DUMP_BEGIN");
-            println!("NUM_FILES:{}", files.len());
-            
-            let mut total_bytes = 0;
-            
-            // Transfer each file
-            for (idx, file_path) in files.iter().enumerate() {
-                let content = match spiffs::read_file(file_path) {
-                    Ok(content) => content,
-                    Err(e) => {
-                        error!("Failed to read file {}: {}", file_path, e);
-                        continue;
-                    }
-                };
-                
-                // Update display
-                clear_display(&mut display)?;
-                draw_text(&mut display, 5, 5, "Dumping files...", true)?;
-                draw_text(&mut display, 5, 20, &format!("File {}/{}", idx+1, files.len()), true)?;
-                draw_text(&mut display, 5, 30, &format!("Size: {} bytes", content.len()), true)?;
-                flush_display(&mut display)?;
-                
-                // Send file header
-                println!("FILE_BEGIN:{}", file_path);
-                println!("FILE_SIZE:{}", content.len());
-                
-                // Send file content in chunks
-                const CHUNK_SIZE: usize = 64;
-                for chunk in content.chunks(CHUNK_SIZE) {
-                    // Convert binary chunk to hex string for reliable transfer
-                    let hex_chunk: String = chunk.iter()
-                        .map(|byte| format!("{:02x}", byte))
-                        .collect();
-                    println!("CHUNK:{}", hex_chunk);
-                    
-                    // Small delay to avoid overwhelming the serial buffer
-                    FreeRtos::delay_ms(5);
+            drop(storage);
+
+            let screen = &mut board.screen;
+            screen.clear()?;
+            match result {
+                Ok(summary) => {
+                    info!("Dump finished: {} files, {} bytes, {} retransmits", summary.files, summary.bytes, summary.retransmits);
+                    screen.draw_text(5, 5, "Transfer complete", true)?;
+                    screen.draw_text(5, 20, &format!("Sent {} files", summary.files), true)?;
+                    screen.draw_text(5, 30, &format!("Total: {} bytes", summary.bytes), true)?;
+                    screen.draw_text(5, 40, &format!("Resent: {} chunks", summary.retransmits), true)?;
+                },
+                Err(e) => {
+                    error!("Dump failed: {}", e);
+                    screen.draw_text(5, 5, "Dump failed", true)?;
                 }
-                
-                println!("FILE_END");
-                total_bytes += content.len();
             }
-            
-            // Transfer complete
-            println!("MAC_SNIFF_DUMP_END");
-            println!("TOTAL_BYTES:{}", total_bytes);
-            
-            clear_display(&mut display)?;
-            draw_text(&mut display, 5, 5, "Transfer complete", true)?;
-            draw_text(&mut display, 5, 20, &format!("Sent {} files", files.len()), true)?;
-            draw_text(&mut display, 5, 30, &format!("Total: {} bytes", total_bytes), true)?;
-            flush_display(&mut display)?;
-            
-            // Unmount filesystem
-            spiffs::unmount()?;
-            
+            screen.flush()?;
             // Wait for user to see the completion message
             FreeRtos::delay_ms(5000);
         },
-        InitMenuDisplayOptions::Exit => {}
+        MenuAction::Exit => {}
     }
     
-    unsafe {
-        esp_deep_sleep_start();
-    }
+    // Arms the timer for the next scheduled scan while the schedule has cycles left
+    let wakeup = settings.schedule.next_wakeup(sleep::completed_cycles());
+    sleep::deep_sleep(&board.input, wakeup)
 }
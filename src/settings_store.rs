@@ -0,0 +1,98 @@
+// Settings persisted in the default NVS partition
+// A blob that fails to load falls back to defaults so a fresh or erased device still boots.
+
+use anyhow::Result;
+use esp_idf_svc::nvs::{EspDefaultNvsPartition, EspNvs, NvsDefault};
+use log::{error, info};
+
+use mac_sniff_core::scan_duration::{CheckpointWindow, ScanDuration};
+use mac_sniff_core::scan_profile::ScanProfile;
+use mac_sniff_core::schedule::{ScheduleCycles, ScheduleInterval};
+use mac_sniff_core::settings::{Settings, MAX_ENCODED_LEN, SCHEMA_VERSION};
+
+const NAMESPACE: &str = "mac_sniff";
+const KEY_SETTINGS: &str = "settings";
+// Schema version 1 kept one u8 id per key
+const LEGACY_KEYS: [&str; 6] = ["scan_profile", "scan_duration", "checkpoint", "sched_interval", "sched_cycles", "sched_profile"];
+
+pub struct SettingsStore {
+    nvs: EspNvs<NvsDefault>,
+}
+
+impl SettingsStore {
+    pub fn open(partition: EspDefaultNvsPartition) -> Result<Self> {
+        let nvs = EspNvs::new(partition, NAMESPACE, true)
+            .map_err(|e| anyhow::anyhow!("Error opening settings namespace: {:?}", e))?;
+        Ok(SettingsStore { nvs })
+    }
+
+    // Loads the stored settings, migrating older schema versions and rewriting them
+    pub fn load(&mut self) -> Settings {
+        let mut buf = [0u8; MAX_ENCODED_LEN];
+        let stored = match self.nvs.get_raw(KEY_SETTINGS, &mut buf) {
+            Ok(stored) => stored.map(Settings::decode),
+            Err(e) => {
+                error!("Error reading settings: {:?}", e);
+                None
+            }
+        };
+
+        let settings = match stored {
+            Some(Ok((settings, version))) if version == SCHEMA_VERSION => return settings,
+            Some(Ok((settings, version))) => {
+                info!("Migrating settings from schema version {}", version);
+                settings
+            },
+            Some(Err(e)) => {
+                error!("Stored settings are unusable, using defaults: {}", e);
+                Settings::default()
+            },
+            None => self.migrate_v1(),
+        };
+        if let Err(e) = self.save(&settings) {
+            error!("{}", e);
+        }
+        settings
+    }
+
+    pub fn save(&mut self, settings: &Settings) -> Result<()> {
+        self.nvs
+            .set_raw(KEY_SETTINGS, &settings.encode())
+            .map_err(|e| anyhow::anyhow!("Error saving settings: {:?}", e))?;
+        info!("Settings saved");
+        Ok(())
+    }
+
+    // Builds settings from the per-key values of schema version 1, then removes those keys
+    fn migrate_v1(&mut self) -> Settings {
+        let mut settings = Settings::default();
+        let legacy = |key: &str| self.nvs.get_u8(key).ok().flatten();
+        if let Some(profile) = legacy(LEGACY_KEYS[0]).and_then(ScanProfile::from_id) {
+            settings.scan_profile = profile;
+        }
+        if let Some(duration) = legacy(LEGACY_KEYS[1]).and_then(ScanDuration::from_id) {
+            settings.scan_duration = duration;
+        }
+        if let Some(window) = legacy(LEGACY_KEYS[2]).and_then(CheckpointWindow::from_id) {
+            settings.checkpoint = window;
+        }
+        if let Some(interval) = legacy(LEGACY_KEYS[3]).and_then(ScheduleInterval::from_id) {
+            settings.schedule.interval = interval;
+        }
+        if let Some(cycles) = legacy(LEGACY_KEYS[4]).and_then(ScheduleCycles::from_id) {
+            settings.schedule.cycles = cycles;
+        }
+        if let Some(profile) = legacy(LEGACY_KEYS[5]).and_then(ScanProfile::from_id) {
+            settings.schedule.profile = profile;
+        }
+
+        for key in LEGACY_KEYS {
+            match self.nvs.remove(key) {
+                Ok(true) => info!("Migrated setting {} to schema version {}", key, SCHEMA_VERSION),
+                Ok(false) => {},
+                Err(e) => error!("Error removing setting {}: {:?}", key, e),
+            }
+        }
+        settings
+    }
+}
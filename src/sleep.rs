@@ -0,0 +1,79 @@
+// Deep sleep, the wake sources armed before entering it, and why the device woke up
+
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::time::Duration;
+
+use esp_idf_hal::delay::FreeRtos;
+use esp_idf_svc::sys::{esp, esp_deep_sleep_start, esp_sleep_enable_ext0_wakeup, esp_sleep_enable_timer_wakeup,
+    esp_sleep_get_wakeup_cause, esp_sleep_source_t, esp_sleep_source_t_ESP_SLEEP_WAKEUP_EXT0,
+    esp_sleep_source_t_ESP_SLEEP_WAKEUP_TIMER, esp_sleep_source_t_ESP_SLEEP_WAKEUP_UNDEFINED, gpio_num_t_GPIO_NUM_0,
+    rtc_gpio_pulldown_dis, rtc_gpio_pullup_en};
+use log::{error, info};
+
+use mac_sniff_core::scan_duration::format_duration;
+
+use crate::button::Button;
+
+// Completed scheduled scans. RTC slow memory survives deep sleep (including a button wakeup to
+// look at the menu), so this only starts over after a power cycle or reset.
+#[link_section = ".rtc.data"]
+static COMPLETED_CYCLES: AtomicU32 = AtomicU32::new(0);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WakeCause {
+    // Power-on or reset, not a wake from deep sleep
+    Reset,
+    Timer,
+    Button,
+    Other(esp_sleep_source_t),
+}
+
+#[allow(non_upper_case_globals)]
+pub fn wake_cause() -> WakeCause {
+    match unsafe { esp_sleep_get_wakeup_cause() } {
+        esp_sleep_source_t_ESP_SLEEP_WAKEUP_UNDEFINED => WakeCause::Reset,
+        esp_sleep_source_t_ESP_SLEEP_WAKEUP_TIMER => WakeCause::Timer,
+        esp_sleep_source_t_ESP_SLEEP_WAKEUP_EXT0 => WakeCause::Button,
+        other => WakeCause::Other(other),
+    }
+}
+
+// Sleeps until the PRG button is pressed or `wakeup` has passed
+pub fn deep_sleep(button: &Button, wakeup: Option<Duration>) -> ! {
+    // A button still held from the menu would wake the device straight away
+    while button.is_pressed() {
+        FreeRtos::delay_ms(50);
+    }
+
+    // GPIO0 is an RTC pin; the button pulls it low, so wake on level 0
+    let armed = esp!(unsafe { rtc_gpio_pullup_en(gpio_num_t_GPIO_NUM_0) })
+        .and_then(|_| esp!(unsafe { rtc_gpio_pulldown_dis(gpio_num_t_GPIO_NUM_0) }))
+        .and_then(|_| esp!(unsafe { esp_sleep_enable_ext0_wakeup(gpio_num_t_GPIO_NUM_0, 0) }));
+    if let Err(e) = armed {
+        error!("Error arming button wakeup: {:?}", e);
+    }
+
+    if let Some(wakeup) = wakeup {
+        match esp!(unsafe { esp_sleep_enable_timer_wakeup(wakeup.as_micros() as u64) }) {
+            Ok(()) => info!("Sleeping, next wakeup in {}", format_duration(wakeup.as_secs())),
+            Err(e) => error!("Error arming wakeup timer: {:?}", e),
+        }
+    } else {
+        info!("Sleeping until the button is pressed");
+    }
+    unsafe { esp_deep_sleep_start() }
+}
+
+pub fn completed_cycles() -> u32 {
+    COMPLETED_CYCLES.load(Ordering::SeqCst)
+}
+
+// Counts a finished scheduled scan and returns the new total
+pub fn complete_cycle() -> u32 {
+    COMPLETED_CYCLES.fetch_add(1, Ordering::SeqCst) + 1
+}
+
+// A reset starts the schedule over
+pub fn reset_cycles() {
+    COMPLETED_CYCLES.store(0, Ordering::SeqCst);
+}
@@ -0,0 +1,159 @@
+// Owner of the promiscuous receive callback
+// Only one Sniffer can exist at a time. Creating it installs the callback; dropping it leaves
+// promiscuous mode, uninstalls the callback, waits for a callback still running on the Wi-Fi
+// task and only then resets the observation ring, so scans can be started and stopped any
+// number of times within a boot.
+
+use std::sync::atomic::{AtomicBool, AtomicU32, AtomicU8, Ordering};
+
+use anyhow::Result;
+use esp_idf_svc::hal::delay::FreeRtos;
+use esp_idf_svc::sys::{esp, esp_wifi_set_promiscuous, esp_wifi_set_promiscuous_rx_cb, wifi_promiscuous_pkt_t,
+    wifi_promiscuous_pkt_type_t, wifi_promiscuous_pkt_type_t_WIFI_PKT_MISC};
+use log::{debug, error, warn};
+
+use mac_sniff_core::frame;
+use mac_sniff_core::hal::PacketSource;
+use mac_sniff_core::observation::{FrameBytes, Observation, ObservationRing, RxMeta};
+use mac_sniff_core::scan_profile::ScanProfile;
+
+use crate::wifi;
+
+// Filled by the callback on the Wi-Fi task, drained through the Sniffer
+static OBSERVATIONS: ObservationRing = ObservationRing::new();
+// Set while a Sniffer exists; guards both the single consumer and the producer
+static SNIFFER_ACTIVE: AtomicBool = AtomicBool::new(false);
+// Id of the running scan profile; its subtype filter is applied before anything is queued
+static ACTIVE_PROFILE: AtomicU8 = AtomicU8::new(0);
+// Cleared first when a Sniffer stops; a callback that runs late sees it and queues nothing
+static RECEIVING: AtomicBool = AtomicBool::new(false);
+// Callbacks currently running on the Wi-Fi task
+static CALLBACKS_RUNNING: AtomicU32 = AtomicU32::new(0);
+// How long Drop waits for a running callback before giving up on the ring reset
+const CALLBACK_DRAIN_MS: u32 = 100;
+
+unsafe extern "C" fn rx_callback(buf: *mut core::ffi::c_void, pkt_type: wifi_promiscuous_pkt_type_t) {
+    CALLBACKS_RUNNING.fetch_add(1, Ordering::SeqCst);
+    if RECEIVING.load(Ordering::SeqCst) {
+        handle_packet(buf, pkt_type);
+    }
+    CALLBACKS_RUNNING.fetch_sub(1, Ordering::SeqCst);
+}
+
+// Runs on the Wi-Fi task for every received frame, so it only parses, filters and queues
+unsafe fn handle_packet(buf: *mut core::ffi::c_void, pkt_type: wifi_promiscuous_pkt_type_t) {
+    if buf.is_null() || pkt_type == wifi_promiscuous_pkt_type_t_WIFI_PKT_MISC {
+        return;
+    }
+
+    // The driver hands us a wifi_promiscuous_pkt_t: rx_ctrl metadata followed by the frame
+    let pkt = &*(buf as *const wifi_promiscuous_pkt_t);
+    let frame_len = frame::frame_len_from_sig_len(pkt.rx_ctrl.sig_len() as usize);
+    let frame_data = std::slice::from_raw_parts(pkt.payload.as_ptr(), frame_len);
+
+    let Ok(header) = frame::parse_header(frame_data) else {
+        return;
+    };
+
+    let profile = ScanProfile::from_id(ACTIVE_PROFILE.load(Ordering::Relaxed)).unwrap_or(ScanProfile::Full);
+    if !profile.subtype_filter().accepts(&header) {
+        return;
+    }
+
+    let rx_ctrl = &pkt.rx_ctrl;
+    let meta = RxMeta {
+        rssi: rx_ctrl.rssi() as i8,
+        noise_floor: rx_ctrl.noise_floor() as i8,
+        channel: rx_ctrl.channel() as u8,
+        secondary_channel: rx_ctrl.secondary_channel() as u8,
+        rate: rx_ctrl.rate() as u8,
+        sig_mode: (rx_ctrl.sig_mode() as u8).into(),
+        mcs: rx_ctrl.mcs() as u8,
+        wide_bandwidth: rx_ctrl.cwb() != 0,
+        sig_len: rx_ctrl.sig_len() as u16,
+        timestamp_us: rx_ctrl.timestamp() as u32,
+        // Only delivered when the profile asks for FCS-failed frames
+        fcs_failed: rx_ctrl.rx_state() != 0,
+    };
+
+    // The Wi-Fi task is the only producer; a full ring drops the frame and counts it
+    OBSERVATIONS.push(Observation { meta, header, frame: FrameBytes::new(frame_data) });
+}
+
+pub struct Sniffer {
+    // Not Send/Sync: the consumer side of the ring stays on the thread that started the scan
+    _not_send: std::marker::PhantomData<*const ()>,
+}
+
+impl Sniffer {
+    pub fn start(profile: ScanProfile) -> Result<Self> {
+        // The flag is claimed before the ring is touched so a second Sniffer cannot race the reset
+        if SNIFFER_ACTIVE.compare_exchange(false, true, Ordering::AcqRel, Ordering::Acquire).is_err() {
+            return Err(anyhow::anyhow!("Sniffer already running"));
+        }
+
+        // The ring was reset when the last Sniffer stopped, once its callback had finished
+        ACTIVE_PROFILE.store(profile.id(), Ordering::Relaxed);
+        if let Err(e) = wifi::set_promiscuous_filter(&profile.frame_classes()) {
+            SNIFFER_ACTIVE.store(false, Ordering::Release);
+            return Err(e);
+        }
+
+        RECEIVING.store(true, Ordering::SeqCst);
+        let installed = esp!(unsafe { esp_wifi_set_promiscuous_rx_cb(Some(rx_callback)) })
+            .and_then(|_| esp!(unsafe { esp_wifi_set_promiscuous(true) }));
+        if let Err(e) = installed {
+            RECEIVING.store(false, Ordering::SeqCst);
+            SNIFFER_ACTIVE.store(false, Ordering::Release);
+            return Err(anyhow::anyhow!("Error starting promiscuous mode: {:?}", e));
+        }
+
+        debug!("Sniffer started with profile {}", profile.label());
+        Ok(Sniffer { _not_send: std::marker::PhantomData })
+    }
+}
+
+impl PacketSource for Sniffer {
+    fn next_observation(&mut self) -> Option<Observation> {
+        // Only the single live Sniffer pops, and it cannot leave this thread
+        unsafe { OBSERVATIONS.pop() }
+    }
+
+    fn set_channel(&mut self, channel: u8) -> Result<()> {
+        wifi::set_channel(channel)
+    }
+
+    fn dropped(&self) -> u32 {
+        OBSERVATIONS.dropped()
+    }
+}
+
+impl Drop for Sniffer {
+    fn drop(&mut self) {
+        // Stop the radio delivering frames before the callback goes away
+        if let Err(e) = esp!(unsafe { esp_wifi_set_promiscuous(false) }) {
+            error!("Error leaving promiscuous mode: {:?}", e);
+        }
+        RECEIVING.store(false, Ordering::SeqCst);
+        if let Err(e) = esp!(unsafe { esp_wifi_set_promiscuous_rx_cb(None) }) {
+            warn!("Error removing the promiscuous callback: {:?}", e);
+        }
+
+        // A callback that started before RECEIVING was cleared may still be pushing
+        let mut waited_ms = 0;
+        while CALLBACKS_RUNNING.load(Ordering::SeqCst) != 0 && waited_ms < CALLBACK_DRAIN_MS {
+            FreeRtos::delay_ms(1);
+            waited_ms += 1;
+        }
+        if CALLBACKS_RUNNING.load(Ordering::SeqCst) == 0 {
+            // Nothing is producing and this is the only consumer
+            unsafe {
+                OBSERVATIONS.reset();
+            }
+        } else {
+            warn!("Promiscuous callback still running after {} ms, observation ring not reset", CALLBACK_DRAIN_MS);
+        }
+        SNIFFER_ACTIVE.store(false, Ordering::Release);
+        debug!("Sniffer stopped");
+    }
+}
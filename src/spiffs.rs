@@ -1,13 +1,76 @@
 use std::ptr::null;
-use std::ffi::{CStr, CString};
+use std::ffi::CString;
 use log::{info, error};
-use std::fs::{self, File};
+use std::fs::{self, File, OpenOptions};
 use std::io::{Write, Read};
-use std::path::Path;
 
-use esp_idf_hal::sys::{esp_vfs_spiffs_conf_t, esp_vfs_spiffs_register, esp_vfs_spiffs_unregister, ESP_OK, esp_spiffs_info};
+use esp_idf_hal::sys::{esp_vfs_spiffs_conf_t, esp_vfs_spiffs_register, esp_vfs_spiffs_unregister, ESP_OK, esp_spiffs_info, esp_spiffs_format};
 
-pub fn mount(path: &str) -> anyhow::Result<()> {
+use mac_sniff_core::hal::Storage;
+
+// Where the partition is mounted
+pub const BASE_PATH: &str = "/spffs";
+
+// The SPIFFS partition, mounted for as long as this exists
+pub struct Spiffs {
+    base_path: &'static str,
+}
+
+impl Spiffs {
+    pub fn mount(base_path: &'static str) -> anyhow::Result<Self> {
+        mount(base_path)?;
+        Ok(Spiffs { base_path })
+    }
+
+    pub fn path(&self, name: &str) -> String {
+        format!("{}/{}", self.base_path, name)
+    }
+}
+
+impl Drop for Spiffs {
+    fn drop(&mut self) {
+        // unmount logs its own errors
+        let _ = unmount();
+    }
+}
+
+impl Storage for Spiffs {
+    fn list(&self) -> anyhow::Result<Vec<String>> {
+        list_files(self.base_path)
+    }
+
+    fn size(&self, name: &str) -> anyhow::Result<usize> {
+        Ok(fs::metadata(self.path(name))?.len() as usize)
+    }
+
+    fn read(&self, name: &str) -> anyhow::Result<Vec<u8>> {
+        read_file(&self.path(name))
+    }
+
+    fn write(&mut self, name: &str, data: &[u8]) -> anyhow::Result<()> {
+        save_to_file(&self.path(name), data)
+    }
+
+    fn append(&mut self, name: &str, data: &[u8]) -> anyhow::Result<()> {
+        let mut file = OpenOptions::new().append(true).open(self.path(name))?;
+        file.write_all(data)?;
+        Ok(())
+    }
+
+    fn delete(&mut self, name: &str) -> anyhow::Result<()> {
+        delete_file(&self.path(name))
+    }
+
+    fn space(&self) -> anyhow::Result<(usize, usize)> {
+        get_space_info()
+    }
+
+    fn has_enough_space(&self, needed_bytes: usize) -> anyhow::Result<bool> {
+        has_enough_space(needed_bytes)
+    }
+}
+
+fn mount(path: &str) -> anyhow::Result<()> {
     let base_path = CString::new(path).unwrap();
     let spiffs_config = esp_vfs_spiffs_conf_t {
         base_path: base_path.as_ptr(),
@@ -29,7 +92,7 @@ pub fn mount(path: &str) -> anyhow::Result<()> {
     Ok(())
 }
 
-pub fn unmount() -> anyhow::Result<()> {
+fn unmount() -> anyhow::Result<()> {
     unsafe {
         let result = esp_vfs_spiffs_unregister(null());
         if result != ESP_OK {
@@ -43,7 +106,7 @@ pub fn unmount() -> anyhow::Result<()> {
     Ok(())
 }
 
-pub fn get_space_info() -> anyhow::Result<(usize, usize)> {
+fn get_space_info() -> anyhow::Result<(usize, usize)> {
     let mut total_bytes: usize = 0;
     let mut used_bytes: usize = 0;
     
@@ -59,21 +122,22 @@ pub fn get_space_info() -> anyhow::Result<(usize, usize)> {
     Ok((total_bytes, used_bytes))
 }
 
-pub fn save_to_file(path: &str, data: &[u8]) -> anyhow::Result<()> {
+fn save_to_file(path: &str, data: &[u8]) -> anyhow::Result<()> {
     let mut file = File::create(path)?;
     file.write_all(data)?;
     info!("Successfully wrote {} bytes to {}", data.len(), path);
     Ok(())
 }
 
-pub fn has_enough_space(needed_bytes: usize) -> anyhow::Result<bool> {
+fn has_enough_space(needed_bytes: usize) -> anyhow::Result<bool> {
     let (total, used) = get_space_info()?;
     let available = total - used;
     info!("SPIFFS space check - available: {} bytes, needed: {} bytes", available, needed_bytes);
     Ok(available >= needed_bytes)
 }
 
-pub fn list_files(dir_path: &str) -> anyhow::Result<Vec<String>> {
+// Names of the files in `dir_path`; SPIFFS has no subdirectories
+fn list_files(dir_path: &str) -> anyhow::Result<Vec<String>> {
     let paths = fs::read_dir(dir_path)?;
     
     let mut files = Vec::new();
@@ -82,8 +146,8 @@ pub fn list_files(dir_path: &str) -> anyhow::Result<Vec<String>> {
         let path = entry.path();
         
         if path.is_file() {
-            if let Some(path_str) = path.to_str() {
-                files.push(path_str.to_string());
+            if let Some(name) = path.file_name().and_then(|name| name.to_str()) {
+                files.push(name.to_string());
             }
         }
     }
@@ -91,9 +155,29 @@ pub fn list_files(dir_path: &str) -> anyhow::Result<Vec<String>> {
     Ok(files)
 }
 
-pub fn read_file(file_path: &str) -> anyhow::Result<Vec<u8>> {
+fn read_file(file_path: &str) -> anyhow::Result<Vec<u8>> {
     let mut file = File::open(file_path)?;
     let mut buffer = Vec::new();
     file.read_to_end(&mut buffer)?;
     Ok(buffer)
 }
+
+fn delete_file(file_path: &str) -> anyhow::Result<()> {
+    fs::remove_file(file_path)?;
+    info!("Deleted {}", file_path);
+    Ok(())
+}
+
+// Erases the whole partition; works whether or not it is mounted
+pub fn format() -> anyhow::Result<()> {
+    unsafe {
+        let result = esp_spiffs_format(null());
+        if result != ESP_OK {
+            error!("Failed to format SPIFFS. Error code: {}", result);
+            return Err(anyhow::anyhow!("SPIFFS format failed with error code: {}", result));
+        }
+    }
+
+    info!("SPIFFS formatted");
+    Ok(())
+}
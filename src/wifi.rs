@@ -3,11 +3,24 @@ use esp_idf_svc::{
     eventloop::EspSystemEventLoop, 
     nvs::EspDefaultNvsPartition, 
     wifi::{self, ClientConfiguration, WifiDriver},
-    sys::{esp_wifi_set_mode, esp_wifi_set_promiscuous_filter, wifi_promiscuous_filter_t, WIFI_PROMIS_FILTER_MASK_ALL},
+    sys::{esp, esp_efuse_mac_get_default, esp_wifi_set_channel, esp_wifi_set_mode, esp_wifi_set_promiscuous_ctrl_filter,
+        esp_wifi_set_promiscuous_filter, wifi_promiscuous_filter_t, wifi_second_chan_t_WIFI_SECOND_CHAN_NONE,
+        WIFI_PROMIS_CTRL_FILTER_MASK_ALL, WIFI_PROMIS_FILTER_MASK_CTRL, WIFI_PROMIS_FILTER_MASK_DATA,
+        WIFI_PROMIS_FILTER_MASK_FCSFAIL, WIFI_PROMIS_FILTER_MASK_MGMT},
 };
 use anyhow::Result;
 use log::debug;
 
+use mac_sniff_core::scan_profile::{self, FrameClasses};
+
+// The core crate keeps its own copy of the filter bits so the profile mapping is tested on the host
+const _: () = assert!(
+    scan_profile::FILTER_MASK_MGMT == WIFI_PROMIS_FILTER_MASK_MGMT
+        && scan_profile::FILTER_MASK_CTRL == WIFI_PROMIS_FILTER_MASK_CTRL
+        && scan_profile::FILTER_MASK_DATA == WIFI_PROMIS_FILTER_MASK_DATA
+        && scan_profile::FILTER_MASK_FCSFAIL == WIFI_PROMIS_FILTER_MASK_FCSFAIL
+        && scan_profile::CTRL_FILTER_MASK_ALL == WIFI_PROMIS_CTRL_FILTER_MASK_ALL
+);
 
 pub fn create_wifi_driver(modem: Modem, sys_loop: EspSystemEventLoop, nvs: EspDefaultNvsPartition) -> Result<WifiDriver<'static>> {
     let basic_client_config = ClientConfiguration {
@@ -26,16 +39,38 @@ pub fn create_wifi_driver(modem: Modem, sys_loop: EspSystemEventLoop, nvs: EspDe
     };
 
     wifi_driver.start()?;
-    wifi_driver.set_promiscuous(true)?;
-
-    unsafe {
-        let filter = wifi_promiscuous_filter_t {
-            filter_mask: WIFI_PROMIS_FILTER_MASK_ALL,
-        };
-        esp_wifi_set_promiscuous_filter(&filter);
-    }
 
     debug!("Wifi Started");
 
     Ok(wifi_driver)
-}
\ No newline at end of file
+}
+
+// Select which frame classes the driver hands to the promiscuous callback
+pub fn set_promiscuous_filter(classes: &FrameClasses) -> Result<()> {
+    let filter_mask = classes.filter_mask();
+    esp!(unsafe { esp_wifi_set_promiscuous_filter(&wifi_promiscuous_filter_t { filter_mask }) })
+        .map_err(|e| anyhow::anyhow!("Error setting promiscuous filter: {:?}", e))?;
+    if let Some(filter_mask) = classes.ctrl_filter_mask() {
+        esp!(unsafe { esp_wifi_set_promiscuous_ctrl_filter(&wifi_promiscuous_filter_t { filter_mask }) })
+            .map_err(|e| anyhow::anyhow!("Error setting control frame filter: {:?}", e))?;
+    }
+    debug!("Promiscuous filter mask {:#x}", filter_mask);
+    Ok(())
+}
+
+// Retune the radio while in promiscuous mode; used by the channel hopper
+pub fn set_channel(channel: u8) -> Result<()> {
+    esp!(unsafe { esp_wifi_set_channel(channel, wifi_second_chan_t_WIFI_SECOND_CHAN_NONE) })
+        .map_err(|e| anyhow::anyhow!("Error setting channel {}: {:?}", channel, e))?;
+    debug!("Hopped to channel {}", channel);
+    Ok(())
+}
+
+// Factory programmed base MAC, used to tell devices apart in saved scans
+pub fn device_id() -> [u8; 6] {
+    let mut mac = [0u8; 6];
+    unsafe {
+        esp_efuse_mac_get_default(mac.as_mut_ptr());
+    }
+    mac
+}
@@ -1,31 +1,215 @@
 import os
 import sys
 import argparse
+import struct
 from datetime import datetime
 
+import oui
+
+# Per-MAC stats record written by the firmware (see src/stats.rs)
+# mac, first_seen_ms, last_seen_ms, frame_count, tx_count, rssi_min, rssi_max, rssi_mean,
+# flags (bit 0 transmitter, bit 1 locally administered), channel bitmask,
+# then 4 x (type << 4 | subtype, count)
+RECORD_FORMAT = "<6sIIIIbbbBH" + "BH" * 4
+RECORD_LEN = struct.calcsize(RECORD_FORMAT)
+FRAME_TYPES = {0: "mgmt", 1: "ctrl", 2: "data", 3: "ext"}
+
+# Versioned scan file (see src/scan_file.rs)
+SCAN_FILE_MAGIC = b"MSNF"
+SCAN_FILE_VERSION = 1
+HEADER_FIXED_FORMAT = "<4sHHQI6sI16s"
+SECTION_FORMAT = "<BBHI"
+RECORD_TYPE_LEGACY_MAC = 0
+RECORD_TYPE_MAC_STATS = 1
+RECORD_TYPE_ACCESS_POINT = 2
+RECORD_TYPE_ASSOCIATION = 3
+RECORD_TYPE_PROBED_SSID = 4
+
+# Access point record (see src/ap.rs)
+# bssid, first_seen_ms, last_seen_ms, beacons, probe_responses, rssi_max, channel,
+# beacon_interval, capability, security, flags (bit 0 hidden, 1 HT, 2 VHT, 3 HE),
+# country, ssid_len, ssid, manufacturer
+AP_RECORD_FORMAT = "<6sIIIIbBHHBB2sB32s16s"
+# Association record (see src/association.rs)
+# station, bssid, first_seen_ms, last_seen_ms, uplink_frames, downlink_frames, station_rssi_max
+ASSOCIATION_RECORD_FORMAT = "<6s6sIIIIb"
+# Probed SSID record (see src/probe_ssid.rs)
+# mac, count, flags (bit 0 hashed), ssid_len, ssid (or 8-byte little endian salted hash)
+PROBED_SSID_RECORD_FORMAT = "<6sIBB32s"
+SECURITY = {0: "open", 1: "wep", 2: "wpa", 3: "wpa2", 4: "wpa3", 5: "wpa2/wpa3", 6: "owe"}
+
 def mac_to_string(mac_bytes):
     """Convert a 6-byte MAC address to a human-readable string."""
     return ":".join([f"{b:02x}" for b in mac_bytes])
 
+def channels_to_string(mask):
+    """Convert the channel bitmask to a list like 1/6/11."""
+    return "/".join(str(ch) for ch in range(16) if mask & (1 << ch))
+
+def subtypes_to_string(fields):
+    """Convert the saved (type << 4 | subtype, count) pairs to mgmt.4=12 style entries."""
+    entries = []
+    for i in range(0, len(fields), 2):
+        key, count = fields[i], fields[i + 1]
+        if key == 0xFF:
+            continue
+        entries.append(f"{FRAME_TYPES[key >> 4]}.{key & 0x0F}={count}")
+    return " ".join(entries)
+
+def standards_to_string(flags):
+    """Convert the AP flag bits to a list like HT/VHT."""
+    names = [name for bit, name in ((1, "HT"), (2, "VHT"), (3, "HE")) if flags & (1 << bit)]
+    return "/".join(names)
+
+def write_access_points(out, record_len, records):
+    """Write one line per saved access point."""
+    out.write(f"\n# Access points: {len(records) // record_len}\n")
+    out.write("# bssid,ssid,hidden,channel,security,standards,country,beacon_interval,rssi_max,beacons,probe_responses,first_seen_ms,last_seen_ms,manufacturer\n")
+    for i in range(0, len(records), record_len):
+        (bssid, first, last, beacons, probe_responses, rssi_max, channel, interval, _capability,
+         security, flags, country, ssid_len, ssid, manufacturer) = struct.unpack_from(AP_RECORD_FORMAT, records, i)
+        ssid = ssid[:ssid_len].decode("utf-8", errors="replace")
+        country = country.rstrip(b"\0").decode("ascii", errors="replace")
+        manufacturer = manufacturer.rstrip(b"\0").decode("utf-8", errors="replace")
+        out.write(
+            f"{mac_to_string(bssid)},{ssid},{flags & 1},{channel},{SECURITY.get(security, security)},"
+            f"{standards_to_string(flags)},{country},{interval},{rssi_max},{beacons},{probe_responses},"
+            f"{first},{last},{manufacturer}\n"
+        )
+
+def write_associations(out, record_len, records, ssids):
+    """Write one line per station/BSSID pair, grouped by network."""
+    rows = [struct.unpack_from(ASSOCIATION_RECORD_FORMAT, records, i) for i in range(0, len(records), record_len)]
+    rows.sort(key=lambda row: (row[1], row[0]))
+    out.write(f"\n# Station associations: {len(rows)}\n")
+    out.write("# bssid,ssid,station,uplink_frames,downlink_frames,station_rssi_max,first_seen_ms,last_seen_ms\n")
+    for station, bssid, first, last, uplink, downlink, rssi_max in rows:
+        out.write(
+            f"{mac_to_string(bssid)},{ssids.get(bssid, '')},{mac_to_string(station)},"
+            f"{uplink},{downlink},{rssi_max if uplink else ''},{first},{last}\n"
+        )
+
+def write_probed_ssids(out, record_len, records):
+    """Write each MAC's preferred network list, most probed first."""
+    by_mac = {}
+    for i in range(0, len(records), record_len):
+        mac, count, flags, ssid_len, ssid = struct.unpack_from(PROBED_SSID_RECORD_FORMAT, records, i)
+        if flags & 1:
+            name = "#" + struct.unpack_from("<Q", ssid)[0].to_bytes(8, "big").hex()
+        else:
+            name = ssid[:ssid_len].decode("utf-8", errors="replace")
+        by_mac.setdefault(mac, []).append((count, name))
+    out.write(f"\n# Probed SSIDs: {len(by_mac)} devices (hashed SSIDs start with #)\n")
+    out.write("# mac,ssid=count ...\n")
+    for mac in sorted(by_mac):
+        entries = sorted(by_mac[mac], key=lambda entry: (-entry[0], entry[1]))
+        out.write(f"{mac_to_string(mac)}," + " ".join(f"{name}={count}" for count, name in entries) + "\n")
+
+def access_point_ssids(record_len, records):
+    """Map BSSID bytes to SSID from the access point section."""
+    ssids = {}
+    for i in range(0, len(records), record_len):
+        fields = struct.unpack_from(AP_RECORD_FORMAT, records, i)
+        ssids[fields[0]] = fields[13][:fields[12]].decode("utf-8", errors="replace")
+    return ssids
+
+def parse_scan_file(data):
+    """Split a scan file into (header dict or None, {record_type: (record_len, bytes)})."""
+    if data[:4] != SCAN_FILE_MAGIC:
+        # Headerless files from older firmware are plain 6-byte MACs
+        if len(data) % 6 != 0:
+            raise ValueError(f"headerless file of {len(data)} bytes is not a list of 6-byte MACs")
+        return None, {RECORD_TYPE_LEGACY_MAC: (6, data)}
+
+    magic, version, header_len, start_time, duration_ms, device_id, boot_count, firmware = \
+        struct.unpack_from(HEADER_FIXED_FORMAT, data, 0)
+    if version == 0 or version > SCAN_FILE_VERSION:
+        raise ValueError(f"unsupported scan file version {version}")
+
+    offset = struct.calcsize(HEADER_FIXED_FORMAT)
+    hop_count = data[offset]
+    offset += 1
+    channel_plan = []
+    for _ in range(hop_count):
+        channel, dwell_ms = struct.unpack_from("<BI", data, offset)
+        channel_plan.append((channel, dwell_ms))
+        offset += 5
+    (section_count,) = struct.unpack_from("<H", data, offset)
+
+    header = {
+        "version": version,
+        "start_time": start_time,
+        "duration_ms": duration_ms,
+        "device_id": mac_to_string(device_id),
+        "boot_count": boot_count,
+        "firmware": firmware.rstrip(b"\0").decode("utf-8", errors="replace"),
+        "channel_plan": channel_plan,
+    }
+
+    sections = {}
+    offset = header_len
+    for _ in range(section_count):
+        record_type, _, record_len, record_count = struct.unpack_from(SECTION_FORMAT, data, offset)
+        offset += struct.calcsize(SECTION_FORMAT)
+        end = offset + record_len * record_count
+        if end > len(data):
+            raise ValueError(f"section {record_type} truncated")
+        sections[record_type] = (record_len, data[offset:end])
+        offset = end
+    return header, sections
+
 def process_binary_file(input_file, output_file):
-    """Process a binary file containing 6-byte MAC addresses and write them to a text file."""
+    """Process a scan file (versioned or legacy headerless) and write it to a text file."""
     try:
         with open(input_file, 'rb') as f:
             data = f.read()
         
-        # Each MAC address is 6 bytes
-        mac_count = len(data) // 6
+        header, sections = parse_scan_file(data)
         
         with open(output_file, 'w') as out:
             out.write(f"# MAC addresses extracted from {os.path.basename(input_file)}\n")
             out.write(f"# Extracted on {datetime.now().strftime('%Y-%m-%d %H:%M:%S')}\n")
-            out.write(f"# Total MAC addresses: {mac_count}\n\n")
-            
-            for i in range(0, len(data), 6):
-                if i + 6 <= len(data):  # Ensure we have a complete MAC address
-                    mac_bytes = data[i:i+6]
-                    mac_str = mac_to_string(mac_bytes)
-                    out.write(f"{mac_str}\n")
+            if header:
+                plan = " ".join(f"{ch}:{dwell}ms" for ch, dwell in header["channel_plan"])
+                out.write(f"# Format version: {header['version']}, firmware: {header['firmware']}\n")
+                out.write(f"# Device: {header['device_id']}, boot: {header['boot_count']}\n")
+                out.write(f"# Start time: {header['start_time']}, duration: {header['duration_ms']} ms\n")
+                out.write(f"# Channel plan: {plan}\n")
+            else:
+                out.write("# Format: legacy headerless MAC list\n")
+
+            mac_count = 0
+            if RECORD_TYPE_MAC_STATS in sections:
+                record_len, records = sections[RECORD_TYPE_MAC_STATS]
+                mac_count = len(records) // record_len
+                vendors = oui.vendor_counts(records[i:i+6] for i in range(0, len(records), record_len))
+                out.write(f"# Total MAC addresses: {mac_count}\n")
+                out.write("# Vendors: " + ", ".join(f"{vendor} {count}" for vendor, count in vendors) + "\n")
+                out.write("# mac,first_seen_ms,last_seen_ms,frames,tx_frames,rssi_min,rssi_max,rssi_mean,transmitter,randomized,channels,subtypes,vendor\n\n")
+                for i in range(0, len(records), record_len):
+                    # Newer firmware may append fields; only the known prefix is decoded
+                    fields = struct.unpack_from(RECORD_FORMAT, records, i)
+                    mac, first, last, frames, tx_frames, rssi_min, rssi_max, rssi_mean, flags, channels = fields[:10]
+                    out.write(
+                        f"{mac_to_string(mac)},{first},{last},{frames},{tx_frames},"
+                        f"{rssi_min},{rssi_max},{rssi_mean},{flags & 1},{(flags >> 1) & 1},"
+                        f"{channels_to_string(channels)},{subtypes_to_string(fields[10:])},{oui.lookup(mac) or ''}\n"
+                    )
+            elif RECORD_TYPE_LEGACY_MAC in sections:
+                _, records = sections[RECORD_TYPE_LEGACY_MAC]
+                mac_count = len(records) // 6
+                out.write(f"# Total MAC addresses: {mac_count}\n\n")
+                for i in range(0, len(records), 6):
+                    out.write(f"{mac_to_string(records[i:i+6])}\n")
+
+            ssids = {}
+            if RECORD_TYPE_ACCESS_POINT in sections:
+                write_access_points(out, *sections[RECORD_TYPE_ACCESS_POINT])
+                ssids = access_point_ssids(*sections[RECORD_TYPE_ACCESS_POINT])
+            if RECORD_TYPE_ASSOCIATION in sections:
+                write_associations(out, *sections[RECORD_TYPE_ASSOCIATION], ssids)
+            if RECORD_TYPE_PROBED_SSID in sections:
+                write_probed_ssids(out, *sections[RECORD_TYPE_PROBED_SSID])
         
         print(f"Processed {mac_count} MAC addresses from {input_file}")
         return mac_count
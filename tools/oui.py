@@ -0,0 +1,68 @@
+import bisect
+import csv
+from pathlib import Path
+
+# Vendor lookup from the IEEE registries in core/data/oui, mirroring core/src/oui.rs and the
+# name shortening done by core/build.rs so host output matches what the device shows.
+OUI_DIR = Path(__file__).resolve().parent.parent / "core" / "data" / "oui"
+# (registry file, prefix bits)
+REGISTRIES = [("oui36.csv", 36), ("mam.csv", 28), ("oui.csv", 24)]
+
+VENDOR_NAME_MAX_LEN = 16
+NAME_SUFFIXES = {
+    "inc", "incorporated", "ltd", "limited", "co", "coltd", "corp", "corporation", "corporate", "company",
+    "llc", "gmbh", "ag", "sa", "sas", "bv", "nv", "oy", "ab", "as", "asa", "kg", "pte", "pty", "plc",
+    "srl", "spa", "sro", "kk", "electronics", "electronic", "technology", "technologies", "tech",
+    "systems", "communications", "communication", "international", "group", "holdings", "industries",
+    "industrial",
+}
+
+_tables = None
+
+def short_vendor_name(name):
+    """Shorten a registry organization name the same way core/build.rs does ("Apple, Inc." -> "Apple")."""
+    words = name.split(",")[0].split()
+    while len(words) > 1:
+        last = "".join(c for c in words[-1] if c.isalnum()).lower()
+        if last not in NAME_SUFFIXES:
+            break
+        words.pop()
+    return " ".join(words)[:VENDOR_NAME_MAX_LEN].rstrip(" .-&")
+
+def _load_tables():
+    """Sorted (prefixes, names) per registry, most specific first."""
+    global _tables
+    if _tables is None:
+        _tables = []
+        for filename, bits in REGISTRIES:
+            entries = {}
+            with open(OUI_DIR / filename, newline="", encoding="utf-8") as f:
+                for row in csv.DictReader(f):
+                    try:
+                        entries[int(row["Assignment"], 16)] = short_vendor_name(row["Organization Name"])
+                    except ValueError:
+                        continue
+            prefixes = sorted(entries)
+            _tables.append((bits, prefixes, [entries[p] for p in prefixes]))
+    return _tables
+
+def lookup(mac_bytes):
+    """Short vendor name for a 6-byte MAC, or None for unknown and locally administered addresses."""
+    if mac_bytes[0] & 0x02:
+        return None
+    address = int.from_bytes(bytes([mac_bytes[0] & ~0x01]) + bytes(mac_bytes[1:6]), "big")
+    for bits, prefixes, names in _load_tables():
+        prefix = address >> (48 - bits)
+        idx = bisect.bisect_left(prefixes, prefix)
+        if idx < len(prefixes) and prefixes[idx] == prefix:
+            return names[idx]
+    return None
+
+def vendor_counts(macs):
+    """[(vendor, count)] for the known vendors among `macs`, most common first."""
+    counts = {}
+    for mac in macs:
+        vendor = lookup(mac)
+        if vendor:
+            counts[vendor] = counts.get(vendor, 0) + 1
+    return sorted(counts.items(), key=lambda item: (-item[1], item[0]))
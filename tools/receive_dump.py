@@ -1,9 +1,119 @@
 from serial import Serial
 import argparse
-import os
-import time
+import base64
+import binascii
+import zlib
 from pathlib import Path
 
+# Must match PROTOCOL_VERSION in core/src/dump_protocol.rs
+PROTOCOL_VERSION = 2
+
+def crc32(data):
+    return zlib.crc32(data) & 0xffffffff
+
+def check_crc(line):
+    """Body of a line ending in ':<crc32>', or None if the CRC does not match."""
+    body, sep, crc = line.rpartition(":")
+    if not sep:
+        return None
+    try:
+        expected = int(crc, 16)
+    except ValueError:
+        return None
+    return body if crc32(body.encode()) == expected else None
+
+def decode(chunk_format, data):
+    try:
+        if chunk_format == "base64":
+            return base64.b64decode(data, validate=True)
+        return bytes.fromhex(data)
+    except (binascii.Error, ValueError):
+        return None
+
+def file_name(path):
+    """Plain file name for a device path, or None if it could leave the output directory.
+    Mirrors file_name in core/src/dump_protocol.rs."""
+    name = path.rsplit("/", 1)[-1]
+    if name in ("", ".", "..") or "\\" in name or ":" in name:
+        return None
+    return name
+
+class IncomingFile:
+    def __init__(self, path, name, size, crc, chunk_format, output_dir):
+        self.path = path
+        self.size = size
+        self.crc = crc
+        self.format = chunk_format
+        self.target = output_dir / name
+        # Data received so far is kept next to the target so an interrupted dump can resume
+        self.part = self.target.with_name(self.target.name + ".part")
+        self.data = bytearray()
+
+    def load_partial(self):
+        if self.part.exists():
+            self.data = bytearray(self.part.read_bytes())
+        if len(self.data) > self.size:
+            self.data = bytearray()
+
+    def append(self, data):
+        self.data.extend(data)
+        with open(self.part, 'ab') as f:
+            f.write(data)
+
+    def finish(self):
+        if len(self.data) != self.size or crc32(bytes(self.data)) != self.crc:
+            # Start over on the next attempt
+            self.part.unlink(missing_ok=True)
+            return False
+        self.target.write_bytes(self.data)
+        self.part.unlink(missing_ok=True)
+        return True
+
+def handle_begin(header, current, output_dir):
+    body = check_crc(header)
+    if body is None:
+        return current, None
+    fields = body.split(":", 3)
+    if len(fields) != 4 or fields[2] not in ("hex", "base64"):
+        return current, None
+    size, crc, chunk_format, path = int(fields[0]), int(fields[1], 16), fields[2], fields[3]
+    # Never answered, so the device gives up on the file
+    name = file_name(path)
+    if name is None:
+        print(f"Refusing file name {path!r}")
+        return current, None
+    # A repeated header keeps what already arrived for the file
+    if current is None or current.path != path:
+        current = IncomingFile(path, name, size, crc, chunk_format, output_dir)
+        current.load_partial()
+        print(f"Receiving: {path} ({size} bytes)")
+    if len(current.data) > 0:
+        print(f"Resuming at byte {len(current.data)}")
+    return current, f"RESUME {len(current.data)}"
+
+def handle_chunk(chunk, current):
+    seq = chunk.split(":", 1)[0]
+    if not seq.isdigit():
+        return None
+    nak = f"NAK {seq}"
+    body = check_crc(chunk)
+    if body is None or current is None:
+        return nak
+    fields = body.split(":", 2)
+    if len(fields) != 3 or not fields[1].isdigit():
+        return nak
+    offset = int(fields[1])
+    data = decode(current.format, fields[2])
+    if data is None:
+        return nak
+    # A chunk sent again because its ACK was lost is acknowledged without appending it twice
+    if offset + len(data) <= len(current.data):
+        return f"ACK {seq}"
+    if offset != len(current.data):
+        return nak
+    current.append(data)
+    return f"ACK {seq}"
+
 def main():
     parser = argparse.ArgumentParser(description='Receive SPIFFS dump from ESP32')
     parser.add_argument('port', help='Serial port (e.g., /dev/ttyUSB0 or COM3)')
@@ -21,18 +131,24 @@ def main():
     print("Waiting for data... (press Ctrl+C to abort)")
     
     with Serial(args.port, args.baud, timeout=1) as ser:
+        def reply(line):
+            ser.write((line + "\n").encode())
+
         receiving = False
-        current_file = None
-        file_path = None
-        file_size = 0
-        current_data = bytearray()
+        current = None
+        saved = 0
+        failed = 0
         
         while True:
             line = ser.readline().decode('utf-8', errors='ignore').strip()
             if not line:
                 continue
                 
-            if line == "MAC_SNIFF_DUMP_BEGIN":
+            if line.startswith("MAC_SNIFF_DUMP_BEGIN"):
+                version = line.partition(":")[2]
+                if version != str(PROTOCOL_VERSION):
+                    print(f"Unsupported dump protocol '{version or 1}', expected {PROTOCOL_VERSION}")
+                    return
                 print("Transfer started")
                 receiving = True
                 continue
@@ -41,11 +157,11 @@ def main():
                 continue
                 
             if line == "MAC_SNIFF_DUMP_END":
-                print("Transfer completed")
-                total_bytes = 0
-                if line.startswith("TOTAL_BYTES:"):
-                    total_bytes = int(line.split(":")[1])
-                print(f"Received {total_bytes} bytes total")
+                print(f"Transfer completed: {saved} files saved, {failed} failed")
+                continue
+
+            if line.startswith("TOTAL_BYTES:"):
+                print(f"Device sent {int(line.split(':')[1])} bytes total")
                 break
                 
             if line.startswith("NUM_FILES:"):
@@ -54,35 +170,27 @@ def main():
                 continue
                 
             if line.startswith("FILE_BEGIN:"):
-                file_path = line.split(":", 1)[1]
-                print(f"Receiving: {file_path}")
-                # Extract just the filename part
-                base_filename = os.path.basename(file_path)
-                current_file = output_dir / base_filename
-                current_data = bytearray()
-                continue
-                
-            if line.startswith("FILE_SIZE:"):
-                file_size = int(line.split(":")[1])
-                print(f"File size: {file_size} bytes")
+                current, response = handle_begin(line[len("FILE_BEGIN:"):], current, output_dir)
+                if response:
+                    reply(response)
                 continue
                 
             if line.startswith("CHUNK:"):
-                hex_data = line[6:]  # Remove "CHUNK:" prefix
-                # Convert hex string back to binary
-                for i in range(0, len(hex_data), 2):
-                    if i+1 < len(hex_data):
-                        byte = int(hex_data[i:i+2], 16)
-                        current_data.append(byte)
+                response = handle_chunk(line[len("CHUNK:"):], current)
+                if response:
+                    reply(response)
                 continue
                 
-            if line == "FILE_END" and current_file and current_data:
-                # Save the file
-                with open(current_file, 'wb') as f:
-                    f.write(current_data)
-                print(f"Saved: {current_file} ({len(current_data)} bytes)")
-                current_file = None
-                current_data = bytearray()
+            if line == "FILE_END" and current:
+                if current.finish():
+                    print(f"Saved: {current.target} ({len(current.data)} bytes)")
+                    saved += 1
+                    reply("DONE")
+                else:
+                    print(f"CRC mismatch for {current.path}, requesting it again")
+                    failed += 1
+                    reply("FAIL")
+                current = None
                 continue
 
 if __name__ == "__main__":